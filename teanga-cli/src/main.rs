@@ -5,6 +5,7 @@ use std::io::BufReader;
 use std::thread;
 use teanga::DiskCorpus;
 use teanga::CuacConfig;
+use teanga::{Corpus, ReadableCorpus, SimpleCorpus, WriteableCorpus};
 use teanga::read_json;
 use teanga::read_jsonl;
 use teanga::read_yaml;
@@ -25,6 +26,8 @@ struct Args {
 enum SubCommand {
     Load(LoadCommand),
     Convert(ConvertCommand),
+    Validate(ValidateCommand),
+    Stats(StatsCommand),
 }
 
 /// Command to load a file into the corpus
@@ -53,6 +56,8 @@ enum Format {
     JSONL,
     YAML,
     Cuac,
+    Conllu,
+    Zstd,
     Guess
 }
 
@@ -77,6 +82,10 @@ impl Format {
                     Format::YAML
                 } else if file.ends_with(".cuac") || file.ends_with(".cuac.gz")  || file.ends_with(".tcf") || file.ends_with(".tcf.gz") {
                     Format::Cuac
+                } else if file.ends_with(".conllu") || file.ends_with(".conllu.gz") {
+                    Format::Conllu
+                } else if file.ends_with(".zst") {
+                    Format::Zstd
                 } else {
                     Format::YAML
                 }
@@ -126,6 +135,99 @@ struct ConvertCommand {
     ignore_id_errors: bool
 }
 
+/// Command to check a corpus against its meta information
+#[derive(Parser, Debug)]
+#[command(name = "validate", about = "Check that every document matches the layer meta information")]
+struct ValidateCommand {
+    /// The file to validate
+    input: String,
+
+    /// The format of the input file
+    #[arg(short,long)]
+    #[clap(default_value="guess")]
+    input_format: Format,
+
+    /// The meta information, as a separate YAML file (required for JSONL)
+    #[arg(short,long)]
+    meta_file: Option<String>,
+
+    /// Print the problems as a JSON array
+    #[arg(long)]
+    json: bool,
+
+    /// Ignore incorrect document IDs
+    #[arg(long)]
+    #[clap(default_value="false")]
+    ignore_id_errors: bool
+}
+
+/// Command to summarize a corpus
+#[derive(Parser, Debug)]
+#[command(name = "stats", about = "Show the document, character and annotation counts of a corpus")]
+struct StatsCommand {
+    /// The file to summarize
+    input: String,
+
+    /// The format of the input file
+    #[arg(short,long)]
+    #[clap(default_value="guess")]
+    input_format: Format,
+
+    /// The meta information, as a separate YAML file (required for JSONL)
+    #[arg(short,long)]
+    meta_file: Option<String>,
+
+    /// Print the statistics as JSON
+    #[arg(long)]
+    json: bool,
+
+    /// Ignore incorrect document IDs
+    #[arg(long)]
+    #[clap(default_value="false")]
+    ignore_id_errors: bool
+}
+
+/// Read a whole corpus into memory
+fn read_corpus(input : &str, format : &Format, meta_file : Option<&String>,
+    ignore_id_errors : bool) -> Result<SimpleCorpus, String> {
+    let mut reader = if input.ends_with(".gz") {
+        Box::new(BufReader::new(flate2::read::GzDecoder::new(File::open(input)
+            .map_err(|e| format!("Failed to open input file: {}", e))?))) as Box<dyn std::io::BufRead>
+    } else {
+        Box::new(BufReader::new(File::open(input)
+            .map_err(|e| format!("Failed to open input file: {}", e))?)) as Box<dyn std::io::BufRead>
+    };
+    let settings = if ignore_id_errors {
+        teanga::SerializationSettings::new().ignore_id_errors()
+    } else {
+        teanga::SerializationSettings::new()
+    };
+    let mut corpus = SimpleCorpus::new();
+    if let Some(meta_file) = meta_file {
+        read_yaml_with_config(File::open(meta_file)
+            .map_err(|e| format!("Failed to open meta file: {}", e))?,
+            &mut corpus,
+            teanga::SerializationSettings::new().header_only())
+            .map_err(|e| format!("Failed to read meta file: {}", e))?;
+    }
+    match format.guess(input) {
+        Format::JSON => teanga::read_json_with_config(&mut reader, &mut corpus, settings)
+            .map_err(|e| format!("Failed to read JSON: {}", e))?,
+        Format::JSONL => read_jsonl(&mut reader, &mut corpus)
+            .map_err(|e| format!("Failed to read JSONL: {}", e))?,
+        Format::YAML => read_yaml_with_config(&mut reader, &mut corpus, settings)
+            .map_err(|e| format!("Failed to read YAML: {}", e))?,
+        Format::Cuac => teanga::read_cuac(&mut reader, &mut corpus)
+            .map_err(|e| format!("Failed to read Cuac: {}", e))?,
+        Format::Conllu => teanga::read_conllu(&mut reader, &mut corpus)
+            .map_err(|e| format!("Failed to read CoNLL-U: {}", e))?,
+        Format::Zstd => teanga::read_compressed(&mut reader, &mut corpus)
+            .map_err(|e| format!("Failed to read zstd: {}", e))?,
+        Format::Guess => panic!("unreachable")
+    };
+    Ok(corpus)
+}
+
 impl LoadCommand {
     fn run(&self) -> Result<(), String> {
         let mut corpus = DiskCorpus::new(&self.db)
@@ -159,6 +261,51 @@ impl LoadCommand {
     }
 }
 
+impl ValidateCommand {
+    /// Returns whether the corpus is valid
+    fn run(&self) -> Result<bool, String> {
+        let corpus = read_corpus(&self.input, &self.input_format, self.meta_file.as_ref(),
+            self.ignore_id_errors)?;
+        let errors = teanga::validate_corpus(&corpus)
+            .map_err(|e| format!("Failed to validate corpus: {}", e))?;
+        if self.json {
+            println!("{}", serde_json::to_string(&errors)
+                .map_err(|e| format!("Failed to write JSON: {}", e))?);
+        } else {
+            for error in errors.iter() {
+                println!("{}", error);
+            }
+        }
+        Ok(errors.is_empty())
+    }
+}
+
+impl StatsCommand {
+    fn run(&self) -> Result<(), String> {
+        let corpus = read_corpus(&self.input, &self.input_format, self.meta_file.as_ref(),
+            self.ignore_id_errors)?;
+        let stats = corpus.stats()
+            .map_err(|e| format!("Failed to compute statistics: {}", e))?;
+        if self.json {
+            println!("{}", serde_json::to_string(&stats)
+                .map_err(|e| format!("Failed to write JSON: {}", e))?);
+        } else {
+            println!("Documents: {}", stats.documents);
+            println!("Characters: {}", stats.characters);
+            for (name, layer) in stats.layers.iter() {
+                println!("{}: {} annotations in {} documents ({:.2} per document)",
+                    name, layer.annotations, layer.documents, layer.mean_per_doc);
+                if let Some(ref values) = layer.values {
+                    for (value, count) in values.iter() {
+                        println!("    {}: {}", value, count);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 impl ConvertCommand {
     fn run(&self) -> Result<(), String> {
         let (mut corpus, rx_corpus) = teanga::channel_corpus::channel_corpus();
@@ -210,6 +357,21 @@ impl ConvertCommand {
                     teanga::read_cuac(&mut input, &mut corpus)
                         .map_err(|e| format!("Failed to read Cuac: {}", e)).unwrap();
                     }
+                Format::Conllu => {
+                    // CoNLL-U reading adds its layers one at a time, so it
+                    // cannot stream into the channel corpus
+                    let mut conllu = SimpleCorpus::new();
+                    teanga::read_conllu(&mut input, &mut conllu)
+                        .map_err(|e| format!("Failed to read CoNLL-U: {}", e)).unwrap();
+                    corpus.set_meta(conllu.get_meta().clone()).unwrap();
+                    for id in conllu.get_order() {
+                        corpus.add_doc(conllu.get_doc_by_id(id).unwrap().content).unwrap();
+                    }
+                }
+                Format::Zstd => {
+                    teanga::read_compressed(&mut input, &mut corpus)
+                        .map_err(|e| format!("Failed to read zstd: {}", e)).unwrap();
+                    }
                 Format::Guess => panic!("unreachable")
             };
 
@@ -247,6 +409,16 @@ impl ConvertCommand {
                     teanga::write_cuac_with_config(&mut output, &rx_corpus, &config)
                         .map_err(|e| format!("Failed to write Cuac: {}", e)).unwrap();
                     }
+                Format::Conllu => {
+                    let rx_corpus = rx_corpus.await_meta();
+                    teanga::write_conllu(&mut output, &rx_corpus)
+                        .map_err(|e| format!("Failed to write CoNLL-U: {}", e)).unwrap();
+                    }
+                Format::Zstd => {
+                    let rx_corpus = rx_corpus.await_meta();
+                    teanga::write_compressed(&mut output, &rx_corpus)
+                        .map_err(|e| format!("Failed to write zstd: {}", e)).unwrap();
+                    }
                 Format::Guess => panic!("unreachable")
             }
         });
@@ -265,6 +437,14 @@ fn main() {
         },
         SubCommand::Convert(to_cbor) => {
             to_cbor.run().unwrap();
+        },
+        SubCommand::Stats(stats) => {
+            stats.run().unwrap();
+        },
+        SubCommand::Validate(validate) => {
+            if !validate.run().unwrap() {
+                std::process::exit(1);
+            }
         }
     }
 }
@@ -48,6 +48,13 @@ impl WriteableCorpus for PyCorpus {
         }
     }
 
+    fn merge_meta(&mut self, meta: HashMap<String, LayerDesc>) -> TeangaResult<()> {
+        match self {
+            PyCorpus::Disk(corpus) => corpus.merge_meta(meta),
+            PyCorpus::Mem(corpus) => corpus.merge_meta(meta)
+        }
+    }
+
     fn set_order(&mut self, order: Vec<String>) -> TeangaResult<()> {
         match self {
             PyCorpus::Disk(corpus) => corpus.set_order(order),
@@ -196,6 +203,35 @@ impl PyDiskCorpus {
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))
     }
 
+    /// Remove a document from the corpus
+    ///
+    /// # Arguments
+    /// * `id` - The ID of the document
+    fn remove_doc(&mut self, id : &str) -> PyResult<()> {
+        self.0.remove_doc(id)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))
+    }
+
+    /// Find the annotations of a layer, optionally only those with a given
+    /// value or overlapping an annotation of another layer
+    ///
+    /// # Returns
+    /// The document ID, the index in the layer and the character span of
+    /// each annotation
+    #[pyo3(signature = (layer, equals=None, overlapping=None))]
+    fn query_annotations(&self, layer : &str, equals : Option<String>,
+        overlapping : Option<String>) -> PyResult<Vec<(String, usize, (usize, usize))>> {
+        let mut query = self.0.query().layer(layer);
+        if let Some(equals) = equals {
+            query = query.equals(equals);
+        }
+        if let Some(overlapping) = overlapping {
+            query = query.overlapping(&overlapping);
+        }
+        query.run()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))
+    }
+
     fn search(&self, query : query::PyQuery) -> PyResult<Vec<String>> {
         let mut vec = Vec::new();
         for result in self.0.search(query.0) {
@@ -252,6 +288,8 @@ impl PyLayerDesc {
             Some(DataType::Enum(v)) => format!("{:?}", v),
             Some(DataType::String) => "string".to_string(),
             Some(DataType::Link) => "link".to_string(),
+            Some(DataType::Float) => "float".to_string(),
+            Some(DataType::Int) => "int".to_string(),
             None => "None".to_string()
         };
         let base = match &self.0.base {
@@ -298,7 +336,12 @@ fn layerdesc_from_dict(dict: HashMap<String, Bound<PyAny>>) -> PyResult<PyLayerD
     let data = dict.get("data")
         .and_then(|v| v.extract::<PyValue>().ok())
         .and_then(|v| match v {
-            PyValue::String(_) => Some(DataType::String),
+            PyValue::String(s) => match s.as_str() {
+                "link" => Some(DataType::Link),
+                "float" => Some(DataType::Float),
+                "int" => Some(DataType::Int),
+                _ => Some(DataType::String),
+            },
             PyValue::Array(arr) => Some(DataType::Enum(arr.into_iter().filter_map(|x| match x {
                 PyValue::String(s) => Some(s),
                 _ => None,
@@ -774,6 +817,8 @@ impl <'py> FromPyObject<'py> for PyDataType {
         match ob.extract::<String>()?.to_lowercase().as_str() {
             "string" => Ok(PyDataType(DataType::String)),
             "link" => Ok(PyDataType(DataType::Link)),
+            "float" => Ok(PyDataType(DataType::Float)),
+            "int" => Ok(PyDataType(DataType::Int)),
             _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
                 format!("Unknown data type {}", ob.extract::<String>()?)))
         }
@@ -790,6 +835,8 @@ impl<'py> IntoPyObject<'py> for PyDataType {
             DataType::String => "string".into_bound_py_any(py),
             DataType::Enum(v) => v.into_bound_py_any(py),
             DataType::Link => "link".into_bound_py_any(py),
+            DataType::Float => "float".into_bound_py_any(py),
+            DataType::Int => "int".into_bound_py_any(py),
         }
     }
 }
@@ -212,6 +212,30 @@ def test_val_freq():
     assert (corpus.val_freq("pos", lambda x: x[0] == "A") ==
         Counter({'ADJ': 2, 'ADV': 1}))
  
+def test_remove_doc():
+    corpus = teangadb.Corpus("<memory>")
+    corpus.add_layer_meta("text", "characters", {}, None, None, None, None, None)
+    doc_id = corpus.add_doc({"text": "This is a document."})
+    other_id = corpus.add_doc({"text": "Another document."})
+    corpus.remove_doc(doc_id)
+    assert corpus.order == [other_id]
+
+def test_query_annotations():
+    corpus = teangadb.Corpus("<memory>")
+    corpus.add_layer_meta("text", "characters", {}, None, None, None, None, None)
+    corpus.add_layer_meta("words", "span", {}, "text", None, None, None, None)
+    corpus.add_layer_meta("pos", "seq", {}, "words", "string", None, None, None)
+    corpus.add_layer_meta("ner", "span", {}, "text", None, None, None, None)
+    doc_id = corpus.add_doc({"text": "Dublin is in Ireland",
+        "words": [(0, 6), (7, 9), (10, 12), (13, 20)],
+        "pos": ["PROPN", "AUX", "ADP", "PROPN"],
+        "ner": [(13, 20)]})
+    assert corpus.query_annotations("pos", equals="PROPN") == [
+        (doc_id, 0, (0, 6)), (doc_id, 3, (13, 20))]
+    assert corpus.query_annotations("words", overlapping="ner") == [
+        (doc_id, 3, (13, 20))]
+    assert corpus.query_annotations("pos", equals="VERB") == []
+
 # Require fix of #29 in teanga2
 #def test_tcf():
 #    corpus = Corpus()
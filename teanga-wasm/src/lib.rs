@@ -7,8 +7,10 @@ use web_sys::console;
 // Import the actual Teanga types but only the in-memory ones for WASM
 use teanga::{
     SimpleCorpus, LayerType, DataType, Layer, Corpus, ReadableCorpus, WriteableCorpus,
-    LayerDesc, Document, Value, TeangaError
+    LayerDesc, Document, Value, TeangaError, TeangaData, TeangaResult
 };
+use teanga::formats::label_studio::{label_studio_spans, label_studio_task, LabelStudioError,
+    LabelStudioLayers};
 
 // Setup panic hook for better debugging
 #[wasm_bindgen(start)]
@@ -18,6 +20,7 @@ pub fn main() {
 
 // JavaScript-friendly error type
 #[wasm_bindgen]
+#[derive(Debug)]
 pub struct WasmError {
     message: String,
 }
@@ -38,6 +41,14 @@ impl From<TeangaError> for WasmError {
     }
 }
 
+impl From<LabelStudioError> for WasmError {
+    fn from(err: LabelStudioError) -> Self {
+        WasmError {
+            message: format!("{}", err),
+        }
+    }
+}
+
 impl From<serde_json::Error> for WasmError {
     fn from(err: serde_json::Error) -> Self {
         WasmError {
@@ -46,10 +57,25 @@ impl From<serde_json::Error> for WasmError {
     }
 }
 
+impl From<serde_wasm_bindgen::Error> for WasmError {
+    fn from(err: serde_wasm_bindgen::Error) -> Self {
+        WasmError {
+            message: format!("JS conversion error: {}", err),
+        }
+    }
+}
+
 // Main WASM wrapper for Teanga corpus
 #[wasm_bindgen]
 pub struct TeangaWasm {
     corpus: SimpleCorpus,
+    float_precision: Option<usize>,
+    default_char_layer: Option<String>,
+    layer_order: Vec<String>,
+    required_layers: Vec<String>,
+    strict: bool,
+    apply_defaults: bool,
+    corpus_meta: HashMap<String, Value>,
 }
 
 #[wasm_bindgen]
@@ -58,9 +84,77 @@ impl TeangaWasm {
     pub fn new() -> TeangaWasm {
         TeangaWasm {
             corpus: SimpleCorpus::new(),
+            float_precision: None,
+            default_char_layer: None,
+            layer_order: Vec::new(),
+            required_layers: Vec::new(),
+            strict: false,
+            apply_defaults: false,
+            corpus_meta: HashMap::new(),
         }
     }
 
+    /// Round floats to the given number of decimal places when serializing.
+    /// Pass `None` to write floats at full precision. Non-finite floats have
+    /// no JSON representation and are always written as the strings `"NaN"`,
+    /// `"Infinity"` and `"-Infinity"`.
+    #[wasm_bindgen]
+    pub fn set_float_precision(&mut self, digits: Option<usize>) {
+        self.float_precision = digits;
+    }
+
+    /// Set the characters layer used by methods when no layer is given
+    #[wasm_bindgen]
+    pub fn set_default_char_layer(&mut self, name: &str) {
+        self.default_char_layer = Some(name.to_string());
+    }
+
+    /// Set the default value of a declared layer from its JSON value. See
+    /// `set_apply_defaults`.
+    #[wasm_bindgen]
+    pub fn set_layer_default(&mut self, name: &str, value_json: &str) -> Result<(), WasmError> {
+        let default = self.json_value_to_layer(name, serde_json::from_str(value_json)?)?;
+        let mut meta = self.corpus.clone_meta();
+        let layer_desc = meta.get_mut(name).ok_or_else(|| WasmError {
+            message: format!("Layer {} is not described in meta", name)
+        })?;
+        layer_desc.default = Some(default);
+        self.corpus.set_meta(meta)?;
+        Ok(())
+    }
+
+    /// When set, documents read with `get_doc_by_id` or `get_doc_by_id_js`
+    /// include the default value of each layer they do not contain. The
+    /// defaults are not stored in the documents.
+    #[wasm_bindgen]
+    pub fn set_apply_defaults(&mut self, on: bool) {
+        self.apply_defaults = on;
+    }
+
+    /// Set the layers every document must contain, as a JSON array of names.
+    /// Required layers need not be declared yet.
+    #[wasm_bindgen]
+    pub fn set_required_layers(&mut self, names_json: &str) -> Result<(), WasmError> {
+        self.required_layers = serde_json::from_str(names_json)?;
+        Ok(())
+    }
+
+    /// In strict mode `add_doc` rejects documents for which `validate_doc`
+    /// reports a problem, and documents given as JSON may not repeat a layer
+    #[wasm_bindgen]
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Check a document given as JSON against the corpus without adding it.
+    /// Returns a JSON array of problems, such as missing required layers or
+    /// undeclared layers, which is empty if the document is valid.
+    #[wasm_bindgen]
+    pub fn validate_doc(&self, doc_json: &str) -> Result<String, WasmError> {
+        let doc_data = self.parse_doc_json(doc_json)?;
+        Ok(serde_json::to_string(&self.doc_problems(&doc_data))?)
+    }
+
     #[wasm_bindgen]
     pub fn add_layer_meta(
         &mut self,
@@ -83,8 +177,25 @@ impl TeangaWasm {
         let data = match data_type.as_deref() {
             Some("string") => Some(DataType::String),
             Some("link") => Some(DataType::Link),
+            Some("float") => Some(DataType::Float),
+            Some("int") => Some(DataType::Int),
             Some(enum_str) if enum_str.starts_with('[') => {
                 let values: Vec<String> = serde_json::from_str(enum_str)?;
+                if values.is_empty() {
+                    return Err(WasmError {
+                        message: format!("Enum of layer {} has no values", name)
+                    });
+                }
+                let mut seen = std::collections::HashSet::new();
+                let duplicates: std::collections::BTreeSet<&String> = values.iter()
+                    .filter(|v| !seen.insert(*v))
+                    .collect();
+                if !duplicates.is_empty() {
+                    return Err(WasmError {
+                        message: format!("Enum of layer {} repeats values: {}", name,
+                            duplicates.into_iter().cloned().collect::<Vec<_>>().join(", "))
+                    });
+                }
                 Some(DataType::Enum(values))
             }
             None => None,
@@ -103,6 +214,9 @@ impl TeangaWasm {
             None, // default
             HashMap::new(), // meta
         )?;
+        if !self.layer_order.iter().any(|n| n == name) {
+            self.layer_order.push(name.to_string());
+        }
 
         Ok(())
     }
@@ -110,31 +224,315 @@ impl TeangaWasm {
     #[wasm_bindgen]
     pub fn add_doc(&mut self, doc_json: &str) -> Result<String, WasmError> {
         // Parse the JSON into a map
-        let doc_data: HashMap<String, serde_json::Value> = serde_json::from_str(doc_json)?;
+        let doc_data = self.parse_doc_json(doc_json)?;
+        let layers = self.prepare_doc(doc_data)?;
+        let doc_id = self.corpus.add_doc(layers)?;
+        Ok(doc_id)
+    }
 
-        // Convert JSON values to Teanga layers
-        let mut layers = HashMap::new();
+    /// Like `add_doc` but takes a JS object instead of a JSON string
+    #[wasm_bindgen]
+    pub fn add_doc_js(&mut self, doc: JsValue) -> Result<String, WasmError> {
+        let doc_data: HashMap<String, serde_json::Value> = serde_wasm_bindgen::from_value(doc)?;
+        let layers = self.prepare_doc(doc_data)?;
+        Ok(self.corpus.add_doc(layers)?)
+    }
+
+    /// Add a JSON array of documents, returning a JSON array of their IDs.
+    /// Nothing is added if any document is invalid.
+    #[wasm_bindgen]
+    pub fn add_docs(&mut self, docs_json: &str) -> Result<String, WasmError> {
+        let docs = self.parse_docs_json(docs_json)?;
+        Ok(serde_json::to_string(&self.add_parsed_docs(docs)?)?)
+    }
+
+    /// Like `add_docs` but takes a JS array of objects and returns a JS array
+    /// of IDs
+    #[wasm_bindgen]
+    pub fn add_docs_js(&mut self, docs: JsValue) -> Result<JsValue, WasmError> {
+        let docs: Vec<HashMap<String, serde_json::Value>> = serde_wasm_bindgen::from_value(docs)?;
+        let ids = self.add_parsed_docs(docs)?;
+        Ok(ids.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+    }
+
+    /// Check a JSON array of documents as `add_docs` would without changing
+    /// the corpus. Returns a JSON report `{would_add, ids, failed, problems}`
+    /// with the number of documents that would be added, the IDs they would
+    /// get, the index and error of each document that would be rejected, and
+    /// the index and `validate_doc` problems of each document that has any.
+    #[wasm_bindgen]
+    pub fn add_docs_dry_run(&self, docs_json: &str) -> String {
+        let docs = match self.parse_docs_json(docs_json) {
+            Ok(docs) => docs,
+            Err(e) => return serde_json::json!({ "error": e.message }).to_string(),
+        };
+        let mut existing = self.corpus.get_order().clone();
+        let mut ids = Vec::new();
+        let mut failed = Vec::new();
+        let mut problems = Vec::new();
+        for (index, doc_data) in docs.into_iter().enumerate() {
+            let doc_problems = self.doc_problems(&doc_data);
+            if !doc_problems.is_empty() {
+                problems.push(serde_json::json!({ "index": index, "problems": doc_problems }));
+            }
+            let doc = self.prepare_doc(doc_data).and_then(|layers|
+                Ok(Document::new(layers, self.corpus.get_meta())?));
+            match doc {
+                Ok(doc) => {
+                    let id = teanga::teanga_id(&existing, &doc);
+                    existing.push(id.clone());
+                    ids.push(id);
+                }
+                Err(e) => failed.push(serde_json::json!({ "index": index, "error": e.message })),
+            }
+        }
+        serde_json::json!({
+            "would_add": if failed.is_empty() { ids.len() } else { 0 },
+            "ids": ids,
+            "failed": failed,
+            "problems": problems
+        }).to_string()
+    }
+
+    /// Add one document per row of a CSV table with a header row. The
+    /// `text_column` is stored as a characters layer of the same name and the
+    /// other columns as a `_metadata` object. If no `text_column` is given
+    /// the default characters layer is used. As document IDs are derived from
+    /// the content, the value of `id_column`, if given, is kept as `_id`.
+    /// Returns a JSON array of the new document IDs or, if `id_column` is
+    /// given, of `{"id": ..., "csv_id": ...}` objects pairing each new
+    /// document ID with the ID from the CSV.
+    #[wasm_bindgen]
+    pub fn add_csv(&mut self, csv: &str, text_column: Option<String>, id_column: Option<String>) -> Result<String, WasmError> {
+        let text_column = &self.resolve_char_layer(text_column)?;
+        let mut rows = parse_csv(csv)?.into_iter();
+        let header = rows.next().ok_or_else(|| WasmError {
+            message: "CSV has no header row".to_string()
+        })?;
+        let column = |name: &str| header.iter().position(|h| h == name).ok_or_else(|| WasmError {
+            message: format!("CSV has no column {}", name)
+        });
+        let text_index = column(text_column)?;
+        let id_index = id_column.as_deref().map(column).transpose()?;
+        match self.corpus.get_meta().get(text_column) {
+            Some(desc) if desc.layer_type != LayerType::characters => return Err(WasmError {
+                message: format!("Layer {} is not a characters layer", text_column)
+            }),
+            Some(_) => {}
+            None => self.add_layer_meta(text_column, "characters", None, None)?,
+        }
+        let mut ids = Vec::new();
+        let mut csv_ids = Vec::new();
+        for (n, row) in rows.enumerate() {
+            if row.len() != header.len() {
+                return Err(WasmError {
+                    message: format!("CSV row {} has {} fields but the header has {}",
+                        n + 2, row.len(), header.len())
+                });
+            }
+            let mut fields = HashMap::new();
+            let mut layers = Vec::new();
+            for (i, value) in row.into_iter().enumerate() {
+                if i == text_index {
+                    layers.push((text_column.to_string(), Layer::Characters(value)));
+                } else if Some(i) == id_index {
+                    csv_ids.push(value.clone());
+                    layers.push(("_id".to_string(), Layer::MetaLayer(Some(Value::String(value)))));
+                } else {
+                    fields.insert(header[i].clone(), Value::String(value));
+                }
+            }
+            layers.push(("_metadata".to_string(), Layer::MetaLayer(Some(Value::Object(fields)))));
+            ids.push(self.corpus.add_doc(layers)?);
+        }
+        if id_index.is_some() {
+            let pairs: Vec<serde_json::Value> = ids.into_iter().zip(csv_ids)
+                .map(|(id, csv_id)| serde_json::json!({ "id": id, "csv_id": csv_id }))
+                .collect();
+            Ok(serde_json::to_string(&pairs)?)
+        } else {
+            Ok(serde_json::to_string(&ids)?)
+        }
+    }
+
+    /// Replace layers of an existing document with those of a JSON document,
+    /// keeping its other layers. Returns the ID of the document, which changes
+    /// if its characters layers change; the document keeps its position.
+    #[wasm_bindgen]
+    pub fn update_doc(&mut self, id: &str, doc_json: &str) -> Result<String, WasmError> {
+        self.merge_doc(id, doc_json, true)
+    }
+
+    /// Remove a document from the corpus
+    #[wasm_bindgen]
+    pub fn remove_doc(&mut self, id: &str) -> Result<(), WasmError> {
+        self.corpus.get_doc_by_id(id)?;
+        self.corpus.remove_doc(id)?;
+        Ok(())
+    }
+
+    /// Merge the layers of a JSON document into an existing document. Layers
+    /// only in the existing document are kept; layers in both are replaced if
+    /// `overwrite` is set and are an error otherwise. Returns the ID of the
+    /// document, which changes if its characters layers change.
+    #[wasm_bindgen]
+    pub fn merge_doc(&mut self, id: &str, doc_json: &str, overwrite: bool) -> Result<String, WasmError> {
+        let doc_data = self.parse_doc_json(doc_json)?;
+        let doc = self.corpus.get_doc_by_id(id)?;
+        if !overwrite {
+            if let Some(name) = self.ordered_layer_names(doc_data.keys()).into_iter()
+                .find(|name| doc.get(name).is_some()) {
+                return Err(WasmError {
+                    message: format!("Layer {} already exists in document {}", name, id)
+                });
+            }
+        }
+        if self.strict {
+            let mut merged: HashMap<String, serde_json::Value> = doc.content.iter()
+                .map(|(name, layer)| (name.clone(), self.layer_to_json_value(layer)))
+                .collect();
+            merged.extend(doc_data.iter().map(|(name, value)| (name.clone(), value.clone())));
+            self.check_strict(&merged)?;
+        }
+        let mut layers = Vec::new();
         for (key, value) in doc_data {
-            let layer = self.json_value_to_layer(value)?;
-            layers.insert(key, layer);
+            let layer = self.json_value_to_layer(&key, value)?;
+            layers.push((key, layer));
         }
+        Ok(self.corpus.update_doc(id, layers)?)
+    }
 
-        let doc_id = self.corpus.add_doc(layers)?;
-        Ok(doc_id)
+    /// Add a document from plain text stored as `char_layer`. If
+    /// `token_layer` is given the text is split with the simple tokenizer and
+    /// the tokens stored as a span layer. Either layer is registered if it is
+    /// not yet described. If no `char_layer` is given the default characters
+    /// layer is used. Returns the ID of the new document.
+    #[wasm_bindgen]
+    pub fn add_text(&mut self, text: &str, char_layer: Option<String>, token_layer: Option<String>) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        match self.corpus.get_meta().get(char_layer) {
+            Some(desc) if desc.layer_type != LayerType::characters => return Err(WasmError {
+                message: format!("Layer {} is not a characters layer", char_layer)
+            }),
+            Some(_) => {}
+            None => self.add_layer_meta(char_layer, "characters", None, None)?,
+        }
+        let mut layers = vec![(char_layer.to_string(), Layer::Characters(text.to_string()))];
+        if let Some(token_layer) = token_layer {
+            match self.corpus.get_meta().get(&token_layer) {
+                Some(desc) if desc.layer_type != LayerType::span
+                    || desc.base.as_deref() != Some(char_layer) => return Err(WasmError {
+                    message: format!("Layer {} is not a span layer over {}", token_layer, char_layer)
+                }),
+                Some(_) => {}
+                None => self.add_layer_meta(&token_layer, "span", Some(char_layer.to_string()), None)?,
+            }
+            layers.push((token_layer, Layer::L2(simple_tokenize(text, false))));
+        }
+        Ok(self.corpus.add_doc(layers)?)
     }
 
     #[wasm_bindgen]
     pub fn get_doc_by_id(&self, id: &str) -> Result<String, WasmError> {
-        let doc = self.corpus.get_doc_by_id(id)?;
-        
-        // Convert document to JSON-serializable format
-        let mut doc_map = HashMap::new();
-        for (key, layer) in &doc.content {
-            doc_map.insert(key.clone(), self.layer_to_json_value(layer));
+        let doc = self.read_doc(id)?;
+        self.doc_to_json_string(&doc)
+    }
+
+    /// Get a short URL-safe hash of all layers of a document. Unlike the
+    /// document ID it does not depend on the other documents of the corpus,
+    /// so identical documents in different corpora get the same value.
+    #[wasm_bindgen]
+    pub fn content_id(&self, doc_id: &str) -> Result<String, WasmError> {
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        Ok(teanga::content_id(&doc)?[..CONTENT_ID_LENGTH].to_string())
+    }
+
+    /// Like `get_doc_by_id` but with the surface text in `char_layer` of each
+    /// span appended to its entry, e.g. `[0, 5, "N", "Hello"]`, for every span
+    /// layer that resolves to `char_layer`
+    #[wasm_bindgen]
+    pub fn get_doc_resolved(&self, doc_id: &str, char_layer: Option<String>) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let meta = self.corpus.get_meta();
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let text = doc.get(char_layer).and_then(|l| l.characters());
+        let mut entries = Vec::new();
+        for name in self.ordered_layer_names(doc.content.keys()) {
+            let mut value = self.layer_to_json_value(&doc.content[name]);
+            let resolves = meta.get(name).map_or(false, |d| d.layer_type == LayerType::span)
+                && self.characters_layer_of(name).map_or(false, |c| c == char_layer);
+            if let (true, Some(text), serde_json::Value::Array(items)) = (resolves, text, &mut value) {
+                let indexes = doc.indexes(name, char_layer, meta)?;
+                for (item, (start, end)) in items.iter_mut().zip(indexes) {
+                    if let serde_json::Value::Array(entry) = item {
+                        entry.push(text.get(start..end).unwrap_or_default().into());
+                    }
+                }
+            }
+            entries.push((name.as_str(), serde_json::to_string(&value)?));
         }
-        
-        let json = serde_json::to_string(&doc_map)?;
-        Ok(json)
+        Ok(ordered_json_object(entries)?)
+    }
+
+    /// Like `get_doc_by_id` but returns a JS object instead of a JSON string
+    #[wasm_bindgen]
+    pub fn get_doc_by_id_js(&self, id: &str) -> Result<JsValue, WasmError> {
+        let doc = self.read_doc(id)?;
+        Ok(self.ordered_doc(&doc).serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+    }
+
+    /// Call `callback(doc_id, layer_name, layer_json)` for every layer of every
+    /// document, in corpus order and with layers in registration order. The
+    /// traversal stops early if the callback returns `false`.
+    #[wasm_bindgen]
+    pub fn for_each_layer(&self, callback: &js_sys::Function) -> Result<(), WasmError> {
+        self.for_each_layer_with(|doc_id, name, layer_json| {
+            let result = callback.call3(&JsValue::NULL,
+                &JsValue::from_str(doc_id),
+                &JsValue::from_str(name),
+                &JsValue::from_str(&layer_json))
+                .map_err(|e| WasmError { message: format!("Callback failed: {:?}", e) })?;
+            Ok(result != JsValue::FALSE)
+        })
+    }
+
+    /// Transform every document with `callback(doc_json)`, which returns the
+    /// new document as JSON (or as an object), or `null` to leave it
+    /// unchanged. All results are validated before any document is replaced,
+    /// so a failing callback or invalid result leaves the corpus untouched.
+    /// Replaced documents keep their position but get new IDs, while
+    /// documents returned unchanged keep their IDs.
+    #[wasm_bindgen]
+    pub fn map_docs(&mut self, callback: &js_sys::Function) -> Result<(), WasmError> {
+        self.map_docs_with(|doc_id, doc_json| {
+            let result = callback.call1(&JsValue::NULL, &JsValue::from_str(&doc_json))
+                .map_err(|e| WasmError { message: format!("Callback failed for document {}: {:?}", doc_id, e) })?;
+            if result.is_null() || result.is_undefined() {
+                return Ok(None);
+            }
+            match result.as_string() {
+                Some(json) => Ok(Some(json)),
+                None => js_sys::JSON::stringify(&result).ok().and_then(|s| s.as_string())
+                    .map(Some)
+                    .ok_or_else(|| WasmError {
+                        message: format!("Callback returned a value that is not JSON for document {}", doc_id)
+                    }),
+            }
+        })
+    }
+
+    /// Like `get_doc_ids` but returns a JS array instead of a JSON string
+    #[wasm_bindgen]
+    pub fn get_doc_ids_js(&self) -> Result<JsValue, WasmError> {
+        let ids = self.corpus.get_docs();
+        Ok(ids.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+    }
+
+    /// Like `get_meta` but returns a JS object instead of a JSON string
+    #[wasm_bindgen]
+    pub fn get_meta_js(&self) -> Result<JsValue, WasmError> {
+        let meta = self.meta_to_json_value();
+        Ok(meta.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
     }
 
     #[wasm_bindgen]
@@ -143,91 +541,229 @@ impl TeangaWasm {
         let json = serde_json::to_string(&ids)?;
         Ok(json)
     }
+
+    /// Get the position of a document in the corpus order. Indices are dense
+    /// and stable while documents are only added; removing a document shifts
+    /// the indices of all later documents down by one, and reordering (for
+    /// example with `shuffle_docs`) changes them.
+    #[wasm_bindgen]
+    pub fn doc_index(&self, id: &str) -> Result<usize, WasmError> {
+        self.corpus.get_order().iter().position(|doc_id| doc_id == id).ok_or_else(|| WasmError {
+            message: format!("Document {} not found", id)
+        })
+    }
+
+    /// Get the ID of the document at a position in the corpus order. See
+    /// `doc_index`.
+    #[wasm_bindgen]
+    pub fn doc_id_at(&self, index: usize) -> Result<String, WasmError> {
+        self.corpus.get_order().get(index).cloned().ok_or_else(|| WasmError {
+            message: format!("No document at index {}", index)
+        })
+    }
+
     #[wasm_bindgen]
     pub fn get_meta(&self) -> Result<String, WasmError> {
-        // Convert metadata to JSON-serializable format
-        let mut meta_map = HashMap::new();
-        for (name, layer_desc) in self.corpus.get_meta() {
-            let mut desc_map = HashMap::new();
-            desc_map.insert("layer_type".to_string(), 
-                serde_json::Value::String(format!("{}", layer_desc.layer_type)));
-            
-            if let Some(ref base) = layer_desc.base {
-                desc_map.insert("base".to_string(), serde_json::Value::String(base.clone()));
-            }
-            
-            if let Some(ref data) = layer_desc.data {
-                let data_value = match data {
-                    DataType::String => serde_json::Value::String("string".to_string()),
-                    DataType::Link => serde_json::Value::String("link".to_string()),
-                    DataType::Enum(vals) => serde_json::Value::Array(
-                        vals.iter().map(|v| serde_json::Value::String(v.clone())).collect()
-                    ),
-                };
-                desc_map.insert("data".to_string(), data_value);
-            }
-            
-            meta_map.insert(name.clone(), serde_json::Value::Object(
-                desc_map.into_iter().collect()
-            ));
-        }
-        
-        Ok(serde_json::to_string(&meta_map)?)
+        Ok(serde_json::to_string(&self.meta_to_json_value())?)
     }
 
     #[wasm_bindgen]
     pub fn tokenize_simple(&self, text: &str) -> String {
-        let tokens = simple_tokenize(text);
+        let tokens = simple_tokenize(text, false);
         serde_json::to_string(&tokens).unwrap_or_else(|_| "[]".to_string())
     }
 
+    /// Tokenize a text with the tokenizer named by `mode` ("simple" or
+    /// "whitespace"), returning the token offsets as JSON. With
+    /// `keep_apostrophes` the simple tokenizer keeps apostrophes between
+    /// letters inside words.
+    #[wasm_bindgen]
+    pub fn tokenize(&self, text: &str, mode: &str, keep_apostrophes: bool) -> Result<String, WasmError> {
+        let tokens = tokenize_with_mode(text, mode, keep_apostrophes)?;
+        Ok(serde_json::to_string(&tokens)?)
+    }
+
+    /// Tokenize a text with the simple tokenizer in chunks of about
+    /// `chunk_chars` characters, split at whitespace so that no token crosses
+    /// a chunk. `callback` is called with the JSON token offsets of each
+    /// chunk, which are relative to the whole text.
+    #[wasm_bindgen]
+    pub fn tokenize_chunked(&self, text: &str, chunk_chars: usize, callback: &js_sys::Function) -> Result<(), WasmError> {
+        tokenize_chunks(text, chunk_chars, |tokens| {
+            callback.call1(&JsValue::NULL, &JsValue::from_str(&serde_json::to_string(&tokens)?))
+                .map_err(|e| WasmError { message: format!("Callback failed: {:?}", e) })?;
+            Ok(())
+        })
+    }
+
+    /// Summarize how `mode` ("simple" or "whitespace") tokenizes a text: the
+    /// number of tokens, their mean length in characters, and how many are
+    /// punctuation or numbers
+    #[wasm_bindgen]
+    pub fn tokenize_stats(&self, text: &str, mode: &str) -> String {
+        let tokens = match tokenize_with_mode(text, mode, false) {
+            Ok(tokens) => tokens,
+            Err(e) => return serde_json::json!({ "error": e.message }).to_string(),
+        };
+        let words: Vec<&str> = tokens.iter()
+            .map(|&(start, end)| &text[start as usize..end as usize])
+            .collect();
+        let total_chars: usize = words.iter().map(|w| w.chars().count()).sum();
+        let mean_length = if words.is_empty() { 0.0 } else { total_chars as f64 / words.len() as f64 };
+        let punctuation = words.iter()
+            .filter(|w| w.chars().all(|c| !c.is_alphanumeric() && !c.is_whitespace()))
+            .count();
+        let numeric = words.iter()
+            .filter(|w| w.chars().all(|c| c.is_numeric()))
+            .count();
+        serde_json::json!({
+            "token_count": words.len(),
+            "mean_token_length": mean_length,
+            "punctuation_count": punctuation,
+            "numeric_count": numeric
+        }).to_string()
+    }
+
     #[wasm_bindgen]
     pub fn to_yaml(&self) -> Result<String, WasmError> {
-        // Generate YAML manually since serde_yaml might not work well in WASM
-        let mut yaml = String::new();
-        
-        // Add metadata
-        yaml.push_str("_meta:\n");
-        for (name, layer_desc) in self.corpus.get_meta() {
-            yaml.push_str(&format!("  {}:\n", name));
-            yaml.push_str(&format!("    type: {}\n", layer_desc.layer_type));
-            
-            if let Some(ref base) = layer_desc.base {
-                yaml.push_str(&format!("    base: {}\n", base));
-            }
-            
-            if let Some(ref data) = layer_desc.data {
-                match data {
-                    DataType::String => yaml.push_str("    data: string\n"),
-                    DataType::Link => yaml.push_str("    data: link\n"),
-                    DataType::Enum(values) => {
-                        yaml.push_str(&format!("    data: {:?}\n", values));
+        self.yaml_for_docs(&self.corpus.get_docs())
+    }
+
+    // Write the metadata and the given documents as YAML with the core
+    // writer, followed by the corpus metadata on a single line
+    fn yaml_for_docs(&self, ids: &[String]) -> Result<String, WasmError> {
+        let mut docs = Vec::new();
+        for doc_id in ids {
+            if let Ok(mut doc) = self.corpus.get_doc_by_id(doc_id) {
+                for layer in doc.content.values_mut() {
+                    if let Layer::MetaLayer(Some(value)) = layer {
+                        *value = serde_json::from_value(self.value_to_json_value(value))?;
                     }
                 }
+                docs.push((doc_id.clone(), doc));
             }
         }
-        
-        // Add documents
+        let selection = DocSelection { meta: self.corpus.get_meta(), docs };
+        let mut out = Vec::new();
+        teanga::write_yaml(&mut out, &selection).map_err(|e| WasmError {
+            message: format!("Could not write YAML: {}", e)
+        })?;
+        let mut yaml = String::from_utf8(out).map_err(|e| WasmError { message: e.to_string() })?;
+        if !self.corpus_meta.is_empty() {
+            yaml.push_str(&format!("{}: {}\n", CORPUS_META_KEY, self.get_corpus_meta()));
+        }
+        Ok(yaml)
+    }
+
+    /// Replace the contents of this corpus with a corpus in Teanga YAML, as
+    /// written by `to_yaml`. Document keys starting with `_` are kept as
+    /// document metadata so that they survive a round trip, and a single-line
+    /// corpus metadata block is restored.
+    #[wasm_bindgen]
+    pub fn from_yaml(&mut self, yaml: &str) -> Result<(), WasmError> {
+        let mut corpus = SimpleCorpus::new();
+        teanga::read_yaml(yaml.as_bytes(), &mut corpus).map_err(|e| WasmError {
+            message: format!("Could not read YAML: {}", e)
+        })?;
+        let prefix = format!("{}: ", CORPUS_META_KEY);
+        let corpus_meta = match yaml.lines().find_map(|line| line.strip_prefix(prefix.as_str())) {
+            Some(json) => serde_json::from_str(json)?,
+            None => HashMap::new(),
+        };
+        self.layer_order = seed_layer_order(corpus.get_meta(), &yaml_meta_keys(yaml));
+        self.corpus = corpus;
+        self.corpus_meta = corpus_meta;
+        Ok(())
+    }
+
+    /// Set the metadata of the corpus itself, such as its title or license,
+    /// from a JSON object. It is written under `_corpus_meta` by `to_json`
+    /// and `to_yaml`.
+    #[wasm_bindgen]
+    pub fn set_corpus_meta(&mut self, json: &str) -> Result<(), WasmError> {
+        match serde_json::from_str(json)? {
+            Value::Object(map) => {
+                self.corpus_meta = map;
+                Ok(())
+            }
+            _ => Err(WasmError {
+                message: "Corpus metadata must be a JSON object".to_string()
+            }),
+        }
+    }
+
+    /// Get the metadata of the corpus itself as a JSON object
+    #[wasm_bindgen]
+    pub fn get_corpus_meta(&self) -> String {
+        let map = self.corpus_meta.iter()
+            .map(|(k, v)| (k.clone(), self.value_to_json_value(v)))
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::Value::Object(map).to_string()
+    }
+
+    /// Serialize the corpus as Teanga JSON, with the layers of the metadata and
+    /// of each document in the order they were registered.
+    #[wasm_bindgen]
+    pub fn to_json(&self) -> Result<String, WasmError> {
+        self.to_json_with_progress(None)
+    }
+
+    /// Like `to_json` but calls `progress` periodically with
+    /// `{processed, total}`
+    #[wasm_bindgen]
+    pub fn to_json_with_progress(&self, progress: Option<js_sys::Function>) -> Result<String, WasmError> {
+        self.json_for_docs(&self.corpus.get_docs(), progress)
+    }
+
+    // Write the metadata and the given documents as Teanga JSON
+    fn json_for_docs(&self, ids: &[String], progress: Option<js_sys::Function>) -> Result<String, WasmError> {
+        let meta = self.corpus.get_meta();
+        let mut meta_entries = Vec::new();
+        for name in self.ordered_layer_names(meta.keys()) {
+            meta_entries.push((name.as_str(), serde_json::to_string(&meta[name])?));
+        }
+        let mut entries = vec![("_meta", ordered_json_object(meta_entries)?)];
+        if !self.corpus_meta.is_empty() {
+            entries.push((CORPUS_META_KEY, self.get_corpus_meta()));
+        }
+        let progress = Progress::new(progress, ids.len());
+        for (n, doc_id) in ids.iter().enumerate() {
+            progress.report(n);
+            let doc = self.corpus.get_doc_by_id(doc_id)?;
+            entries.push((doc_id.as_str(), self.doc_to_json_string(&doc)?));
+        }
+        progress.report(ids.len());
+        Ok(ordered_json_object(entries)?)
+    }
+
+    /// Serialize only the documents that contain `layer_name` as `"json"`,
+    /// `"jsonl"` or `"yaml"`. If `equals` is given, the layer must also have
+    /// that text, metadata value or, for labelled layers, at least one label.
+    /// JSONL output has one document per line and no metadata.
+    #[wasm_bindgen]
+    pub fn export_filtered(&self, layer_name: &str, equals: Option<String>, format: &str) -> Result<String, WasmError> {
+        let mut ids = Vec::new();
         for doc_id in self.corpus.get_docs() {
-            if let Ok(doc) = self.corpus.get_doc_by_id(&doc_id) {
-                yaml.push_str(&format!("{}:\n", doc_id));
-                for (layer_name, layer) in &doc.content {
-                    match layer {
-                        Layer::Characters(text) => {
-                            let escaped = text.replace("\"", "\\\"").replace("\n", "\\n");
-                            yaml.push_str(&format!("  {}: \"{}\"\n", layer_name, escaped));
-                        }
-                        other => {
-                            let json_val = self.layer_to_json_value(other);
-                            yaml.push_str(&format!("  {}: {}\n", layer_name, 
-                                serde_json::to_string(&json_val).unwrap_or("null".to_string())));
-                        }
-                    }
+            let doc = self.corpus.get_doc_by_id(&doc_id)?;
+            if doc.get(layer_name).map_or(false, |layer| layer_matches(layer, equals.as_deref())) {
+                ids.push(doc_id);
+            }
+        }
+        match format {
+            "json" => self.json_for_docs(&ids, None),
+            "yaml" => self.yaml_for_docs(&ids),
+            "jsonl" => {
+                let mut out = String::new();
+                for doc_id in &ids {
+                    out.push_str(&self.doc_to_json_string(&self.corpus.get_doc_by_id(doc_id)?)?);
+                    out.push('\n');
                 }
+                Ok(out)
             }
+            _ => Err(WasmError {
+                message: format!("Unknown export format {}", format)
+            }),
         }
-        
-        Ok(yaml)
     }
 
     #[wasm_bindgen]
@@ -246,182 +782,3905 @@ impl TeangaWasm {
          serde_json::to_string(&info).map_err(|e| WasmError { message: e.to_string() })
     }
 
-    // Helper methods
-    fn json_value_to_layer(&self, value: serde_json::Value) -> Result<Layer, WasmError> {
-        match value {
-            serde_json::Value::String(text) => Ok(Layer::Characters(text)),
-            serde_json::Value::Array(arr) => {
-                if arr.is_empty() {
-                    return Ok(Layer::L1(vec![]));
-                }
-                
-                match &arr[0] {
-                    serde_json::Value::Number(_) => {
-                        // Array of numbers -> L1
-                        let nums: Result<Vec<u32>, _> = arr.iter()
-                            .map(|v| v.as_u64().map(|n| n as u32).ok_or_else(|| 
-                                WasmError { message: "Expected number".to_string() }))
-                            .collect();
-                        Ok(Layer::L1(nums?))
-                    }
-                    serde_json::Value::Array(inner) => {
-                        // Array of arrays
-                        if inner.len() == 2 {
-                            // Span layer [[start, end], ...]
-                            let spans: Result<Vec<(u32, u32)>, _> = arr.iter()
-                                .map(|v| {
-                                    let inner_arr = v.as_array().ok_or_else(|| 
-                                        WasmError { message: "Expected array".to_string() })?;
-                                    if inner_arr.len() >= 2 {
-                                        let start = inner_arr[0].as_u64().ok_or_else(|| 
-                                            WasmError { message: "Expected number".to_string() })? as u32;
-                                        let end = inner_arr[1].as_u64().ok_or_else(|| 
-                                            WasmError { message: "Expected number".to_string() })? as u32;
-                                        Ok((start, end))
-                                    } else {
-                                        Err(WasmError { message: "Expected array of length >= 2".to_string() })
-                                    }
-                                })
-                                .collect();
-                            Ok(Layer::L2(spans?))
-                        } else if inner.len() == 3 {
-                            // Triple array
-                            let triples: Result<Vec<(u32, u32, u32)>, _> = arr.iter()
-                                .map(|v| {
-                                    let inner_arr = v.as_array().ok_or_else(|| 
-                                        WasmError { message: "Expected array".to_string() })?;
-                                    if inner_arr.len() >= 3 {
-                                        let a = inner_arr[0].as_u64().ok_or_else(|| 
-                                            WasmError { message: "Expected number".to_string() })? as u32;
-                                        let b = inner_arr[1].as_u64().ok_or_else(|| 
-                                            WasmError { message: "Expected number".to_string() })? as u32;
-                                        let c = inner_arr[2].as_u64().ok_or_else(|| 
-                                            WasmError { message: "Expected number".to_string() })? as u32;
-                                        Ok((a, b, c))
-                                    } else {
-                                        Err(WasmError { message: "Expected array of length >= 3".to_string() })
-                                    }
-                                })
-                                .collect();
-                            Ok(Layer::L3(triples?))
-                        } else {
-                            Err(WasmError { message: "Unsupported array structure".to_string() })
-                        }
-                    }
-                    serde_json::Value::String(_) => {
-                        // Array of strings -> LS
-                        let strings: Result<Vec<String>, _> = arr.iter()
-                            .map(|v| v.as_str().map(|s| s.to_string()).ok_or_else(|| 
-                                WasmError { message: "Expected string".to_string() }))
-                            .collect();
-                        Ok(Layer::LS(strings?))
+    /// Get summary statistics of the corpus as a JSON object
+    /// `{documents, characters, layers}`, where each layer has its
+    /// `documents`, `annotations`, `mean_per_doc` and, for string and enum
+    /// layers, the frequency of each value in `values`
+    #[wasm_bindgen]
+    pub fn stats(&self) -> Result<String, WasmError> {
+        Ok(serde_json::to_string(&self.corpus.stats()?)?)
+    }
+
+    /// Check that every link in a link layer points at an existing entry of
+    /// its target layer. Returns a JSON array of the dangling references as
+    /// `{doc_id, layer, entry_index, bad_target}` objects.
+    #[wasm_bindgen]
+    pub fn check_links(&self) -> String {
+        let meta = self.corpus.get_meta();
+        let mut link_layers: Vec<(&String, &str)> = meta.iter()
+            .filter(|(_, desc)| desc.data == Some(DataType::Link))
+            .map(|(name, desc)| (name, desc.target.as_deref().unwrap_or(name)))
+            .collect();
+        link_layers.sort();
+        let mut dangling = Vec::new();
+        for doc_id in self.corpus.get_docs() {
+            let doc = match self.corpus.get_doc_by_id(&doc_id) {
+                Ok(doc) => doc,
+                Err(_) => continue,
+            };
+            for &(name, target) in &link_layers {
+                let data = match doc.data(name, meta) {
+                    Some(data) => data,
+                    None => continue,
+                };
+                let target_len = doc.get(target).map_or(0, |l| l.len());
+                for (entry_index, value) in data.into_iter().enumerate() {
+                    let link = match value {
+                        TeangaData::Link(l) | TeangaData::TypedLink(l, _) => l,
+                        _ => continue,
+                    };
+                    if link as usize >= target_len {
+                        dangling.push(serde_json::json!({
+                            "doc_id": doc_id,
+                            "layer": name,
+                            "entry_index": entry_index,
+                            "bad_target": link
+                        }));
                     }
-                    _ => Err(WasmError { message: "Unsupported array content".to_string() }),
                 }
             }
-            _ => Err(WasmError { message: "Unsupported value type".to_string() }),
         }
+        serde_json::to_string(&dangling).unwrap_or_else(|_| "[]".to_string())
     }
 
-    fn layer_to_json_value(&self, layer: &Layer) -> serde_json::Value {
-        match layer {
-            Layer::Characters(text) => serde_json::Value::String(text.clone()),
-            Layer::L1(data) => serde_json::Value::Array(
-                data.iter().map(|&n| serde_json::Value::Number(n.into())).collect()
-            ),
-            Layer::L2(data) => serde_json::Value::Array(
-                data.iter().map(|&(a, b)| serde_json::Value::Array(vec![
-                    serde_json::Value::Number(a.into()),
-                    serde_json::Value::Number(b.into())
-                ])).collect()
-            ),
-            Layer::L3(data) => serde_json::Value::Array(
-                data.iter().map(|&(a, b, c)| serde_json::Value::Array(vec![
-                    serde_json::Value::Number(a.into()),
-                    serde_json::Value::Number(b.into()),
-                    serde_json::Value::Number(c.into())
-                ])).collect()
-            ),
-            Layer::LS(data) => serde_json::Value::Array(
-                data.iter().map(|s| serde_json::Value::String(s.clone())).collect()
-            ),
-            Layer::L1S(data) => serde_json::Value::Array(
-                data.iter().map(|(n, s)| serde_json::Value::Array(vec![
-                    serde_json::Value::Number((*n).into()),
-                    serde_json::Value::String(s.clone())
-                ])).collect()
-            ),
-            Layer::L2S(data) => serde_json::Value::Array(
-                data.iter().map(|(a, b, s)| serde_json::Value::Array(vec![
-                    serde_json::Value::Number((*a).into()),
-                    serde_json::Value::Number((*b).into()),
-                    serde_json::Value::String(s.clone())
-                ])).collect()
-            ),
-            Layer::L3S(data) => serde_json::Value::Array(
-                data.iter().map(|(a, b, c, s)| serde_json::Value::Array(vec![
-                    serde_json::Value::Number((*a).into()),
-                    serde_json::Value::Number((*b).into()),
-                    serde_json::Value::Number((*c).into()),
-                    serde_json::Value::String(s.clone())
-                ])).collect()
-            ),
-            Layer::MetaLayer(data) => {
-                // Convert Value to serde_json::Value
-                match data {
-                    Some(val) => self.value_to_json_value(val),
-                    None => serde_json::Value::Null,
+    /// Report documents with identical content without removing any. Returns
+    /// a JSON object `{total, unique, duplicate_groups}` where each group lists
+    /// the IDs of documents sharing the same content, in corpus order.
+    #[wasm_bindgen]
+    pub fn duplicate_report(&self) -> String {
+        self.duplicate_report_with_progress(None)
+    }
+
+    /// Like `duplicate_report` but calls `progress` periodically with
+    /// `{processed, total}`
+    #[wasm_bindgen]
+    pub fn duplicate_report_with_progress(&self, progress: Option<js_sys::Function>) -> String {
+        let docs = self.corpus.get_docs();
+        let progress = Progress::new(progress, docs.len());
+        let mut groups: Vec<Vec<String>> = Vec::new();
+        let mut group_of: HashMap<String, usize> = HashMap::new();
+        for (n, doc_id) in docs.iter().enumerate() {
+            progress.report(n);
+            let doc = match self.corpus.get_doc_by_id(doc_id) {
+                Ok(doc) => doc,
+                Err(_) => continue,
+            };
+            let content: std::collections::BTreeMap<&String, &Layer> = doc.content.iter().collect();
+            let key = serde_json::to_string(&content).unwrap_or_default();
+            match group_of.get(&key) {
+                Some(&n) => groups[n].push(doc_id.clone()),
+                None => {
+                    group_of.insert(key, groups.len());
+                    groups.push(vec![doc_id.clone()]);
                 }
             }
         }
+        progress.report(docs.len());
+        let unique = groups.len();
+        let duplicate_groups: Vec<Vec<String>> = groups.into_iter().filter(|g| g.len() > 1).collect();
+        serde_json::json!({
+            "total": docs.len(),
+            "unique": unique,
+            "duplicate_groups": duplicate_groups
+        }).to_string()
     }
 
-    fn value_to_json_value(&self, value: &Value) -> serde_json::Value {
-        match value {
-            Value::Bool(b) => serde_json::Value::Bool(*b),
-            Value::Int(i) => serde_json::Value::Number((*i).into()),
-            Value::Float(f) => serde_json::Number::from_f64(*f)
-                .map(serde_json::Value::Number)
-                .unwrap_or(serde_json::Value::Null),
-            Value::String(s) => serde_json::Value::String(s.clone()),
-            Value::Array(arr) => serde_json::Value::Array(
-                arr.iter().map(|v| self.value_to_json_value(v)).collect()
-            ),
-            Value::Object(obj) => serde_json::Value::Object(
-                obj.iter().map(|(k, v)| (k.clone(), self.value_to_json_value(v))).collect()
-            ),
+    /// Remove near-duplicate documents, comparing the lowercased words of a
+    /// characters layer as shingles of `SHINGLE_SIZE` words. Documents whose
+    /// estimated Jaccard similarity to an earlier kept document is at least
+    /// `threshold` are removed; the first of each group in corpus order is
+    /// kept. Returns the removed IDs as a JSON array.
+    #[wasm_bindgen]
+    pub fn dedupe_fuzzy(&mut self, char_layer: Option<String>, threshold: f64) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(WasmError {
+                message: format!("Threshold {} is not between 0 and 1", threshold)
+            });
+        }
+        let mut kept: Vec<Vec<u64>> = Vec::new();
+        let mut removed = Vec::new();
+        for doc_id in self.corpus.get_docs() {
+            let doc = self.corpus.get_doc_by_id(&doc_id)?;
+            let text = match doc.get(char_layer).and_then(|l| l.characters()) {
+                Some(text) => text,
+                None => continue,
+            };
+            let signature = minhash_signature(text);
+            let similar = kept.iter().any(|other| {
+                let same = signature.iter().zip(other).filter(|(a, b)| a == b).count();
+                same as f64 / MINHASH_PERMUTATIONS as f64 >= threshold
+            });
+            if similar {
+                self.corpus.remove_doc(&doc_id)?;
+                removed.push(doc_id);
+            } else {
+                kept.push(signature);
+            }
         }
+        Ok(serde_json::to_string(&removed)?)
     }
-}
 
-// Simple tokenization function
-fn simple_tokenize(text: &str) -> Vec<(u32, u32)> {
-    let mut tokens = Vec::new();
-    let mut start = 0;
-    let mut in_word = false;
-    
-    for (i, ch) in text.char_indices() {
-        if ch.is_alphabetic() || ch.is_numeric() {
-            if !in_word {
-                start = i;
-                in_word = true;
+    /// Reorder the documents with a seeded shuffle. The same seed always
+    /// produces the same order.
+    #[wasm_bindgen]
+    pub fn shuffle_docs(&mut self, seed: u64) -> Result<(), WasmError> {
+        let mut order = self.corpus.get_docs();
+        let mut state = seed;
+        for i in (1..order.len()).rev() {
+            let j = (splitmix64(&mut state) % (i as u64 + 1)) as usize;
+            order.swap(i, j);
+        }
+        self.corpus.set_order(order)?;
+        Ok(())
+    }
+
+    /// Get the sorted names of the layers declared with the given type
+    /// ("characters", "span", "seq", "div" or "element")
+    #[wasm_bindgen]
+    pub fn layers_of_type(&self, layer_type: &str) -> String {
+        let mut names: Vec<&String> = self.corpus.get_meta().iter()
+            .filter(|(_, desc)| desc.layer_type.to_string() == layer_type)
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        serde_json::to_string(&names).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Get a JSON array describing each declared layer: its name, type, the
+    /// fraction of documents containing it, its total number of entries and
+    /// its base layer
+    #[wasm_bindgen]
+    pub fn layer_summary(&self) -> String {
+        serde_json::to_string(&self.layer_summary_rows()).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Get the same information as `layer_summary` as a CSV table with the
+    /// columns `name,type,doc_coverage,total_entries,base`
+    #[wasm_bindgen]
+    pub fn layer_summary_csv(&self) -> String {
+        let mut csv = String::from("name,type,doc_coverage,total_entries,base\n");
+        for row in self.layer_summary_rows() {
+            csv.push_str(&format!("{},{},{},{},{}\n",
+                csv_field(&row.name),
+                csv_field(&row.layer_type),
+                row.doc_coverage,
+                row.total_entries,
+                csv_field(row.base.as_deref().unwrap_or(""))));
+        }
+        csv
+    }
+
+    /// Get the sorted names of all layers that appear in at least one document
+    #[wasm_bindgen]
+    pub fn used_layer_names(&self) -> String {
+        let mut names = std::collections::BTreeSet::new();
+        for doc_id in self.corpus.get_docs() {
+            if let Ok(doc) = self.corpus.get_doc_by_id(&doc_id) {
+                names.extend(doc.content.into_keys());
             }
-        } else {
-            if in_word {
-                tokens.push((start as u32, i as u32));
-                in_word = false;
+        }
+        serde_json::to_string(&names).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Generate a JSON Schema describing a document of this corpus from the
+    /// current layer metadata
+    #[wasm_bindgen]
+    pub fn json_schema(&self) -> String {
+        let meta = self.corpus.get_meta();
+        let mut properties = serde_json::Map::new();
+        let mut names: Vec<&String> = meta.keys().collect();
+        names.sort();
+        for name in names {
+            properties.insert(name.clone(), layer_json_schema(&meta[name]));
+        }
+        serde_json::json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "properties": properties,
+            "patternProperties": { "^_": {} },
+            "additionalProperties": false
+        }).to_string()
+    }
+
+    /// Serialize the corpus with `to_json`, parse it back into a fresh corpus
+    /// and check that the metadata, document hashes and contents are
+    /// unchanged. Returns an error naming the document and layer of the first
+    /// discrepancy found.
+    #[wasm_bindgen]
+    pub fn verify_roundtrip(&self) -> Result<bool, WasmError> {
+        let mut parsed: HashMap<String, serde_json::Value> = serde_json::from_str(&self.to_json()?)?;
+        let mut other = TeangaWasm::new();
+        if let Some(meta) = parsed.remove("_meta") {
+            let meta: HashMap<String, LayerDesc> = serde_json::from_value(meta).map_err(|e| WasmError {
+                message: format!("Metadata cannot be read after round-trip: {}", e)
+            })?;
+            other.corpus.set_meta(meta)?;
+        }
+        self.check_same_meta(&other.corpus)?;
+
+        for doc_id in self.corpus.get_docs() {
+            let doc_json = parsed.remove(&doc_id).ok_or_else(|| WasmError {
+                message: format!("Document {} missing after round-trip", doc_id)
+            })?;
+            let doc_data: HashMap<String, serde_json::Value> = serde_json::from_value(doc_json)?;
+            let mut layers = HashMap::new();
+            for (name, value) in doc_data {
+                let layer = other.json_value_to_layer(&name, value).map_err(|e| WasmError {
+                    message: format!("Layer {} of document {} cannot be read after round-trip: {}",
+                        name, doc_id, e.message)
+                })?;
+                layers.insert(name, layer);
             }
-            if !ch.is_whitespace() {
-                // Add punctuation as separate token
-                tokens.push((i as u32, (i + ch.len_utf8()) as u32));
+            let new_id = other.corpus.add_doc(layers).map_err(|e| WasmError {
+                message: format!("Document {} cannot be added after round-trip: {}", doc_id, e)
+            })?;
+            if new_id != doc_id {
+                return Err(WasmError {
+                    message: format!("Document {} has hash {} after round-trip", doc_id, new_id)
+                });
             }
+            let doc = self.corpus.get_doc_by_id(&doc_id)?;
+            let new_doc = other.corpus.get_doc_by_id(&new_id)?;
+            check_same_doc(&doc_id, &doc, &new_doc)?;
         }
+
+        Ok(true)
     }
-    
-    // Handle final token
-    if in_word {
-        tokens.push((start as u32, text.len() as u32));
+
+    /// Serialize the corpus as `"json"`, `"yaml"` or `"tcf"`, read it back
+    /// into a temporary corpus and check that the metadata and every document
+    /// are unchanged. TCF only stores the layers described in the metadata, so
+    /// for `"tcf"` undescribed metadata layers such as `_metadata` are not
+    /// compared. Returns an error describing the first discrepancy.
+    #[wasm_bindgen]
+    pub fn assert_exportable(&self, format: &str) -> Result<(), WasmError> {
+        let settings = || teanga::SerializationSettings::new().ignore_id_errors();
+        let mut other = SimpleCorpus::new();
+        match format {
+            "json" => teanga::read_json_with_config(self.to_json()?.as_bytes(), &mut other, settings())
+                .map_err(|e| WasmError { message: format!("Could not read exported JSON: {}", e) })?,
+            "yaml" => teanga::read_yaml_with_config(self.to_yaml()?.as_bytes(), &mut other, settings())
+                .map_err(|e| WasmError { message: format!("Could not read exported YAML: {}", e) })?,
+            "tcf" => other = SimpleCorpus::from_tcf(&self.corpus.to_tcf()?)?,
+            _ => return Err(WasmError {
+                message: format!("Unknown export format {}", format)
+            }),
+        }
+        self.check_same_meta(&other)?;
+        let meta = self.corpus.get_meta();
+        for doc_id in self.corpus.get_docs() {
+            let new_doc = other.get_doc_by_id(&doc_id).map_err(|_| WasmError {
+                message: format!("Document {} missing after round-trip", doc_id)
+            })?;
+            let mut doc = self.corpus.get_doc_by_id(&doc_id)?;
+            if format == "tcf" {
+                doc.content.retain(|name, _| meta.contains_key(name));
+            }
+            check_same_doc(&doc_id, &doc, &new_doc)?;
+        }
+        Ok(())
     }
-    
-    tokens
-}
\ No newline at end of file
+
+    /// Remove the spans of a layer whose length (`end - start`) lies outside
+    /// `[min_len, max_len]`. Seq layers based on this layer are filtered in
+    /// step so they stay aligned. Returns the number of spans removed.
+    #[wasm_bindgen]
+    pub fn filter_spans(
+        &mut self,
+        doc_id: &str,
+        layer_name: &str,
+        min_len: u32,
+        max_len: u32,
+    ) -> Result<usize, WasmError> {
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let layer = doc.get(layer_name).ok_or_else(|| WasmError {
+            message: format!("Layer {} not found in document {}", layer_name, doc_id)
+        })?;
+        let in_range = |start: u32, end: u32| {
+            let len = end.saturating_sub(start);
+            len >= min_len && len <= max_len
+        };
+        let keep: Vec<bool> = match layer {
+            Layer::L2(data) => data.iter().map(|&(s, e)| in_range(s, e)).collect(),
+            Layer::L2S(data) => data.iter().map(|(s, e, _)| in_range(*s, *e)).collect(),
+            Layer::L3(data) => data.iter().map(|&(s, e, _)| in_range(s, e)).collect(),
+            Layer::L3S(data) => data.iter().map(|(s, e, _, _)| in_range(*s, *e)).collect(),
+            _ => return Err(WasmError {
+                message: format!("Layer {} is not a span layer", layer_name)
+            }),
+        };
+        let removed = keep.iter().filter(|k| !**k).count();
+        if removed == 0 {
+            return Ok(0);
+        }
+
+        let mut updates = vec![(layer_name.to_string(), retain_entries(layer, &keep))];
+        for (name, desc) in self.corpus.get_meta() {
+            if desc.layer_type == LayerType::seq && desc.base.as_deref() == Some(layer_name) {
+                if let Some(seq_layer) = doc.get(name) {
+                    if seq_layer.len() == keep.len() {
+                        updates.push((name.clone(), retain_entries(seq_layer, &keep)));
+                    }
+                }
+            }
+        }
+        self.corpus.update_doc(doc_id, updates)?;
+        Ok(removed)
+    }
+
+    /// Store one label per token as a seq layer over `token_layer`,
+    /// registering the label layer if it is not yet described
+    #[wasm_bindgen]
+    pub fn add_seq_labels(
+        &mut self,
+        doc_id: &str,
+        token_layer: &str,
+        label_layer: &str,
+        labels_json: &str,
+    ) -> Result<(), WasmError> {
+        let labels: Vec<String> = serde_json::from_str(labels_json)?;
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let token_count = doc.get(token_layer).ok_or_else(|| WasmError {
+            message: format!("Layer {} not found in document {}", token_layer, doc_id)
+        })?.len();
+        if labels.len() != token_count {
+            return Err(WasmError {
+                message: format!("Got {} labels but layer {} has {} tokens",
+                    labels.len(), token_layer, token_count)
+            });
+        }
+        match self.corpus.get_meta().get(label_layer) {
+            Some(desc) if desc.layer_type != LayerType::seq
+                || desc.base.as_deref() != Some(token_layer) => return Err(WasmError {
+                message: format!("Layer {} is not a seq layer over {}", label_layer, token_layer)
+            }),
+            Some(_) => {}
+            None => self.add_layer_meta(label_layer, "seq", Some(token_layer.to_string()),
+                Some("string".to_string()))?,
+        }
+        self.corpus.update_doc(doc_id, vec![(label_layer.to_string(), Layer::LS(labels))])?;
+        Ok(())
+    }
+
+    /// Combine a span layer with a parallel seq layer of labels into a JSON
+    /// array of `[start, end, label]` rows
+    #[wasm_bindgen]
+    pub fn join_spans_labels(&self, doc_id: &str, span_layer: &str, label_layer: &str) -> Result<String, WasmError> {
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let layer = |name: &str| doc.get(name).ok_or_else(|| WasmError {
+            message: format!("Layer {} not found in document {}", name, doc_id)
+        });
+        let bounds = span_bounds(layer(span_layer)?).ok_or_else(|| WasmError {
+            message: format!("Layer {} does not contain spans", span_layer)
+        })?;
+        let labels = match layer(label_layer)? {
+            Layer::LS(labels) => labels,
+            _ => return Err(WasmError {
+                message: format!("Layer {} does not contain labels", label_layer)
+            }),
+        };
+        if labels.len() != bounds.len() {
+            return Err(WasmError {
+                message: format!("Got {} labels but layer {} has {} spans",
+                    labels.len(), span_layer, bounds.len())
+            });
+        }
+        let rows: Vec<(u32, u32, &String)> = bounds.into_iter().zip(labels)
+            .map(|((start, end), label)| (start, end, label))
+            .collect();
+        Ok(serde_json::to_string(&rows)?)
+    }
+
+    /// Append entries to a layer of a document. The entries must have the same
+    /// shape as the existing layer, or the shape its description expects if
+    /// the document has no entries yet; span layers are kept sorted by start
+    /// offset.
+    #[wasm_bindgen]
+    pub fn append_to_layer(&mut self, doc_id: &str, layer_name: &str, entries_json: &str) -> Result<(), WasmError> {
+        let layer_desc = self.corpus.get_meta().get(layer_name).ok_or_else(|| WasmError {
+            message: format!("Layer {} is not described in meta", layer_name)
+        })?;
+        let is_span = layer_desc.layer_type == LayerType::span;
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let existing = doc.get(layer_name).filter(|layer| layer.len() > 0);
+        let like = existing.cloned().or_else(|| empty_layer(layer_desc));
+        let shape_error = |e: String| WasmError {
+            message: format!("Entries do not match the shape of layer {}: {}", layer_name, e)
+        };
+        let entries = parse_layer_like(like.as_ref(), serde_json::from_str(entries_json)?)
+            .map_err(|e| shape_error(e.to_string()))?;
+        if entries.len() == 0 {
+            return Ok(());
+        }
+        let mut layer = match existing {
+            Some(existing) => {
+                let mut layer = existing.clone();
+                extend_layer(&mut layer, entries)
+                    .map_err(|_| shape_error("the layer cannot be extended".to_string()))?;
+                layer
+            }
+            None => entries,
+        };
+        if is_span {
+            match layer {
+                Layer::L2(ref mut data) => data.sort_by_key(|&(s, e)| (s, e)),
+                Layer::L2S(ref mut data) => data.sort_by_key(|(s, e, _)| (*s, *e)),
+                Layer::L3(ref mut data) => data.sort_by_key(|&(s, e, _)| (s, e)),
+                Layer::L3S(ref mut data) => data.sort_by_key(|(s, e, _, _)| (*s, *e)),
+                _ => {}
+            }
+        }
+        self.corpus.update_doc(doc_id, vec![(layer_name.to_string(), layer)])?;
+        Ok(())
+    }
+
+    /// Put a document's layers into a canonical state: span layers are sorted
+    /// by start offset with identical entries removed, and empty layers use
+    /// the representation expected for their type. Seq layers over a span
+    /// layer are reordered with it; span layers that other non-seq layers
+    /// refer into are left untouched.
+    #[wasm_bindgen]
+    pub fn compact_doc(&mut self, doc_id: &str) -> Result<(), WasmError> {
+        let meta = self.corpus.get_meta();
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let mut updates = Vec::new();
+        for (name, layer) in &doc.content {
+            let desc = match meta.get(name) {
+                Some(desc) => desc,
+                None => continue,
+            };
+            if layer.len() == 0 {
+                if let Some(empty) = empty_layer(desc) {
+                    if &empty != layer {
+                        updates.push((name.clone(), empty));
+                    }
+                }
+                continue;
+            }
+            let bounds = match (desc.layer_type == LayerType::span, span_bounds(layer)) {
+                (true, Some(bounds)) => bounds,
+                _ => continue,
+            };
+            let dependents: Vec<(&String, &LayerDesc)> = meta.iter()
+                .filter(|(_, d)| d.base.as_deref() == Some(name.as_str()))
+                .collect();
+            if dependents.iter().any(|(_, d)| d.layer_type != LayerType::seq) {
+                continue;
+            }
+            let seq_layers: Vec<(&String, &Layer)> = dependents.iter()
+                .filter_map(|(n, _)| doc.get(n).map(|l| (*n, l)))
+                .filter(|(_, l)| l.len() == layer.len())
+                .collect();
+            let mut order: Vec<usize> = (0..layer.len()).collect();
+            order.sort_by_key(|&i| bounds[i]);
+            let mut kept: Vec<usize> = Vec::with_capacity(order.len());
+            for i in order {
+                let duplicate = kept.iter().rev().take_while(|&&k| bounds[k] == bounds[i])
+                    .any(|&k| entries_equal(layer, k, i)
+                        && seq_layers.iter().all(|(_, l)| entries_equal(l, k, i)));
+                if !duplicate {
+                    kept.push(i);
+                }
+            }
+            if kept.iter().copied().eq(0..layer.len()) {
+                continue;
+            }
+            updates.push((name.clone(), select_entries(layer, &kept)));
+            for (seq_name, seq_layer) in seq_layers {
+                updates.push((seq_name.clone(), select_entries(seq_layer, &kept)));
+            }
+        }
+        if !updates.is_empty() {
+            self.corpus.update_doc(doc_id, updates)?;
+        }
+        Ok(())
+    }
+
+    /// Compact every document in the corpus, see `compact_doc`.
+    #[wasm_bindgen]
+    pub fn compact(&mut self) -> Result<(), WasmError> {
+        self.compact_with_progress(None)
+    }
+
+    /// Like `compact` but calls `progress` periodically with
+    /// `{processed, total}`
+    #[wasm_bindgen]
+    pub fn compact_with_progress(&mut self, progress: Option<js_sys::Function>) -> Result<(), WasmError> {
+        let ids = self.corpus.get_docs();
+        let progress = Progress::new(progress, ids.len());
+        for (n, doc_id) in ids.iter().enumerate() {
+            progress.report(n);
+            self.compact_doc(doc_id)?;
+        }
+        progress.report(ids.len());
+        Ok(())
+    }
+
+    /// Shrink the spans of a span layer over `char_layer` so that they do not
+    /// start or end with whitespace. Spans that only contain whitespace are
+    /// removed, along with the matching entries of seq layers over the span
+    /// layer. If no `char_layer` is given the default characters layer is
+    /// used. Returns the number of spans trimmed or removed.
+    #[wasm_bindgen]
+    pub fn trim_spans(&mut self, doc_id: &str, layer_name: &str, char_layer: Option<String>) -> Result<usize, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let meta = self.corpus.get_meta();
+        match meta.get(layer_name) {
+            Some(desc) if desc.layer_type == LayerType::span
+                && desc.base.as_deref() == Some(char_layer) => {}
+            _ => return Err(WasmError {
+                message: format!("Layer {} is not a span layer over {}", layer_name, char_layer)
+            }),
+        }
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let text = doc.get(char_layer).and_then(|l| l.characters()).ok_or_else(|| WasmError {
+            message: format!("Document {} has no characters layer {}", doc_id, char_layer)
+        })?;
+        let layer = match doc.get(layer_name) {
+            Some(layer) => layer,
+            None => return Ok(0),
+        };
+        let bounds = span_bounds(layer).ok_or_else(|| WasmError {
+            message: format!("Layer {} does not contain spans", layer_name)
+        })?;
+        let mut trimmed = Vec::with_capacity(bounds.len());
+        let mut keep = Vec::with_capacity(bounds.len());
+        let mut modified = 0;
+        for &(start, end) in &bounds {
+            let span = text.get(start as usize..end as usize).ok_or_else(|| WasmError {
+                message: format!("Span {}..{} is not a valid character range in {}", start, end, char_layer)
+            })?;
+            let new_start = end - span.trim_start().len() as u32;
+            let new_end = start + span.trim_end().len() as u32;
+            keep.push(new_start < new_end);
+            if new_start >= new_end || (new_start, new_end) != (start, end) {
+                modified += 1;
+            }
+            trimmed.push((new_start, new_end.max(new_start)));
+        }
+        if modified == 0 {
+            return Ok(0);
+        }
+        let updates = self.span_bound_updates(&doc, layer_name, layer, &trimmed, &keep)?;
+        self.corpus.update_doc(doc_id, updates)?;
+        Ok(modified)
+    }
+
+    /// Swap the start and end of reversed spans of a layer and sort it by
+    /// start offset, reordering the seq layers over it to match. Returns the
+    /// number of entries swapped or moved.
+    #[wasm_bindgen]
+    pub fn repair_spans(&mut self, doc_id: &str, layer_name: &str) -> Result<usize, WasmError> {
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let layer = doc.get(layer_name).ok_or_else(|| WasmError {
+            message: format!("Layer {} not found in document {}", layer_name, doc_id)
+        })?;
+        let bounds = span_bounds(layer).ok_or_else(|| WasmError {
+            message: format!("Layer {} does not contain spans", layer_name)
+        })?;
+        let fixed: Vec<(u32, u32)> = bounds.iter()
+            .map(|&(start, end)| (start.min(end), start.max(end)))
+            .collect();
+        let mut order: Vec<usize> = (0..fixed.len()).collect();
+        order.sort_by_key(|&i| fixed[i]);
+        let changed = order.iter().enumerate()
+            .filter(|&(pos, &i)| pos != i || fixed[i] != bounds[i])
+            .count();
+        if changed == 0 {
+            return Ok(0);
+        }
+        let meta = self.corpus.get_meta();
+        let moved = order.iter().enumerate().any(|(pos, &i)| pos != i);
+        if moved {
+            if let Some((name, _)) = meta.iter().find(|(_, d)| d.base.as_deref() == Some(layer_name)
+                && d.layer_type != LayerType::seq) {
+                return Err(WasmError {
+                    message: format!("Cannot reorder spans of {} as layer {} refers to them",
+                        layer_name, name)
+                });
+            }
+        }
+        let keep = vec![true; fixed.len()];
+        let mut updates = Vec::new();
+        for (name, layer) in self.span_bound_updates(&doc, layer_name, layer, &fixed, &keep)? {
+            updates.push((name, select_entries(&layer, &order)));
+        }
+        if moved {
+            for (name, desc) in meta {
+                if desc.layer_type == LayerType::seq && desc.base.as_deref() == Some(layer_name) {
+                    if let Some(seq_layer) = doc.get(name).filter(|l| l.len() == order.len()) {
+                        updates.push((name.clone(), select_entries(seq_layer, &order)));
+                    }
+                }
+            }
+        }
+        self.corpus.update_doc(doc_id, updates)?;
+        Ok(changed)
+    }
+
+    /// Shift the spans of every span layer over `char_layer` after the text of
+    /// that layer has been edited. `diff_json` is a list of edits, applied in
+    /// order, of the form `{"position": p, "deleted": d, "inserted": i}`:
+    /// `d` bytes were removed at byte offset `p` and `i` bytes inserted in
+    /// their place. Spans lying inside deleted text are removed, along with
+    /// the matching entries of seq layers over them. If no `char_layer` is
+    /// given the default characters layer is used.
+    #[wasm_bindgen]
+    pub fn reindex_spans(&mut self, doc_id: &str, char_layer: Option<String>, diff_json: &str) -> Result<(), WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let edits: Vec<TextEdit> = serde_json::from_str(diff_json)?;
+        let meta = self.corpus.get_meta();
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let mut updates = Vec::new();
+        for (name, desc) in meta {
+            if desc.layer_type != LayerType::span || desc.base.as_deref() != Some(char_layer) {
+                continue;
+            }
+            let layer = match doc.get(name) {
+                Some(layer) => layer,
+                None => continue,
+            };
+            let bounds = span_bounds(layer).ok_or_else(|| WasmError {
+                message: format!("Layer {} does not contain spans", name)
+            })?;
+            let shifted = bounds.iter()
+                .map(|&(start, end)| edits.iter().try_fold((start, end), |(s, e), edit| edit.shift(s, e)))
+                .collect::<Option<Vec<(u32, u32)>>>()
+                .ok_or_else(|| WasmError {
+                    message: format!("Edits move spans of layer {} past the largest offset", name)
+                })?;
+            if shifted == bounds {
+                continue;
+            }
+            let keep: Vec<bool> = shifted.iter().zip(&bounds)
+                .map(|(&(s, e), &(start, end))| s < e || start == end)
+                .collect();
+            updates.extend(self.span_bound_updates(&doc, name, layer, &shifted, &keep)?);
+        }
+        if !updates.is_empty() {
+            self.corpus.update_doc(doc_id, updates)?;
+        }
+        Ok(())
+    }
+
+    /// Compare the allowed values of an enum layer with a JSON array of other
+    /// values, as `{only_here, only_there, shared}` in declaration order.
+    #[wasm_bindgen]
+    pub fn enum_diff(&self, layer_name: &str, other_values_json: &str) -> Result<String, WasmError> {
+        let other: Vec<String> = serde_json::from_str(other_values_json)?;
+        let here = match self.corpus.get_meta().get(layer_name) {
+            Some(LayerDesc { data: Some(DataType::Enum(values)), .. }) => values,
+            Some(_) => return Err(WasmError {
+                message: format!("Layer {} is not an enum layer", layer_name)
+            }),
+            None => return Err(WasmError {
+                message: format!("Layer {} is not described in meta", layer_name)
+            }),
+        };
+        let only_here: Vec<&String> = here.iter().filter(|v| !other.contains(v)).collect();
+        let only_there: Vec<&String> = other.iter().filter(|v| !here.contains(v)).collect();
+        let shared: Vec<&String> = here.iter().filter(|v| other.contains(v)).collect();
+        Ok(serde_json::to_string(&serde_json::json!({
+            "only_here": only_here,
+            "only_there": only_there,
+            "shared": shared,
+        }))?)
+    }
+
+    /// Rewrite the labels of a layer across the corpus using a JSON object
+    /// mapping old labels to new ones. The allowed values of an enum layer are
+    /// renamed as well. Returns the number of entries changed.
+    #[wasm_bindgen]
+    pub fn relabel(&mut self, layer_name: &str, mapping_json: &str) -> Result<usize, WasmError> {
+        let mapping: HashMap<String, String> = serde_json::from_str(mapping_json)?;
+        let mut meta = self.corpus.clone_meta();
+        let layer_desc = meta.get_mut(layer_name).ok_or_else(|| WasmError {
+            message: format!("Layer {} is not described in meta", layer_name)
+        })?;
+        if let Some(DataType::Enum(ref mut values)) = layer_desc.data {
+            let mut renamed: Vec<String> = Vec::new();
+            for value in values.iter() {
+                let value = mapping.get(value).unwrap_or(value);
+                if !renamed.contains(value) {
+                    renamed.push(value.clone());
+                }
+            }
+            *values = renamed;
+        }
+        self.corpus.set_meta(meta)?;
+
+        let mut changed = 0;
+        for doc_id in self.corpus.get_docs() {
+            let doc = self.corpus.get_doc_by_id(&doc_id)?;
+            let mut layer = match doc.get(layer_name) {
+                Some(layer) => layer.clone(),
+                None => continue,
+            };
+            let mut count = 0;
+            let mut rename = |label: &mut String| {
+                if let Some(new_label) = mapping.get(label.as_str()) {
+                    if new_label != label {
+                        *label = new_label.clone();
+                        count += 1;
+                    }
+                }
+            };
+            match layer {
+                Layer::LS(ref mut data) => data.iter_mut().for_each(|s| rename(s)),
+                Layer::L1S(ref mut data) => data.iter_mut().for_each(|(_, s)| rename(s)),
+                Layer::L2S(ref mut data) => data.iter_mut().for_each(|(_, _, s)| rename(s)),
+                Layer::L3S(ref mut data) => data.iter_mut().for_each(|(_, _, _, s)| rename(s)),
+                _ => {}
+            }
+            if count > 0 {
+                self.corpus.update_doc(&doc_id, vec![(layer_name.to_string(), layer)])?;
+                changed += count;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Check whether two documents have the same layers with the same content
+    #[wasm_bindgen]
+    pub fn docs_equal(&self, id_a: &str, id_b: &str) -> Result<bool, WasmError> {
+        let doc_a = self.corpus.get_doc_by_id(id_a)?;
+        let doc_b = self.corpus.get_doc_by_id(id_b)?;
+        Ok(doc_a.content == doc_b.content)
+    }
+
+    /// Check whether a layer of one document is identical to a layer of another
+    #[wasm_bindgen]
+    pub fn layer_equal(
+        &self,
+        id_a: &str,
+        layer_a: &str,
+        id_b: &str,
+        layer_b: &str,
+    ) -> Result<bool, WasmError> {
+        let doc_a = self.corpus.get_doc_by_id(id_a)?;
+        let doc_b = self.corpus.get_doc_by_id(id_b)?;
+        let a = doc_a.get(layer_a).ok_or_else(|| WasmError {
+            message: format!("Layer {} not found in document {}", layer_a, id_a)
+        })?;
+        let b = doc_b.get(layer_b).ok_or_else(|| WasmError {
+            message: format!("Layer {} not found in document {}", layer_b, id_b)
+        })?;
+        Ok(a == b)
+    }
+
+    /// Get the IDs of the documents whose characters layer contains at least
+    /// one match of a regular expression.
+    #[wasm_bindgen]
+    pub fn docs_matching_regex(&self, char_layer: Option<String>, pattern: &str) -> Result<String, WasmError> {
+        self.docs_matching_regex_with_progress(char_layer, pattern, None)
+    }
+
+    /// Like `docs_matching_regex` but calls `progress` periodically with
+    /// `{processed, total}`
+    #[wasm_bindgen]
+    pub fn docs_matching_regex_with_progress(
+        &self,
+        char_layer: Option<String>,
+        pattern: &str,
+        progress: Option<js_sys::Function>,
+    ) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let regex = regex::Regex::new(pattern).map_err(|e| WasmError {
+            message: format!("Invalid regex {}: {}", pattern, e)
+        })?;
+        match self.corpus.get_meta().get(char_layer) {
+            Some(desc) if desc.layer_type == LayerType::characters => {}
+            Some(_) => return Err(WasmError {
+                message: format!("Layer {} is not a characters layer", char_layer)
+            }),
+            None => return Err(WasmError {
+                message: format!("Layer {} is not described in meta", char_layer)
+            }),
+        }
+        let docs = self.corpus.get_docs();
+        let progress = Progress::new(progress, docs.len());
+        let mut ids = Vec::new();
+        for (n, doc_id) in docs.iter().enumerate() {
+            progress.report(n);
+            let doc = self.corpus.get_doc_by_id(doc_id)?;
+            if let Some(text) = doc.get(char_layer).and_then(|l| l.characters()) {
+                if regex.is_match(text) {
+                    ids.push(doc_id);
+                }
+            }
+        }
+        progress.report(docs.len());
+        Ok(serde_json::to_string(&ids)?)
+    }
+
+    /// Find every occurrence of `query` in a characters layer. Returns a JSON
+    /// object with the total `count` and the `matches` as `{doc_id, start, end}`
+    /// byte offsets. If `whole_word` is set, only matches that are not
+    /// preceded or followed by a word character (as understood by the simple
+    /// tokenizer) are reported.
+    #[wasm_bindgen]
+    pub fn search_text(&self, char_layer: Option<String>, query: &str, whole_word: bool) -> Result<String, WasmError> {
+        self.search_text_with_progress(char_layer, query, whole_word, None)
+    }
+
+    /// Like `search_text` but calls `progress` periodically with
+    /// `{processed, total}`
+    #[wasm_bindgen]
+    pub fn search_text_with_progress(
+        &self,
+        char_layer: Option<String>,
+        query: &str,
+        whole_word: bool,
+        progress: Option<js_sys::Function>,
+    ) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        if query.is_empty() {
+            return Err(WasmError { message: "Query must not be empty".to_string() });
+        }
+        let docs = self.corpus.get_docs();
+        let progress = Progress::new(progress, docs.len());
+        let mut matches = Vec::new();
+        for (n, doc_id) in docs.iter().enumerate() {
+            progress.report(n);
+            let doc = self.corpus.get_doc_by_id(doc_id)?;
+            let text = match doc.get(char_layer).and_then(|l| l.characters()) {
+                Some(text) => text,
+                None => continue,
+            };
+            for (start, matched) in text.match_indices(query) {
+                let end = start + matched.len();
+                if whole_word && (text[..start].chars().next_back().map_or(false, is_word_char)
+                    || text[end..].chars().next().map_or(false, is_word_char)) {
+                    continue;
+                }
+                matches.push(serde_json::json!({ "doc_id": doc_id, "start": start, "end": end }));
+            }
+        }
+        progress.report(docs.len());
+        Ok(serde_json::json!({ "count": matches.len(), "matches": matches }).to_string())
+    }
+
+    /// Get the `top_k` most frequent surface forms of a token layer, leaving
+    /// out the forms in a JSON array of stopwords (compared case-insensitively).
+    /// Returns a JSON array of `{form, count}` objects.
+    #[wasm_bindgen]
+    pub fn keywords(
+        &self,
+        token_layer: &str,
+        char_layer: Option<String>,
+        stopwords_json: &str,
+        top_k: usize,
+    ) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let stopwords: Vec<String> = serde_json::from_str(stopwords_json)?;
+        let stopwords: std::collections::HashSet<String> = stopwords.iter()
+            .map(|w| w.to_lowercase()).collect();
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        self.for_each_token_form(token_layer, char_layer, |form| {
+            if !stopwords.contains(&form.to_lowercase()) {
+                *counts.entry(form.to_string()).or_insert(0) += 1;
+            }
+        })?;
+        let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let keywords: Vec<serde_json::Value> = counts.into_iter().take(top_k)
+            .map(|(form, count)| serde_json::json!({ "form": form, "count": count }))
+            .collect();
+        Ok(serde_json::to_string(&keywords)?)
+    }
+
+    /// Count each character (Unicode scalar value) of a characters layer
+    /// across the corpus. Returns a JSON object from characters to counts,
+    /// most frequent first.
+    #[wasm_bindgen]
+    pub fn char_frequencies(&self, char_layer: Option<String>) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let mut counts: HashMap<char, usize> = HashMap::new();
+        for doc_id in self.corpus.get_docs() {
+            let doc = self.corpus.get_doc_by_id(&doc_id)?;
+            if let Some(text) = doc.get(char_layer).and_then(|l| l.characters()) {
+                for c in text.chars() {
+                    *counts.entry(c).or_insert(0) += 1;
+                }
+            }
+        }
+        let mut counts: Vec<(char, usize)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(ordered_json_object(counts.into_iter()
+            .map(|(c, count)| (c.to_string(), count.to_string()))
+            .collect())?)
+    }
+
+    /// Count the surface forms of the tokens of `token_layer` across the
+    /// corpus, lowercasing them if `case_fold` is set. Returns a JSON array of
+    /// `{form, count}` objects, most frequent first.
+    #[wasm_bindgen]
+    pub fn vocabulary(&self, token_layer: &str, char_layer: Option<String>, case_fold: bool) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        self.for_each_token_form(token_layer, char_layer, |form| {
+            let form = if case_fold { form.to_lowercase() } else { form.to_string() };
+            *counts.entry(form).or_insert(0) += 1;
+        })?;
+        let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let vocabulary: Vec<serde_json::Value> = counts.into_iter()
+            .map(|(form, count)| serde_json::json!({ "form": form, "count": count }))
+            .collect();
+        Ok(serde_json::to_string(&vocabulary)?)
+    }
+
+    /// Count the distinct surface forms of the tokens of `token_layer` across
+    /// the corpus, see `vocabulary`
+    #[wasm_bindgen]
+    pub fn vocabulary_size(&self, token_layer: &str, char_layer: Option<String>, case_fold: bool) -> Result<usize, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let mut forms = std::collections::HashSet::new();
+        self.for_each_token_form(token_layer, char_layer, |form| {
+            if case_fold {
+                forms.insert(form.to_lowercase());
+            } else if !forms.contains(form) {
+                forms.insert(form.to_string());
+            }
+        })?;
+        Ok(forms.len())
+    }
+
+    /// Rank documents against a free-text query by TF-IDF over the lowercased
+    /// tokens of `token_layer`. Returns the `top_k` best matching documents as
+    /// a JSON array of `{doc_id, score}` objects; documents sharing no term
+    /// with the query are left out.
+    #[wasm_bindgen]
+    pub fn rank_by_query(
+        &self,
+        char_layer: Option<String>,
+        token_layer: &str,
+        query: &str,
+        top_k: usize,
+    ) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let terms: std::collections::HashSet<String> = simple_tokenize(query, false).into_iter()
+            .map(|(s, e)| query[s as usize..e as usize].to_lowercase())
+            .collect();
+        let meta = self.corpus.get_meta();
+        // Occurrences of each query term and the number of tokens per document
+        let mut docs: Vec<(String, HashMap<&str, usize>, usize)> = Vec::new();
+        let mut doc_freq: HashMap<&str, usize> = HashMap::new();
+        for doc_id in self.corpus.get_docs() {
+            let doc = self.corpus.get_doc_by_id(&doc_id)?;
+            let text = match (doc.get(token_layer), doc.get(char_layer).and_then(|l| l.characters())) {
+                (Some(_), Some(text)) => text,
+                _ => continue,
+            };
+            let indexes = doc.indexes(token_layer, char_layer, meta)?;
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for &(start, end) in &indexes {
+                if let Some(term) = text.get(start..end).and_then(|form| terms.get(&form.to_lowercase())) {
+                    *counts.entry(term.as_str()).or_insert(0) += 1;
+                }
+            }
+            for term in counts.keys() {
+                *doc_freq.entry(term).or_insert(0) += 1;
+            }
+            docs.push((doc_id, counts, indexes.len()));
+        }
+        let n_docs = docs.len() as f64;
+        let mut scores: Vec<(String, f64)> = docs.into_iter()
+            .filter(|(_, counts, _)| !counts.is_empty())
+            .map(|(doc_id, counts, length)| {
+                let score = counts.iter().map(|(term, &count)| {
+                    let idf = ((n_docs + 1.0) / (doc_freq[term] as f64 + 1.0)).ln() + 1.0;
+                    count as f64 / length as f64 * idf
+                }).sum();
+                (doc_id, score)
+            })
+            .collect();
+        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        let ranked: Vec<serde_json::Value> = scores.into_iter().take(top_k)
+            .map(|(doc_id, score)| serde_json::json!({ "doc_id": doc_id, "score": score }))
+            .collect();
+        Ok(serde_json::to_string(&ranked)?)
+    }
+
+    /// Find the tokens whose surface form is within Levenshtein distance
+    /// `max_distance` of `query`. Returns a JSON array of
+    /// `{doc_id, index, form, distance}` objects.
+    #[wasm_bindgen]
+    pub fn fuzzy_search(
+        &self,
+        token_layer: &str,
+        char_layer: Option<String>,
+        query: &str,
+        max_distance: usize,
+    ) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let query: Vec<char> = query.chars().collect();
+        let meta = self.corpus.get_meta();
+        let mut found = Vec::new();
+        for doc_id in self.corpus.get_docs() {
+            let doc = self.corpus.get_doc_by_id(&doc_id)?;
+            let text = match (doc.get(token_layer), doc.get(char_layer).and_then(|l| l.characters())) {
+                (Some(_), Some(text)) => text,
+                _ => continue,
+            };
+            for (index, (start, end)) in doc.indexes(token_layer, char_layer, meta)?.into_iter().enumerate() {
+                let form = match text.get(start..end) {
+                    Some(form) => form,
+                    None => continue,
+                };
+                let form_chars: Vec<char> = form.chars().collect();
+                if let Some(distance) = bounded_levenshtein(&query, &form_chars, max_distance) {
+                    found.push(serde_json::json!({
+                        "doc_id": doc_id,
+                        "index": index,
+                        "form": form,
+                        "distance": distance
+                    }));
+                }
+            }
+        }
+        Ok(serde_json::to_string(&found)?)
+    }
+
+    /// Build a keyword-in-context concordance of every occurrence of `keyword`
+    /// in a characters layer, with up to `window` characters of context on
+    /// each side. If `collapse_whitespace` is set, runs of whitespace in the
+    /// context are shown as single spaces; the keyword and offsets are
+    /// unaffected.
+    #[wasm_bindgen]
+    pub fn concordance(
+        &self,
+        char_layer: Option<String>,
+        keyword: &str,
+        window: usize,
+        collapse_whitespace: bool,
+    ) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        if keyword.is_empty() {
+            return Err(WasmError { message: "Keyword must not be empty".to_string() });
+        }
+        let display = |context: String| if collapse_whitespace {
+            collapse_whitespace_runs(&context)
+        } else {
+            context
+        };
+        let lines: Vec<serde_json::Value> = self.corpus.concordance(char_layer, keyword, window, None)?
+            .into_iter()
+            .map(|line| serde_json::json!({
+                "doc_id": line.doc_id,
+                "start": line.start,
+                "end": line.end,
+                "left": display(line.left),
+                "keyword": line.keyword,
+                "right": display(line.right)
+            }))
+            .collect();
+        Ok(serde_json::to_string(&lines)?)
+    }
+
+    /// Get the name of the internal variant used to store a layer of a
+    /// document, such as `"L2S"`, `"Characters"` or `"MetaLayer"`
+    #[wasm_bindgen]
+    pub fn layer_variant(&self, doc_id: &str, layer_name: &str) -> Result<String, WasmError> {
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let variant = match doc.get(layer_name) {
+            Some(Layer::Characters(_)) => "Characters",
+            Some(Layer::L1(_)) => "L1",
+            Some(Layer::L2(_)) => "L2",
+            Some(Layer::L3(_)) => "L3",
+            Some(Layer::LS(_)) => "LS",
+            Some(Layer::L1S(_)) => "L1S",
+            Some(Layer::L2S(_)) => "L2S",
+            Some(Layer::L3S(_)) => "L3S",
+            Some(Layer::MetaLayer(_)) => "MetaLayer",
+            None => return Err(WasmError {
+                message: format!("Layer {} not found in document {}", layer_name, doc_id)
+            }),
+        };
+        Ok(variant.to_string())
+    }
+
+    /// Check whether two layers of a document have the same number of
+    /// entries, as required by `add_seq_labels`
+    #[wasm_bindgen]
+    pub fn layers_aligned(&self, doc_id: &str, layer_a: &str, layer_b: &str) -> Result<bool, WasmError> {
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let len = |layer_name: &str| doc.get(layer_name).map(|l| l.len()).ok_or_else(|| WasmError {
+            message: format!("Layer {} not found in document {}", layer_name, doc_id)
+        });
+        Ok(len(layer_a)? == len(layer_b)?)
+    }
+
+    /// Get the entries of an element layer as a JSON array of `{index, value}`
+    /// objects, where `value` is the label or link of the element, or null
+    #[wasm_bindgen]
+    pub fn element_values(&self, doc_id: &str, layer_name: &str) -> Result<String, WasmError> {
+        match self.corpus.get_meta().get(layer_name) {
+            Some(desc) if desc.layer_type == LayerType::element => {}
+            _ => return Err(WasmError {
+                message: format!("Layer {} is not an element layer", layer_name)
+            }),
+        }
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let values: Vec<serde_json::Value> = match doc.get(layer_name) {
+            None => Vec::new(),
+            Some(Layer::L1(data)) => data.iter()
+                .map(|i| serde_json::json!({ "index": i, "value": null }))
+                .collect(),
+            Some(Layer::L1S(data)) => data.iter()
+                .map(|(i, label)| serde_json::json!({ "index": i, "value": label }))
+                .collect(),
+            Some(Layer::L2(data)) => data.iter()
+                .map(|(i, link)| serde_json::json!({ "index": i, "value": link }))
+                .collect(),
+            Some(Layer::L2S(data)) => data.iter()
+                .map(|(i, link, link_type)| serde_json::json!({ "index": i, "value": [link, link_type] }))
+                .collect(),
+            Some(_) => return Err(WasmError {
+                message: format!("Layer {} does not contain elements", layer_name)
+            }),
+        };
+        Ok(serde_json::to_string(&values)?)
+    }
+
+    /// Get the length of a characters layer of a document in UTF-8 bytes,
+    /// Unicode scalar values and UTF-16 code units, as `{bytes, chars, utf16}`
+    #[wasm_bindgen]
+    pub fn char_layer_lengths(&self, doc_id: &str, char_layer: Option<String>) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let text = doc.get(char_layer).and_then(|l| l.characters()).ok_or_else(|| WasmError {
+            message: format!("Document {} has no characters layer {}", doc_id, char_layer)
+        })?;
+        Ok(serde_json::json!({
+            "bytes": text.len(),
+            "chars": text.chars().count(),
+            "utf16": text.encode_utf16().count()
+        }).to_string())
+    }
+
+    /// Get the smallest start and largest end of the spans of a layer as
+    /// `{min_start, max_end, count}`, or `null` if the document has no spans
+    /// in the layer. Offsets are into the base of the layer.
+    #[wasm_bindgen]
+    pub fn layer_extent(&self, doc_id: &str, layer_name: &str) -> Result<String, WasmError> {
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let bounds = match doc.get(layer_name) {
+            Some(layer) => span_bounds(layer).ok_or_else(|| WasmError {
+                message: format!("Layer {} does not contain spans", layer_name)
+            })?,
+            None => Vec::new(),
+        };
+        let extent = match (bounds.iter().map(|b| b.0).min(), bounds.iter().map(|b| b.1).max()) {
+            (Some(min_start), Some(max_end)) => serde_json::json!({
+                "min_start": min_start,
+                "max_end": max_end,
+                "count": bounds.len()
+            }),
+            _ => serde_json::Value::Null,
+        };
+        Ok(extent.to_string())
+    }
+
+    /// Map every offset of a characters layer to the index of the token of
+    /// `token_layer` covering it, or -1 if no token does. Returns a JSON array
+    /// as long as the characters layer; where tokens overlap the first wins.
+    #[wasm_bindgen]
+    pub fn offset_to_token_map(&self, doc_id: &str, token_layer: &str, char_layer: Option<String>) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let text = doc.get(char_layer).and_then(|l| l.characters()).ok_or_else(|| WasmError {
+            message: format!("Document {} has no characters layer {}", doc_id, char_layer)
+        })?;
+        let mut map = vec![-1i64; text.len()];
+        for (index, (start, end)) in doc.indexes(token_layer, char_layer, self.corpus.get_meta())?.into_iter().enumerate() {
+            for slot in map.iter_mut().take(end).skip(start) {
+                if *slot < 0 {
+                    *slot = index as i64;
+                }
+            }
+        }
+        Ok(serde_json::to_string(&map)?)
+    }
+
+    /// Find the text not covered by any token of `token_layer`, as a JSON
+    /// array of `{start, end, text}` for each maximal run of uncovered
+    /// non-whitespace characters. If `include_whitespace` is set, whitespace
+    /// is part of the runs and whitespace-only gaps are reported too.
+    #[wasm_bindgen]
+    pub fn token_gaps(
+        &self,
+        doc_id: &str,
+        token_layer: &str,
+        char_layer: Option<String>,
+        include_whitespace: bool,
+    ) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let text = doc.get(char_layer).and_then(|l| l.characters()).ok_or_else(|| WasmError {
+            message: format!("Document {} has no characters layer {}", doc_id, char_layer)
+        })?;
+        let mut covered = vec![false; text.len()];
+        for (start, end) in doc.indexes(token_layer, char_layer, self.corpus.get_meta())? {
+            covered.iter_mut().take(end).skip(start).for_each(|c| *c = true);
+        }
+        let mut gaps = Vec::new();
+        let mut run: Option<usize> = None;
+        for (i, ch) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+            let in_gap = i < text.len() && !covered[i] && (include_whitespace || !ch.is_whitespace());
+            match run {
+                Some(start) if !in_gap => {
+                    gaps.push(serde_json::json!({ "start": start, "end": i, "text": &text[start..i] }));
+                    run = None;
+                }
+                None if in_gap => run = Some(i),
+                _ => {}
+            }
+        }
+        Ok(serde_json::to_string(&gaps)?)
+    }
+
+    /// Get the surface text of the span at `index` in a layer
+    #[wasm_bindgen]
+    pub fn span_text_at(&self, doc_id: &str, layer_name: &str, index: usize) -> Result<String, WasmError> {
+        let meta = self.corpus.get_meta();
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let char_layer = self.characters_layer_of(layer_name)?;
+        let characters = doc.get(char_layer).and_then(|l| l.characters())
+            .ok_or_else(|| WasmError {
+                message: format!("Document {} has no base layer {} for {}", doc_id, char_layer, layer_name)
+            })?;
+        let indexes = doc.indexes(layer_name, char_layer, meta)?;
+        let (start, end) = *indexes.get(index).ok_or_else(|| WasmError {
+            message: format!("Index {} out of range for layer {} with {} spans",
+                index, layer_name, indexes.len())
+        })?;
+        characters.get(start..end).map(|s| s.to_string()).ok_or_else(|| WasmError {
+            message: format!("Span {}..{} is not a valid character range in {}", start, end, char_layer)
+        })
+    }
+
+    /// Compare the spans of a predicted layer with those of a gold layer.
+    /// Returns `{exact, partial}`, each with `tp`, `fp`, `fn`, `precision`,
+    /// `recall` and `f1`. Spans match exactly if their offsets are equal and
+    /// partially if they overlap; when both layers are labelled the labels
+    /// must also be equal. Each span is matched at most once.
+    #[wasm_bindgen]
+    pub fn span_overlap(&self, doc_id: &str, gold_layer: &str, pred_layer: &str) -> Result<String, WasmError> {
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let spans = |name: &str| doc.get(name).and_then(labelled_spans).ok_or_else(|| WasmError {
+            message: format!("Layer {} is not a span layer of document {}", name, doc_id)
+        });
+        let gold = spans(gold_layer)?;
+        let pred = spans(pred_layer)?;
+        let same_label = |g: &LabelledSpan, p: &LabelledSpan| match (g.2, p.2) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        };
+        let exact = match_spans(&gold, &pred, |g, p| (g.0, g.1) == (p.0, p.1) && same_label(g, p));
+        let partial = match_spans(&gold, &pred, |g, p| g.0 < p.1 && p.0 < g.1 && same_label(g, p));
+        Ok(serde_json::json!({
+            "exact": span_scores(exact, pred.len(), gold.len()),
+            "partial": span_scores(partial, pred.len(), gold.len())
+        }).to_string())
+    }
+
+    /// Get the fraction of the characters (by code point) of a document that
+    /// lie inside at least one span of `span_layer`
+    #[wasm_bindgen]
+    pub fn span_coverage(&self, doc_id: &str, span_layer: &str, char_layer: Option<String>) -> Result<f64, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let (covered, total) = self.covered_chars(&doc, span_layer, char_layer)?;
+        Ok(if total == 0 { 0.0 } else { covered as f64 / total as f64 })
+    }
+
+    /// Get the fraction of the characters (by code point) of the whole corpus
+    /// that lie inside at least one span of `span_layer`
+    #[wasm_bindgen]
+    pub fn corpus_span_coverage(&self, span_layer: &str, char_layer: Option<String>) -> Result<f64, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let (mut covered, mut total) = (0, 0);
+        for doc_id in self.corpus.get_docs() {
+            let doc = self.corpus.get_doc_by_id(&doc_id)?;
+            if doc.get(char_layer).is_none() {
+                continue;
+            }
+            let (c, t) = self.covered_chars(&doc, span_layer, char_layer)?;
+            covered += c;
+            total += t;
+        }
+        Ok(if total == 0 { 0.0 } else { covered as f64 / total as f64 })
+    }
+
+    /// Get every span annotation covering a character offset as a JSON array
+    /// of `{layer, index, start, end, value}` objects
+    #[wasm_bindgen]
+    pub fn annotations_at(&self, doc_id: &str, offset: u32) -> Result<String, WasmError> {
+        let meta = self.corpus.get_meta();
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let mut names: Vec<&String> = doc.content.keys()
+            .filter(|name| meta.get(*name).map_or(false, |d| d.layer_type == LayerType::span))
+            .collect();
+        names.sort();
+        let mut annotations = Vec::new();
+        for name in names {
+            let char_layer = self.characters_layer_of(name)?;
+            let offset = offset as usize;
+            for (index, (start, end, data)) in doc.indexes_data(name, char_layer, meta)?.into_iter().enumerate() {
+                if start <= offset && offset < end {
+                    let value = match data {
+                        TeangaData::None => serde_json::Value::Null,
+                        TeangaData::String(s) => serde_json::Value::String(s),
+                        TeangaData::Link(l) => serde_json::json!(l),
+                        TeangaData::TypedLink(l, s) => serde_json::json!([l, s]),
+                    };
+                    annotations.push(serde_json::json!({
+                        "layer": name,
+                        "index": index,
+                        "start": start,
+                        "end": end,
+                        "value": value
+                    }));
+                }
+            }
+        }
+        Ok(serde_json::to_string(&annotations)?)
+    }
+
+    /// Export a document as a Label Studio task, with the spans of
+    /// `span_layer` labelled by the values of `label_layer` as predictions.
+    /// Offsets are converted to code points as Label Studio expects.
+    #[wasm_bindgen]
+    pub fn to_label_studio(
+        &self,
+        doc_id: &str,
+        char_layer: Option<String>,
+        span_layer: &str,
+        label_layer: &str,
+    ) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let layers = LabelStudioLayers::new(char_layer, span_layer).labels(label_layer);
+        let task = label_studio_task(doc_id, &doc, self.corpus.get_meta(), &layers)?;
+        Ok(serde_json::to_string(&task)?)
+    }
+
+    /// Import a completed Label Studio task as a new document, with the text
+    /// in `char_layer` and every labelled result as an entry of `span_layer`.
+    /// The layers are registered if they are not yet described. Returns the
+    /// ID of the new document.
+    #[wasm_bindgen]
+    pub fn add_label_studio(
+        &mut self,
+        task_json: &str,
+        char_layer: Option<String>,
+        span_layer: &str,
+    ) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let task: serde_json::Value = serde_json::from_str(task_json)?;
+        let (text, spans) = label_studio_spans(&task)?;
+
+        match self.corpus.get_meta().get(char_layer) {
+            Some(desc) if desc.layer_type != LayerType::characters => return Err(WasmError {
+                message: format!("Layer {} is not a characters layer", char_layer)
+            }),
+            Some(_) => {}
+            None => self.add_layer_meta(char_layer, "characters", None, None)?,
+        }
+        match self.corpus.get_meta().get(span_layer) {
+            Some(desc) if desc.layer_type != LayerType::span
+                || desc.base.as_deref() != Some(char_layer) => return Err(WasmError {
+                message: format!("Layer {} is not a span layer over {}", span_layer, char_layer)
+            }),
+            Some(_) => {}
+            None => self.add_layer_meta(span_layer, "span", Some(char_layer.to_string()),
+                Some("string".to_string()))?,
+        }
+        let mut layers = HashMap::new();
+        layers.insert(char_layer.to_string(), Layer::Characters(text));
+        layers.insert(span_layer.to_string(), Layer::L2S(spans));
+        Ok(self.corpus.add_doc(layers)?)
+    }
+
+    /// Concatenate several documents into a new one. The characters layers are
+    /// joined with `separator` and every other layer is appended with its
+    /// indices shifted past the content of the preceding documents. Returns the
+    /// ID of the new document.
+    #[wasm_bindgen]
+    pub fn concat_docs(
+        &mut self,
+        ids_json: &str,
+        char_layer: Option<String>,
+        separator: &str,
+    ) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let ids: Vec<String> = serde_json::from_str(ids_json)?;
+        let meta = self.corpus.get_meta();
+        let mut text = String::new();
+        // Number of entries (or bytes for the characters layer) of each layer
+        // that precede the document currently being appended
+        let mut offsets: HashMap<String, u32> = HashMap::new();
+        let mut layers: HashMap<String, Layer> = HashMap::new();
+        for (n, id) in ids.iter().enumerate() {
+            let doc = self.corpus.get_doc_by_id(id)?;
+            let doc_text = doc.get(char_layer).and_then(|l| l.characters())
+                .ok_or_else(|| WasmError {
+                    message: format!("Document {} has no characters layer {}", id, char_layer)
+                })?;
+            if n > 0 {
+                text.push_str(separator);
+            }
+            offsets.insert(char_layer.to_string(), text.len() as u32);
+            text.push_str(doc_text);
+
+            for (name, layer) in &doc.content {
+                if name == char_layer {
+                    continue;
+                }
+                if name.starts_with('_') {
+                    layers.entry(name.clone()).or_insert_with(|| layer.clone());
+                    continue;
+                }
+                let desc = meta.get(name).ok_or_else(|| WasmError {
+                    message: format!("Layer {} is not described in meta", name)
+                })?;
+                if desc.layer_type == LayerType::characters {
+                    return Err(WasmError {
+                        message: format!("Cannot concatenate characters layer {} other than {}",
+                            name, char_layer)
+                    });
+                }
+                let index_shift = desc.base.as_ref()
+                    .and_then(|b| offsets.get(b)).copied().unwrap_or(0) as i64;
+                let link_target = desc.target.as_deref().unwrap_or(name);
+                let link_shift = offsets.get(link_target).copied().unwrap_or(0) as i64;
+                let shifted = shift_layer(layer, &desc.layer_type, index_shift, link_shift)?;
+                match layers.get_mut(name) {
+                    Some(existing) => extend_layer(existing, shifted).map_err(|_| WasmError {
+                        message: format!("Layer {} has different shapes across documents", name)
+                    })?,
+                    None => { layers.insert(name.clone(), shifted); }
+                }
+            }
+            for (name, layer) in &doc.content {
+                if name != char_layer && !name.starts_with('_') {
+                    *offsets.entry(name.clone()).or_insert(0) += layer.len() as u32;
+                }
+            }
+        }
+
+        for (name, layer) in &layers {
+            if let Some(desc) = meta.get(name) {
+                if desc.layer_type == LayerType::seq {
+                    let base_len = desc.base.as_ref().and_then(|b| layers.get(b)).map(|b| b.len());
+                    if base_len.map_or(false, |n| n != layer.len()) {
+                        return Err(WasmError {
+                            message: format!("Seq layer {} is not present in every document with its base", name)
+                        });
+                    }
+                }
+            }
+        }
+        layers.insert(char_layer.to_string(), Layer::Characters(text));
+        Ok(self.corpus.add_doc(layers)?)
+    }
+
+    /// Split a document into one new document per sentence of
+    /// `sentence_layer`, the inverse of `concat_docs`. The sentence layer may
+    /// be any layer that resolves to `char_layer`, such as a span layer over
+    /// it or a div layer over its tokens. Each new document holds the sentence text
+    /// as `char_layer`, the spans, divisions and elements over `char_layer`
+    /// that lie within the sentence with their offsets rebased, the seq layers
+    /// over those, and the document metadata. Layers holding links are not
+    /// copied. If no `char_layer` is given the default characters layer is
+    /// used. Returns a JSON array of the new document IDs.
+    #[wasm_bindgen]
+    pub fn explode_sentences(
+        &mut self,
+        doc_id: &str,
+        char_layer: Option<String>,
+        sentence_layer: &str,
+        remove_original: bool,
+    ) -> Result<String, WasmError> {
+        let char_layer = &self.resolve_char_layer(char_layer)?;
+        let meta = self.corpus.get_meta().clone();
+        let doc = self.corpus.get_doc_by_id(doc_id)?;
+        let text = doc.get(char_layer).and_then(|l| l.characters()).ok_or_else(|| WasmError {
+            message: format!("Document {} has no characters layer {}", doc_id, char_layer)
+        })?;
+        if self.characters_layer_of(sentence_layer)? != char_layer {
+            return Err(WasmError {
+                message: format!("Layer {} is not based on characters layer {}", sentence_layer, char_layer)
+            });
+        }
+        if doc.get(sentence_layer).is_none() {
+            return Err(WasmError {
+                message: format!("Layer {} not found in document {}", sentence_layer, doc_id)
+            });
+        }
+        let sentences = doc.indexes(sentence_layer, char_layer, &meta)?;
+        let mut ids = Vec::new();
+        for (start, end) in sentences {
+            let (start, end) = (start as u32, end as u32);
+            let sentence = text.get(start as usize..end as usize).ok_or_else(|| WasmError {
+                message: format!("Span {}..{} is not a valid character range in {}", start, end, char_layer)
+            })?;
+            let mut layers: HashMap<String, Layer> = HashMap::new();
+            let mut selected: HashMap<&str, Vec<usize>> = HashMap::new();
+            for (name, layer) in &doc.content {
+                if name.starts_with('_') {
+                    layers.insert(name.clone(), layer.clone());
+                    continue;
+                }
+                let desc = match meta.get(name) {
+                    Some(desc) if name != sentence_layer && desc.base.as_deref() == Some(char_layer)
+                        && desc.data != Some(DataType::Link) => desc,
+                    _ => continue,
+                };
+                let inside: Vec<usize> = match (layer, span_bounds(layer)) {
+                    (_, Some(bounds)) => (0..bounds.len())
+                        .filter(|&i| bounds[i].0 >= start && bounds[i].1 <= end).collect(),
+                    (Layer::L1(data), None) => (0..data.len())
+                        .filter(|&i| data[i] >= start && data[i] < end).collect(),
+                    (Layer::L1S(data), None) => (0..data.len())
+                        .filter(|&i| data[i].0 >= start && data[i].0 < end).collect(),
+                    _ => continue,
+                };
+                let rebased = shift_layer(&select_entries(layer, &inside), &desc.layer_type, -(start as i64), 0)?;
+                layers.insert(name.clone(), rebased);
+                selected.insert(name.as_str(), inside);
+            }
+            for (name, layer) in &doc.content {
+                let base = match meta.get(name) {
+                    Some(desc) if desc.layer_type == LayerType::seq
+                        && desc.data != Some(DataType::Link) => desc.base.as_deref(),
+                    _ => None,
+                };
+                let base_len = base.and_then(|b| doc.get(b)).map(|l| l.len());
+                if let Some(inside) = base.and_then(|b| selected.get(b)) {
+                    if base_len == Some(layer.len()) {
+                        layers.insert(name.clone(), select_entries(layer, inside));
+                    }
+                }
+            }
+            layers.insert(char_layer.to_string(), Layer::Characters(sentence.to_string()));
+            ids.push(self.corpus.add_doc(layers)?);
+        }
+        if remove_original {
+            self.corpus.remove_doc(doc_id)?;
+        }
+        Ok(serde_json::to_string(&ids)?)
+    }
+
+    // Helper methods
+    // Validate all documents before adding any of them
+    fn add_parsed_docs(&mut self, docs: Vec<HashMap<String, serde_json::Value>>) -> Result<Vec<String>, WasmError> {
+        let mut contents = Vec::new();
+        for doc_data in docs {
+            let doc = Document::new(self.prepare_doc(doc_data)?, self.corpus.get_meta())?;
+            contents.push(doc.content);
+        }
+        Ok(self.corpus.add_docs(contents)?)
+    }
+
+    // Replace every document for which `f(doc_id, doc_json)` returns new JSON,
+    // see `map_docs`. Every result is validated against the metadata before
+    // any document is replaced
+    fn map_docs_with<F>(&mut self, mut f: F) -> Result<(), WasmError>
+        where F: FnMut(&str, String) -> Result<Option<String>, WasmError> {
+        let mut order = self.corpus.get_docs();
+        let mut replacements = Vec::new();
+        for (n, doc_id) in order.iter().enumerate() {
+            let doc = self.corpus.get_doc_by_id(doc_id)?;
+            let doc_json = match f(doc_id, self.doc_to_json_string(&doc)?)? {
+                Some(doc_json) => doc_json,
+                None => continue,
+            };
+            let new_doc = self.parse_doc_json(&doc_json)
+                .and_then(|doc_data| self.prepare_doc(doc_data))
+                .and_then(|layers| Ok(Document::new(layers, self.corpus.get_meta())?))
+                .map_err(|e| WasmError { message: format!("Document {}: {}", doc_id, e.message) })?;
+            if new_doc.content != doc.content {
+                replacements.push((n, new_doc.content));
+            }
+        }
+        // Add every replacement before removing any original, so that a
+        // failure leaves the corpus as it was
+        let mut added = Vec::new();
+        for (n, layers) in replacements {
+            match self.corpus.add_doc(layers) {
+                Ok(id) => added.push((n, id)),
+                Err(e) => {
+                    for (_, id) in added {
+                        self.corpus.remove_doc(&id)?;
+                    }
+                    self.corpus.set_order(order)?;
+                    return Err(e.into());
+                }
+            }
+        }
+        for (n, id) in added {
+            self.corpus.remove_doc(&order[n])?;
+            order[n] = id;
+        }
+        self.corpus.set_order(order)?;
+        Ok(())
+    }
+
+    // Call `f(doc_id, layer_name, layer_json)` for every layer, see
+    // `for_each_layer`. The traversal stops when `f` returns false
+    fn for_each_layer_with<F>(&self, mut f: F) -> Result<(), WasmError>
+        where F: FnMut(&str, &str, String) -> Result<bool, WasmError> {
+        for doc_id in self.corpus.get_docs() {
+            let doc = self.corpus.get_doc_by_id(&doc_id)?;
+            for name in self.ordered_layer_names(doc.content.keys()) {
+                let layer_json = serde_json::to_string(&self.layer_to_json_value(&doc.content[name]))?;
+                if !f(&doc_id, name, layer_json)? {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Check that a round-tripped corpus declares the same layers
+    fn check_same_meta(&self, other: &SimpleCorpus) -> Result<(), WasmError> {
+        for (name, desc) in self.corpus.get_meta() {
+            if other.get_meta().get(name) != Some(desc) {
+                return Err(WasmError {
+                    message: format!("Layer {} differs after round-trip", name)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn meta_to_json_value(&self) -> serde_json::Value {
+        // Convert metadata to JSON-serializable format
+        let mut meta_map = HashMap::new();
+        for (name, layer_desc) in self.corpus.get_meta() {
+            let mut desc_map = HashMap::new();
+            desc_map.insert("layer_type".to_string(), 
+                serde_json::Value::String(format!("{}", layer_desc.layer_type)));
+            
+            if let Some(ref base) = layer_desc.base {
+                desc_map.insert("base".to_string(), serde_json::Value::String(base.clone()));
+            }
+            
+            if let Some(ref data) = layer_desc.data {
+                let data_value = match data {
+                    DataType::String => serde_json::Value::String("string".to_string()),
+                    DataType::Link => serde_json::Value::String("link".to_string()),
+                    DataType::Float => serde_json::Value::String("float".to_string()),
+                    DataType::Int => serde_json::Value::String("int".to_string()),
+                    DataType::Enum(vals) => serde_json::Value::Array(
+                        vals.iter().map(|v| serde_json::Value::String(v.clone())).collect()
+                    ),
+                };
+                desc_map.insert("data".to_string(), data_value);
+            }
+            
+            meta_map.insert(name.clone(), serde_json::Value::Object(
+                desc_map.into_iter().collect()
+            ));
+        }
+        
+        serde_json::Value::Object(meta_map.into_iter().collect())
+    }
+
+    // The layers of a document as JSON values in registration order
+    fn ordered_doc<'a>(&self, doc: &'a Document) -> OrderedMap<'a> {
+        OrderedMap(self.ordered_layer_names(doc.content.keys()).into_iter()
+            .map(|name| (name.as_str(), self.layer_to_json_value(&doc.content[name])))
+            .collect())
+    }
+
+    // Use the given characters layer, or the default if none was given
+    fn resolve_char_layer(&self, char_layer: Option<String>) -> Result<String, WasmError> {
+        char_layer.or_else(|| self.default_char_layer.clone()).ok_or_else(|| WasmError {
+            message: "No characters layer given and no default set".to_string()
+        })
+    }
+
+    // Sort layer names by the order their layers were registered in, with
+    // any other layers after them in alphabetical order
+    fn ordered_layer_names<'a>(&self, names: impl Iterator<Item = &'a String>) -> Vec<&'a String> {
+        let mut names: Vec<&String> = names.collect();
+        names.sort_by_key(|name| (
+            self.layer_order.iter().position(|n| n == *name).unwrap_or(usize::MAX),
+            *name
+        ));
+        names
+    }
+
+    // Collect the statistics of each declared layer in registration order
+    fn layer_summary_rows(&self) -> Vec<LayerSummary> {
+        let meta = self.corpus.get_meta();
+        let mut docs_with: HashMap<&str, usize> = HashMap::new();
+        let mut entries: HashMap<&str, usize> = HashMap::new();
+        let doc_ids = self.corpus.get_docs();
+        for doc_id in &doc_ids {
+            if let Ok(doc) = self.corpus.get_doc_by_id(doc_id) {
+                for (name, layer) in &doc.content {
+                    if let Some((name, _)) = meta.get_key_value(name) {
+                        *docs_with.entry(name).or_insert(0) += 1;
+                        *entries.entry(name).or_insert(0) += layer.len();
+                    }
+                }
+            }
+        }
+        self.ordered_layer_names(meta.keys()).into_iter().map(|name| {
+            let desc = &meta[name];
+            let count = docs_with.get(name.as_str()).copied().unwrap_or(0);
+            LayerSummary {
+                name: name.clone(),
+                layer_type: desc.layer_type.to_string(),
+                doc_coverage: if doc_ids.is_empty() { 0.0 } else { count as f64 / doc_ids.len() as f64 },
+                total_entries: entries.get(name.as_str()).copied().unwrap_or(0),
+                base: desc.base.clone(),
+            }
+        }).collect()
+    }
+
+    // The updates replacing the bounds of the spans of a layer, keeping only
+    // the spans flagged in `keep`, along with the matching entries of seq
+    // layers over it. Fails if spans are removed from a layer other non-seq
+    // layers refer into.
+    fn span_bound_updates(
+        &self,
+        doc: &Document,
+        layer_name: &str,
+        layer: &Layer,
+        bounds: &[(u32, u32)],
+        keep: &[bool],
+    ) -> Result<Vec<(String, Layer)>, WasmError> {
+        let dependents: Vec<(&String, &LayerDesc)> = self.corpus.get_meta().iter()
+            .filter(|(_, d)| d.base.as_deref() == Some(layer_name))
+            .collect();
+        let dropped = keep.iter().any(|k| !k);
+        if dropped {
+            if let Some((name, _)) = dependents.iter().find(|(_, d)| d.layer_type != LayerType::seq) {
+                return Err(WasmError {
+                    message: format!("Cannot remove spans of {} as layer {} refers to them",
+                        layer_name, name)
+                });
+            }
+        }
+        let layer = match layer {
+            Layer::L2(_) => Layer::L2(bounds.to_vec()),
+            Layer::L2S(data) => Layer::L2S(bounds.iter().zip(data)
+                .map(|(&(s, e), (_, _, l))| (s, e, l.clone())).collect()),
+            Layer::L3(data) => Layer::L3(bounds.iter().zip(data)
+                .map(|(&(s, e), &(_, _, k))| (s, e, k)).collect()),
+            Layer::L3S(data) => Layer::L3S(bounds.iter().zip(data)
+                .map(|(&(s, e), (_, _, k, l))| (s, e, *k, l.clone())).collect()),
+            _ => return Err(WasmError {
+                message: format!("Layer {} does not contain spans", layer_name)
+            }),
+        };
+        let mut updates = vec![(layer_name.to_string(), retain_entries(&layer, keep))];
+        if dropped {
+            for (name, _) in dependents {
+                if let Some(seq_layer) = doc.get(name).filter(|l| l.len() == keep.len()) {
+                    updates.push((name.clone(), retain_entries(seq_layer, keep)));
+                }
+            }
+        }
+        Ok(updates)
+    }
+
+    // Get a document for display, filling in layer defaults if enabled
+    fn read_doc(&self, id: &str) -> Result<Document, WasmError> {
+        let mut doc = self.corpus.get_doc_by_id(id)?;
+        if self.apply_defaults {
+            for (name, desc) in self.corpus.get_meta() {
+                if let Some(ref default) = desc.default {
+                    doc.content.entry(name.clone()).or_insert_with(|| default.clone());
+                }
+            }
+        }
+        Ok(doc)
+    }
+
+    // Serialize a document with its layers in registration order
+    fn doc_to_json_string(&self, doc: &Document) -> Result<String, WasmError> {
+        let mut entries = Vec::new();
+        for name in self.ordered_layer_names(doc.content.keys()) {
+            entries.push((name.as_str(), serde_json::to_string(&self.layer_to_json_value(&doc.content[name]))?));
+        }
+        Ok(ordered_json_object(entries)?)
+    }
+
+    // Count the code points of a document covered by the union of the spans of
+    // a layer, along with the total number of code points
+    fn covered_chars(&self, doc: &Document, span_layer: &str, char_layer: &str) -> Result<(usize, usize), WasmError> {
+        let text = doc.get(char_layer).and_then(|l| l.characters()).ok_or_else(|| WasmError {
+            message: format!("Document has no characters layer {}", char_layer)
+        })?;
+        let total = text.chars().count();
+        if doc.get(span_layer).is_none() {
+            return Ok((0, total));
+        }
+        let mut spans = doc.indexes(span_layer, char_layer, self.corpus.get_meta())?;
+        spans.sort();
+        let mut covered = 0;
+        let mut current: Option<(usize, usize)> = None;
+        for (start, end) in spans.into_iter().chain(std::iter::once((usize::MAX, usize::MAX))) {
+            match current {
+                Some((s, e)) if start <= e => current = Some((s, e.max(end))),
+                Some((s, e)) => {
+                    covered += text.get(s..e).map_or(0, |t| t.chars().count());
+                    current = Some((start, end));
+                }
+                None => current = Some((start, end)),
+            }
+        }
+        Ok((covered, total))
+    }
+
+    // Call `f` with the surface form of every token of `token_layer` in the
+    // corpus, skipping documents without the token or characters layer
+    fn for_each_token_form<F: FnMut(&str)>(&self, token_layer: &str, char_layer: &str, mut f: F) -> Result<(), WasmError> {
+        let meta = self.corpus.get_meta();
+        for doc_id in self.corpus.get_docs() {
+            let doc = self.corpus.get_doc_by_id(&doc_id)?;
+            let text = match (doc.get(token_layer), doc.get(char_layer).and_then(|l| l.characters())) {
+                (Some(_), Some(text)) => text,
+                _ => continue,
+            };
+            for (start, end) in doc.indexes(token_layer, char_layer, meta)? {
+                if let Some(form) = text.get(start..end) {
+                    f(form);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Parse a document given as JSON into its layers. In strict mode a
+    // repeated key is an error rather than the last value winning.
+    fn parse_doc_json(&self, doc_json: &str) -> Result<HashMap<String, serde_json::Value>, WasmError> {
+        if self.strict {
+            let UniqueKeyMap(doc_data) = serde_json::from_str(doc_json)?;
+            Ok(doc_data)
+        } else {
+            Ok(serde_json::from_str(doc_json)?)
+        }
+    }
+
+    // Parse a JSON array of documents, see `parse_doc_json`
+    fn parse_docs_json(&self, docs_json: &str) -> Result<Vec<HashMap<String, serde_json::Value>>, WasmError> {
+        if self.strict {
+            let docs: Vec<UniqueKeyMap> = serde_json::from_str(docs_json)?;
+            Ok(docs.into_iter().map(|UniqueKeyMap(doc_data)| doc_data).collect())
+        } else {
+            Ok(serde_json::from_str(docs_json)?)
+        }
+    }
+
+    // Convert a parsed document into layers, rejecting it in strict mode if
+    // `validate_doc` reports a problem
+    fn prepare_doc(&self, doc_data: HashMap<String, serde_json::Value>) -> Result<HashMap<String, Layer>, WasmError> {
+        self.check_strict(&doc_data)?;
+
+        // Convert JSON values to Teanga layers
+        let mut layers = HashMap::new();
+        for (key, value) in doc_data {
+            let layer = self.json_value_to_layer(&key, value)?;
+            layers.insert(key, layer);
+        }
+        Ok(layers)
+    }
+
+    // In strict mode, reject a parsed document with any of the problems
+    // reported by `validate_doc`
+    fn check_strict(&self, doc_data: &HashMap<String, serde_json::Value>) -> Result<(), WasmError> {
+        if self.strict {
+            let problems = self.doc_problems(doc_data);
+            if !problems.is_empty() {
+                return Err(WasmError { message: problems.join("; ") });
+            }
+        }
+        Ok(())
+    }
+
+    // The problems `validate_doc` reports for a parsed document
+    fn doc_problems(&self, doc_data: &HashMap<String, serde_json::Value>) -> Vec<String> {
+        let meta = self.corpus.get_meta();
+        let mut problems: Vec<String> = self.required_layers.iter()
+            .filter(|name| !doc_data.contains_key(*name))
+            .map(|name| format!("Missing required layer {}", name))
+            .collect();
+        for name in self.ordered_layer_names(doc_data.keys()) {
+            if name.starts_with('_') {
+                continue;
+            }
+            if !meta.contains_key(name) {
+                problems.push(format!("Layer {} is not described in meta", name));
+            } else if let Err(e) = self.json_value_to_layer(name, doc_data[name].clone()) {
+                problems.push(e.message);
+            }
+        }
+        problems
+    }
+
+    // Follow the base layers of a layer down to its characters layer
+    fn characters_layer_of<'a>(&'a self, layer_name: &'a str) -> Result<&'a str, WasmError> {
+        let meta = self.corpus.get_meta();
+        let mut char_layer = layer_name;
+        let mut seen = std::collections::HashSet::new();
+        while seen.insert(char_layer) {
+            let desc = meta.get(char_layer).ok_or_else(|| WasmError {
+                message: format!("Layer {} is not described in meta", char_layer)
+            })?;
+            match desc.base {
+                Some(ref base) => char_layer = base,
+                None => return Ok(char_layer),
+            }
+        }
+        Err(WasmError {
+            message: format!("Layer {} has a cyclic base", layer_name)
+        })
+    }
+
+    // Parse the JSON value of a layer into the representation expected for
+    // its description. Layers starting with `_` hold any JSON value
+    fn json_value_to_layer(&self, layer_name: &str, value: serde_json::Value) -> Result<Layer, WasmError> {
+        if layer_name.starts_with('_') {
+            return Ok(Layer::MetaLayer(serde_json::from_value(value)?));
+        }
+        let desc = self.corpus.get_meta().get(layer_name).ok_or_else(|| WasmError {
+            message: format!("Layer {} is not described in meta", layer_name)
+        })?;
+        if let serde_json::Value::Array(ref arr) = value {
+            let kind = |v: &serde_json::Value| match v {
+                serde_json::Value::Number(_) => "a number",
+                serde_json::Value::String(_) => "a string",
+                serde_json::Value::Array(_) => "an array",
+                _ => "another value",
+            };
+            if let Some((index, other)) = arr.iter().enumerate().find(|(_, v)| kind(v) != kind(&arr[0])) {
+                return Err(WasmError {
+                    message: format!("Layer {} mixes element types: element 0 is {} but element {} is {}",
+                        layer_name, kind(&arr[0]), index, kind(other))
+                });
+            }
+        }
+        parse_layer_like(empty_layer(desc).as_ref(), value).map_err(|e| WasmError {
+            message: format!("Layer {} does not match its description as a {} layer: {}",
+                layer_name, desc.layer_type, e)
+        })
+    }
+
+    fn layer_to_json_value(&self, layer: &Layer) -> serde_json::Value {
+        match layer {
+            Layer::Characters(text) => serde_json::Value::String(text.clone()),
+            Layer::L1(data) => serde_json::Value::Array(
+                data.iter().map(|&n| serde_json::Value::Number(n.into())).collect()
+            ),
+            Layer::L2(data) => serde_json::Value::Array(
+                data.iter().map(|&(a, b)| serde_json::Value::Array(vec![
+                    serde_json::Value::Number(a.into()),
+                    serde_json::Value::Number(b.into())
+                ])).collect()
+            ),
+            Layer::L3(data) => serde_json::Value::Array(
+                data.iter().map(|&(a, b, c)| serde_json::Value::Array(vec![
+                    serde_json::Value::Number(a.into()),
+                    serde_json::Value::Number(b.into()),
+                    serde_json::Value::Number(c.into())
+                ])).collect()
+            ),
+            Layer::LS(data) => serde_json::Value::Array(
+                data.iter().map(|s| serde_json::Value::String(s.clone())).collect()
+            ),
+            Layer::L1S(data) => serde_json::Value::Array(
+                data.iter().map(|(n, s)| serde_json::Value::Array(vec![
+                    serde_json::Value::Number((*n).into()),
+                    serde_json::Value::String(s.clone())
+                ])).collect()
+            ),
+            Layer::L2S(data) => serde_json::Value::Array(
+                data.iter().map(|(a, b, s)| serde_json::Value::Array(vec![
+                    serde_json::Value::Number((*a).into()),
+                    serde_json::Value::Number((*b).into()),
+                    serde_json::Value::String(s.clone())
+                ])).collect()
+            ),
+            Layer::L3S(data) => serde_json::Value::Array(
+                data.iter().map(|(a, b, c, s)| serde_json::Value::Array(vec![
+                    serde_json::Value::Number((*a).into()),
+                    serde_json::Value::Number((*b).into()),
+                    serde_json::Value::Number((*c).into()),
+                    serde_json::Value::String(s.clone())
+                ])).collect()
+            ),
+            Layer::MetaLayer(data) => {
+                // Convert Value to serde_json::Value
+                match data {
+                    Some(val) => self.value_to_json_value(val),
+                    None => serde_json::Value::Null,
+                }
+            }
+        }
+    }
+
+    // Non-finite floats have no JSON representation, so they are written as
+    // the strings "NaN", "Infinity" and "-Infinity" instead of null
+    fn float_to_json_value(&self, f: f64) -> serde_json::Value {
+        if f.is_nan() {
+            return serde_json::Value::String("NaN".to_string());
+        } else if f.is_infinite() {
+            let sentinel = if f > 0.0 { "Infinity" } else { "-Infinity" };
+            return serde_json::Value::String(sentinel.to_string());
+        }
+        let f = match self.float_precision {
+            Some(digits) => {
+                let scale = 10f64.powi(digits as i32);
+                let rounded = (f * scale).round() / scale;
+                if rounded.is_finite() { rounded } else { f }
+            }
+            None => f,
+        };
+        serde_json::Number::from_f64(f)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::Null)
+    }
+
+    fn value_to_json_value(&self, value: &Value) -> serde_json::Value {
+        match value {
+            Value::Bool(b) => serde_json::Value::Bool(*b),
+            Value::Int(i) => serde_json::Value::Number((*i).into()),
+            Value::Float(f) => self.float_to_json_value(*f),
+            Value::String(s) => serde_json::Value::String(s.clone()),
+            Value::Array(arr) => serde_json::Value::Array(
+                arr.iter().map(|v| self.value_to_json_value(v)).collect()
+            ),
+            Value::Object(obj) => serde_json::Value::Object(
+                obj.iter().map(|(k, v)| (k.clone(), self.value_to_json_value(v))).collect()
+            ),
+        }
+    }
+}
+
+// Native-only conversions, not exported to JavaScript
+impl TeangaWasm {
+    /// Wrap an existing corpus without re-adding its layers and documents
+    pub fn from_corpus(corpus: SimpleCorpus) -> TeangaWasm {
+        TeangaWasm {
+            layer_order: seed_layer_order(corpus.get_meta(), &[]),
+            corpus,
+            float_precision: None,
+            default_char_layer: None,
+            required_layers: Vec::new(),
+            strict: false,
+            apply_defaults: false,
+            corpus_meta: HashMap::new(),
+        }
+    }
+
+    /// Unwrap the underlying corpus
+    pub fn into_corpus(self) -> SimpleCorpus {
+        self.corpus
+    }
+}
+
+// Check that a round-tripped document has the same layers as the original
+fn check_same_doc(doc_id: &str, doc: &Document, new_doc: &Document) -> Result<(), WasmError> {
+    for (layer_name, layer) in &doc.content {
+        if new_doc.get(layer_name) != Some(layer) {
+            return Err(WasmError {
+                message: format!("Layer {} of document {} differs after round-trip",
+                    layer_name, doc_id)
+            });
+        }
+    }
+    for layer_name in new_doc.keys() {
+        if doc.get(&layer_name).is_none() {
+            return Err(WasmError {
+                message: format!("Layer {} of document {} appeared after round-trip",
+                    layer_name, doc_id)
+            });
+        }
+    }
+    Ok(())
+}
+
+// The reserved key holding corpus-level metadata in JSON and YAML output
+const CORPUS_META_KEY: &str = "_corpus_meta";
+
+// Whether a layer has the given text, metadata value or label. Any layer
+// matches when no value is given.
+fn layer_matches(layer: &Layer, equals: Option<&str>) -> bool {
+    let value = match equals {
+        Some(value) => value,
+        None => return true,
+    };
+    match layer {
+        Layer::Characters(text) => text == value,
+        Layer::MetaLayer(Some(Value::String(s))) => s == value,
+        Layer::LS(data) => data.iter().any(|s| s == value),
+        Layer::L1S(data) => data.iter().any(|(_, s)| s == value),
+        Layer::L2S(data) => data.iter().any(|(_, _, s)| s == value),
+        Layer::L3S(data) => data.iter().any(|(_, _, _, s)| s == value),
+        _ => false,
+    }
+}
+
+// Simple tokenization function
+// If `keep_apostrophes` is set, an apostrophe between two letters is kept
+// inside the word, so that "it's" is a single token
+fn simple_tokenize(text: &str, keep_apostrophes: bool) -> Vec<(u32, u32)> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut in_word = false;
+    let mut prev = None;
+    let mut chars = text.char_indices().peekable();
+    
+    while let Some((i, ch)) = chars.next() {
+        let inner_apostrophe = keep_apostrophes && (ch == '\'' || ch == '\u{2019}')
+            && prev.map_or(false, char::is_alphabetic)
+            && chars.peek().map_or(false, |(_, next)| next.is_alphabetic());
+        prev = Some(ch);
+        if is_word_char(ch) || inner_apostrophe {
+            if !in_word {
+                start = i;
+                in_word = true;
+            }
+        } else {
+            if in_word {
+                tokens.push((start as u32, i as u32));
+                in_word = false;
+            }
+            if !ch.is_whitespace() {
+                // Add punctuation as separate token
+                tokens.push((i as u32, (i + ch.len_utf8()) as u32));
+            }
+        }
+    }
+    
+    // Handle final token
+    if in_word {
+        tokens.push((start as u32, text.len() as u32));
+    }
+    
+    tokens
+}
+
+// A single edit of a characters layer, as accepted by `reindex_spans`
+#[derive(Deserialize)]
+struct TextEdit {
+    position: u32,
+    deleted: u32,
+    inserted: u32,
+}
+
+impl TextEdit {
+    // Move a span across this edit. Text inserted at the start of a span
+    // falls before it and text inserted at its end after it. Returns `None`
+    // if an offset would not fit in a `u32`
+    fn shift(&self, start: u32, end: u32) -> Option<(u32, u32)> {
+        let deleted_end = self.position.checked_add(self.deleted)?;
+        let start = if start < self.position {
+            start
+        } else if start >= deleted_end {
+            (start - self.deleted).checked_add(self.inserted)?
+        } else {
+            self.position.checked_add(self.inserted)?
+        };
+        let end = if end <= self.position {
+            end
+        } else if end >= deleted_end {
+            (end - self.deleted).checked_add(self.inserted)?
+        } else {
+            self.position
+        };
+        Some((start, end.max(start)))
+    }
+}
+
+// A JSON object that fails to deserialize if a key is repeated
+struct UniqueKeyMap(HashMap<String, serde_json::Value>);
+
+impl<'de> Deserialize<'de> for UniqueKeyMap {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct UniqueKeyVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for UniqueKeyVisitor {
+            type Value = UniqueKeyMap;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a JSON object")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, mut access: A) -> Result<UniqueKeyMap, A::Error> {
+                let mut map = HashMap::new();
+                while let Some((key, value)) = access.next_entry::<String, serde_json::Value>()? {
+                    if map.contains_key(&key) {
+                        return Err(serde::de::Error::custom(format!("Duplicate layer {}", key)));
+                    }
+                    map.insert(key, value);
+                }
+                Ok(UniqueKeyMap(map))
+            }
+        }
+
+        deserializer.deserialize_map(UniqueKeyVisitor)
+    }
+}
+
+// The statistics of a single layer reported by `layer_summary`
+#[derive(Serialize)]
+struct LayerSummary {
+    name: String,
+    #[serde(rename = "type")]
+    layer_type: String,
+    doc_coverage: f64,
+    total_entries: usize,
+    base: Option<String>,
+}
+
+// Quote a CSV field if it contains a delimiter, quote or line break
+fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+// The start, end and label (if any) of a span
+type LabelledSpan<'a> = (u32, u32, Option<&'a str>);
+
+// The spans of a span layer with their labels
+fn labelled_spans(layer: &Layer) -> Option<Vec<LabelledSpan<'_>>> {
+    match layer {
+        Layer::L2(data) => Some(data.iter().map(|&(s, e)| (s, e, None)).collect()),
+        Layer::L2S(data) => Some(data.iter().map(|(s, e, l)| (*s, *e, Some(l.as_str()))).collect()),
+        Layer::L3(data) => Some(data.iter().map(|&(s, e, _)| (s, e, None)).collect()),
+        Layer::L3S(data) => Some(data.iter().map(|(s, e, _, l)| (*s, *e, Some(l.as_str()))).collect()),
+        _ => None,
+    }
+}
+
+// Greedily pair each predicted span with the first unpaired gold span it
+// matches, returning the number of pairs
+fn match_spans<F>(gold: &[LabelledSpan], pred: &[LabelledSpan], matches: F) -> usize
+    where F: Fn(&LabelledSpan, &LabelledSpan) -> bool {
+    let mut used = vec![false; gold.len()];
+    let mut pairs = 0;
+    for p in pred {
+        if let Some(i) = (0..gold.len()).find(|&i| !used[i] && matches(&gold[i], p)) {
+            used[i] = true;
+            pairs += 1;
+        }
+    }
+    pairs
+}
+
+// Precision, recall and F1 from the number of true positives
+fn span_scores(tp: usize, predicted: usize, gold: usize) -> serde_json::Value {
+    let ratio = |a: usize, b: usize| if b == 0 { 0.0 } else { a as f64 / b as f64 };
+    let precision = ratio(tp, predicted);
+    let recall = ratio(tp, gold);
+    let f1 = if precision + recall == 0.0 { 0.0 } else { 2.0 * precision * recall / (precision + recall) };
+    serde_json::json!({
+        "tp": tp,
+        "fp": predicted - tp,
+        "fn": gold - tp,
+        "precision": precision,
+        "recall": recall,
+        "f1": f1
+    })
+}
+
+// Number of characters of the hash returned by `content_id`
+const CONTENT_ID_LENGTH: usize = 16;
+
+// Number of documents between calls of a progress callback
+const PROGRESS_INTERVAL: usize = 500;
+
+// Reports the progress of a corpus-wide operation to an optional JS callback
+struct Progress {
+    callback: Option<js_sys::Function>,
+    total: usize,
+}
+
+impl Progress {
+    fn new(callback: Option<js_sys::Function>, total: usize) -> Progress {
+        Progress { callback, total }
+    }
+
+    // Whether a callback is due after `processed` documents: every
+    // `PROGRESS_INTERVAL` documents and once all documents are processed
+    fn is_due(&self, processed: usize) -> bool {
+        (processed > 0 && processed % PROGRESS_INTERVAL == 0) || processed == self.total
+    }
+
+    // Call the callback with `{processed, total}` when it is due
+    fn report(&self, processed: usize) {
+        if let Some(ref callback) = self.callback {
+            if self.is_due(processed) {
+                let update = js_sys::Object::new();
+                let _ = js_sys::Reflect::set(&update, &"processed".into(), &(processed as f64).into());
+                let _ = js_sys::Reflect::set(&update, &"total".into(), &(self.total as f64).into());
+                let _ = callback.call1(&JsValue::NULL, &update);
+            }
+        }
+    }
+}
+
+// Some documents of a corpus, for the core writers
+struct DocSelection<'a> {
+    meta: &'a HashMap<String, LayerDesc>,
+    docs: Vec<(String, Document)>,
+}
+
+impl ReadableCorpus for DocSelection<'_> {
+    fn iter_docs<'a>(&'a self) -> Box<dyn Iterator<Item=TeangaResult<Document>> + 'a> {
+        Box::new(self.docs.iter().map(|(_, doc)| Ok(doc.clone())))
+    }
+
+    fn iter_doc_ids<'a>(&'a self) -> Box<dyn Iterator<Item=TeangaResult<(String, Document)>> + 'a> {
+        Box::new(self.docs.iter().map(|(id, doc)| Ok((id.clone(), doc.clone()))))
+    }
+
+    fn get_meta(&self) -> &HashMap<String, LayerDesc> {
+        self.meta
+    }
+}
+
+// Map entries serialized in the order given
+struct OrderedMap<'a>(Vec<(&'a str, serde_json::Value)>);
+
+impl Serialize for OrderedMap<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in &self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+// Order the layers of a corpus that were not registered through
+// `add_layer_meta`, keeping the `preferred` order where it puts every base
+// before the layers over it and otherwise using alphabetical order
+fn seed_layer_order(meta: &HashMap<String, LayerDesc>, preferred: &[String]) -> Vec<String> {
+    let mut remaining: Vec<&String> = meta.keys().collect();
+    remaining.sort_by_key(|name| (
+        preferred.iter().position(|n| n == *name).unwrap_or(usize::MAX),
+        *name
+    ));
+    let mut order: Vec<String> = Vec::new();
+    while !remaining.is_empty() {
+        // A base outside the corpus or a cycle cannot be resolved, so fall
+        // back to the first remaining layer
+        let next = remaining.iter().position(|name| match &meta[*name].base {
+            Some(base) => order.contains(base) || !meta.contains_key(base),
+            None => true
+        }).unwrap_or(0);
+        order.push(remaining.remove(next).clone());
+    }
+    order
+}
+
+// The layer names of the `_meta` block of a Teanga YAML file, in the order
+// they are written
+fn yaml_meta_keys(yaml: &str) -> Vec<String> {
+    let lines: Vec<&str> = yaml.lines()
+        .skip_while(|line| line.trim_end() != "_meta:")
+        .skip(1)
+        .take_while(|line| line.is_empty() || line.starts_with(' '))
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let key_indent = lines.first().map_or(0, |line| indent(line));
+    lines.into_iter()
+        .filter(|line| indent(line) == key_indent)
+        .filter_map(|line| {
+            let key = line.trim_start();
+            if key.starts_with('"') {
+                // Quoted keys are written as JSON-compatible strings
+                serde_json::Deserializer::from_str(key).into_iter::<String>().next()?.ok()
+            } else {
+                key.split_once(':').map(|(key, _)| key.trim().to_string())
+            }
+        })
+        .collect()
+}
+
+// Write a JSON object from already serialized values, keeping the order of
+// the entries
+fn ordered_json_object<K: AsRef<str>>(entries: Vec<(K, String)>) -> Result<String, serde_json::Error> {
+    let mut json = String::from("{");
+    for (n, (key, value)) in entries.iter().enumerate() {
+        if n > 0 {
+            json.push(',');
+        }
+        json.push_str(&serde_json::to_string(key.as_ref())?);
+        json.push(':');
+        json.push_str(value);
+    }
+    json.push('}');
+    Ok(json)
+}
+
+// Characters that the simple tokenizer treats as part of a word
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphabetic() || ch.is_numeric()
+}
+
+// Split on whitespace only, keeping punctuation attached to words
+fn whitespace_tokenize(text: &str) -> Vec<(u32, u32)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, ch) in text.char_indices() {
+        match (ch.is_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push((s as u32, i as u32));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s as u32, text.len() as u32));
+    }
+    tokens
+}
+
+// SplitMix64 pseudo-random number generator step
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+// The number of words in a shingle and of hash functions in a MinHash signature
+const SHINGLE_SIZE: usize = 3;
+const MINHASH_PERMUTATIONS: usize = 128;
+
+// A MinHash signature of the word shingles of a text, ignoring case,
+// punctuation and whitespace
+fn minhash_signature(text: &str) -> Vec<u64> {
+    let lowered = text.to_lowercase();
+    let words: Vec<&str> = lowered.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    let mut signature = vec![u64::MAX; MINHASH_PERMUTATIONS];
+    for shingle in words.windows(SHINGLE_SIZE.min(words.len()).max(1)) {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in shingle.join(" ").bytes() {
+            hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+        for (i, slot) in signature.iter_mut().enumerate() {
+            let mut state = hash ^ (i as u64);
+            *slot = (*slot).min(splitmix64(&mut state));
+        }
+    }
+    signature
+}
+
+// The Levenshtein distance between two strings, or None if it exceeds `max`
+fn bounded_levenshtein(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        if curr.iter().min().map_or(false, |&m| m > max) {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    Some(prev[b.len()]).filter(|&d| d <= max)
+}
+
+// Replace each run of whitespace with a single space
+fn collapse_whitespace_runs(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_space = false;
+    for ch in text.chars() {
+        if ch.is_whitespace() {
+            if !in_space {
+                out.push(' ');
+            }
+            in_space = true;
+        } else {
+            out.push(ch);
+            in_space = false;
+        }
+    }
+    out
+}
+
+// Tokenize with the tokenizer named by `mode`
+fn tokenize_with_mode(text: &str, mode: &str, keep_apostrophes: bool) -> Result<Vec<(u32, u32)>, WasmError> {
+    match mode {
+        "simple" => Ok(simple_tokenize(text, keep_apostrophes)),
+        "whitespace" => Ok(whitespace_tokenize(text)),
+        _ => Err(WasmError { message: format!("Unknown tokenization mode: {}", mode) }),
+    }
+}
+
+// Parse CSV text into rows of fields. Fields may be quoted with `"`, in
+// which case they can contain commas, line breaks and doubled quotes.
+fn parse_csv(csv: &str) -> Result<Vec<Vec<String>>, WasmError> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = csv.chars().peekable();
+    let mut in_quotes = false;
+    let mut at_field_start = true;
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if at_field_start => in_quotes = true,
+            ',' => {
+                row.push(std::mem::take(&mut field));
+                at_field_start = true;
+                continue;
+            }
+            '\r' if chars.peek() == Some(&'\n') => continue,
+            // Blank lines are skipped
+            '\n' | '\r' if row.is_empty() && at_field_start => continue,
+            '\n' | '\r' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+                at_field_start = true;
+                continue;
+            }
+            _ => field.push(c),
+        }
+        at_field_start = false;
+    }
+    if in_quotes {
+        return Err(WasmError { message: "CSV has an unterminated quoted field".to_string() });
+    }
+    if !at_field_start || !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+// Split a text into chunks of at least `chunk_chars` characters, extending
+// each chunk up to the next whitespace character
+fn chunk_bounds(text: &str, chunk_chars: usize) -> Vec<(usize, usize)> {
+    let mut bounds = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let mut chars = text[start..].char_indices().skip(chunk_chars);
+        let end = chars.find(|(_, c)| c.is_whitespace())
+            .map_or(text.len(), |(i, _)| start + i);
+        bounds.push((start, end));
+        start = end;
+    }
+    bounds
+}
+
+// Call `f` with the token offsets of each chunk of a text, see
+// `tokenize_chunked`
+fn tokenize_chunks<F>(text: &str, chunk_chars: usize, mut f: F) -> Result<(), WasmError>
+    where F: FnMut(Vec<(u32, u32)>) -> Result<(), WasmError> {
+    if chunk_chars == 0 {
+        return Err(WasmError { message: "Chunk size must be positive".to_string() });
+    }
+    for (start, end) in chunk_bounds(text, chunk_chars) {
+        f(simple_tokenize(&text[start..end], false).into_iter()
+            .map(|(s, e)| (s + start as u32, e + start as u32))
+            .collect())?;
+    }
+    Ok(())
+}
+
+// Parse a JSON value as a layer of the same variant as `like`, or as any
+// variant if there is none to follow
+fn parse_layer_like(like: Option<&Layer>, value: serde_json::Value) -> Result<Layer, serde_json::Error> {
+    match like {
+        Some(Layer::Characters(_)) => serde_json::from_value(value).map(Layer::Characters),
+        Some(Layer::L1(_)) => serde_json::from_value(value).map(Layer::L1),
+        Some(Layer::L2(_)) => serde_json::from_value(value).map(Layer::L2),
+        Some(Layer::L3(_)) => serde_json::from_value(value).map(Layer::L3),
+        Some(Layer::LS(_)) => serde_json::from_value(value).map(Layer::LS),
+        Some(Layer::L1S(_)) => serde_json::from_value(value).map(Layer::L1S),
+        Some(Layer::L2S(_)) => serde_json::from_value(value).map(Layer::L2S),
+        Some(Layer::L3S(_)) => serde_json::from_value(value).map(Layer::L3S),
+        _ => serde_json::from_value(value),
+    }
+}
+
+// Keep only the entries of a layer whose position is flagged in `keep`
+fn retain_entries(layer: &Layer, keep: &[bool]) -> Layer {
+    fn retain<T: Clone>(data: &[T], keep: &[bool]) -> Vec<T> {
+        data.iter().zip(keep).filter(|(_, k)| **k).map(|(d, _)| d.clone()).collect()
+    }
+    match layer {
+        Layer::L1(data) => Layer::L1(retain(data, keep)),
+        Layer::L2(data) => Layer::L2(retain(data, keep)),
+        Layer::L3(data) => Layer::L3(retain(data, keep)),
+        Layer::LS(data) => Layer::LS(retain(data, keep)),
+        Layer::L1S(data) => Layer::L1S(retain(data, keep)),
+        Layer::L2S(data) => Layer::L2S(retain(data, keep)),
+        Layer::L3S(data) => Layer::L3S(retain(data, keep)),
+        other => other.clone(),
+    }
+}
+
+// Take the entries of a layer at the given positions, in that order
+fn select_entries(layer: &Layer, indices: &[usize]) -> Layer {
+    fn select<T: Clone>(data: &[T], indices: &[usize]) -> Vec<T> {
+        indices.iter().map(|&i| data[i].clone()).collect()
+    }
+    match layer {
+        Layer::L1(data) => Layer::L1(select(data, indices)),
+        Layer::L2(data) => Layer::L2(select(data, indices)),
+        Layer::L3(data) => Layer::L3(select(data, indices)),
+        Layer::LS(data) => Layer::LS(select(data, indices)),
+        Layer::L1S(data) => Layer::L1S(select(data, indices)),
+        Layer::L2S(data) => Layer::L2S(select(data, indices)),
+        Layer::L3S(data) => Layer::L3S(select(data, indices)),
+        other => other.clone(),
+    }
+}
+
+// Whether two entries of a layer are identical
+fn entries_equal(layer: &Layer, i: usize, j: usize) -> bool {
+    match layer {
+        Layer::L1(data) => data[i] == data[j],
+        Layer::L2(data) => data[i] == data[j],
+        Layer::L3(data) => data[i] == data[j],
+        Layer::LS(data) => data[i] == data[j],
+        Layer::L1S(data) => data[i] == data[j],
+        Layer::L2S(data) => data[i] == data[j],
+        Layer::L3S(data) => data[i] == data[j],
+        _ => false,
+    }
+}
+
+// The start and end of the entries of a span layer
+fn span_bounds(layer: &Layer) -> Option<Vec<(u32, u32)>> {
+    match layer {
+        Layer::L2(data) => Some(data.clone()),
+        Layer::L2S(data) => Some(data.iter().map(|(s, e, _)| (*s, *e)).collect()),
+        Layer::L3(data) => Some(data.iter().map(|&(s, e, _)| (s, e)).collect()),
+        Layer::L3S(data) => Some(data.iter().map(|(s, e, _, _)| (*s, *e)).collect()),
+        _ => None,
+    }
+}
+
+// An empty layer in the representation expected for its description
+fn empty_layer(desc: &LayerDesc) -> Option<Layer> {
+    let typed_link = desc.link_types.is_some();
+    let layer = match (&desc.layer_type, &desc.data) {
+        (LayerType::characters, _) => Layer::Characters(String::new()),
+        (LayerType::span, None) => Layer::L2(vec![]),
+        (LayerType::span, Some(DataType::Link)) if typed_link => Layer::L3S(vec![]),
+        (LayerType::span, Some(DataType::Link)) => Layer::L3(vec![]),
+        (LayerType::span, Some(_)) => Layer::L2S(vec![]),
+        (LayerType::seq, Some(DataType::Link)) if typed_link => Layer::L1S(vec![]),
+        (LayerType::seq, Some(DataType::Link)) => Layer::L1(vec![]),
+        (LayerType::seq, Some(_)) => Layer::LS(vec![]),
+        (LayerType::seq, None) => return None,
+        (_, None) => Layer::L1(vec![]),
+        (_, Some(DataType::Link)) if typed_link => Layer::L2S(vec![]),
+        (_, Some(DataType::Link)) => Layer::L2(vec![]),
+        (_, Some(_)) => Layer::L1S(vec![]),
+    };
+    Some(layer)
+}
+
+// The JSON Schema of a single layer in a document
+fn layer_json_schema(desc: &LayerDesc) -> serde_json::Value {
+    if desc.layer_type == LayerType::characters {
+        return serde_json::json!({ "type": "string" });
+    }
+    let index = serde_json::json!({ "type": "integer", "minimum": 0 });
+    let mut items = match desc.layer_type {
+        LayerType::span => vec![index.clone(), index.clone()],
+        LayerType::div | LayerType::element => vec![index.clone()],
+        _ => vec![],
+    };
+    match desc.data {
+        Some(DataType::String) => items.push(serde_json::json!({ "type": "string" })),
+        Some(DataType::Enum(ref values)) => items.push(serde_json::json!({ "enum": values })),
+        Some(DataType::Float) => items.push(serde_json::json!({
+            "type": "string", "pattern": "^-?[0-9]*\\.?[0-9]+([eE][-+]?[0-9]+)?$"
+        })),
+        Some(DataType::Int) => items.push(serde_json::json!({
+            "type": "string", "pattern": "^-?[0-9]+$"
+        })),
+        Some(DataType::Link) => {
+            items.push(index);
+            if let Some(ref link_types) = desc.link_types {
+                items.push(serde_json::json!({ "enum": link_types }));
+            }
+        }
+        None => {}
+    }
+    let entry = if items.len() == 1 {
+        items.pop().unwrap()
+    } else {
+        serde_json::json!({
+            "type": "array",
+            "prefixItems": items,
+            "minItems": items.len(),
+            "maxItems": items.len()
+        })
+    };
+    serde_json::json!({ "type": "array", "items": entry })
+}
+
+// Shift the indices of a layer into its base by `index_shift` and any link
+// values by `link_shift`
+fn shift_layer(
+    layer: &Layer,
+    layer_type: &LayerType,
+    index_shift: i64,
+    link_shift: i64,
+) -> Result<Layer, WasmError> {
+    let idx = |i: u32| (i as i64 + index_shift) as u32;
+    let link = |i: u32| (i as i64 + link_shift) as u32;
+    let shifted = match (layer, layer_type) {
+        (Layer::L1(data), LayerType::seq) => Layer::L1(data.iter().map(|&i| link(i)).collect()),
+        (Layer::L1(data), _) => Layer::L1(data.iter().map(|&i| idx(i)).collect()),
+        (Layer::L2(data), LayerType::span) => Layer::L2(
+            data.iter().map(|&(s, e)| (idx(s), idx(e))).collect()),
+        (Layer::L2(data), _) => Layer::L2(
+            data.iter().map(|&(i, k)| (idx(i), link(k))).collect()),
+        (Layer::L3(data), LayerType::span) => Layer::L3(
+            data.iter().map(|&(s, e, k)| (idx(s), idx(e), link(k))).collect()),
+        (Layer::L1S(data), LayerType::seq) => Layer::L1S(
+            data.iter().map(|(k, l)| (link(*k), l.clone())).collect()),
+        (Layer::L1S(data), _) => Layer::L1S(
+            data.iter().map(|(i, l)| (idx(*i), l.clone())).collect()),
+        (Layer::L2S(data), LayerType::span) => Layer::L2S(
+            data.iter().map(|(s, e, l)| (idx(*s), idx(*e), l.clone())).collect()),
+        (Layer::L2S(data), _) => Layer::L2S(
+            data.iter().map(|(i, k, l)| (idx(*i), link(*k), l.clone())).collect()),
+        (Layer::L3S(data), LayerType::span) => Layer::L3S(
+            data.iter().map(|(s, e, k, l)| (idx(*s), idx(*e), link(*k), l.clone())).collect()),
+        (Layer::LS(_), _) | (Layer::MetaLayer(_), _) => layer.clone(),
+        (_, layer_type) => return Err(WasmError {
+            message: format!("Cannot shift indices of a {} layer with this shape", layer_type)
+        }),
+    };
+    Ok(shifted)
+}
+
+// Append the entries of `other` to `layer`, failing if the shapes differ
+fn extend_layer(layer: &mut Layer, other: Layer) -> Result<(), ()> {
+    match (layer, other) {
+        (Layer::L1(a), Layer::L1(b)) => a.extend(b),
+        (Layer::L2(a), Layer::L2(b)) => a.extend(b),
+        (Layer::L3(a), Layer::L3(b)) => a.extend(b),
+        (Layer::LS(a), Layer::LS(b)) => a.extend(b),
+        (Layer::L1S(a), Layer::L1S(b)) => a.extend(b),
+        (Layer::L2S(a), Layer::L2S(b)) => a.extend(b),
+        (Layer::L3S(a), Layer::L3S(b)) => a.extend(b),
+        _ => return Err(()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens_corpus() -> SimpleCorpus {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("tokens").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus
+    }
+
+    #[test]
+    fn test_from_corpus_into_corpus() {
+        let mut corpus = tokens_corpus();
+        let id = corpus.build_doc()
+            .layer("text", "Hello world").unwrap()
+            .layer("tokens", vec![(0, 5), (6, 11)]).unwrap()
+            .layer("pos", vec!["N", "V"]).unwrap()
+            .add().unwrap();
+        let wasm = TeangaWasm::from_corpus(corpus);
+        assert_eq!(wasm.get_doc_ids().unwrap(), format!("[\"{}\"]", id));
+        assert!(wasm.verify_roundtrip().unwrap());
+        let corpus = wasm.into_corpus();
+        assert_eq!(corpus.get_docs(), vec![id]);
+    }
+
+    #[test]
+    fn test_verify_roundtrip() {
+        let mut corpus = tokens_corpus();
+        corpus.build_layer("ner").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        corpus.add_layer_meta("dep".to_string(), LayerType::element, Some("tokens".to_string()),
+            Some(DataType::Link), None, Some("tokens".to_string()), None, HashMap::new()).unwrap();
+        let mut wasm = TeangaWasm::from_corpus(corpus);
+        wasm.add_doc(r#"{"text":"Hello world","tokens":[[0,5],[6,11]],"ner":[[6,11,"LOC"]],
+            "dep":[[0,1]],"_metadata":{"source":"web"},"_score":0.25}"#).unwrap();
+        assert!(wasm.verify_roundtrip().unwrap());
+
+        wasm.set_float_precision(Some(1));
+        let err = wasm.verify_roundtrip().unwrap_err();
+        assert!(err.message.starts_with("Layer _score of document"), "{}", err.message);
+
+        let mut corpus = wasm.into_corpus();
+        let id = corpus.add_doc(vec![("text".to_string(), Layer::Characters("Cork".to_string())),
+            ("ner".to_string(), Layer::L2(vec![(0, 4)]))]).unwrap();
+        let err = TeangaWasm::from_corpus(corpus).verify_roundtrip().unwrap_err();
+        assert!(err.message.starts_with(&format!("Layer ner of document {} cannot be read", id)),
+            "{}", err.message);
+    }
+
+    #[test]
+    fn test_filter_spans() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"Hello , world","tokens":[[0,5],[6,7],[8,13]],"pos":["N","P","V"]}"#).unwrap();
+        assert_eq!(wasm.filter_spans(&id, "tokens", 2, 10).unwrap(), 1);
+        let corpus = wasm.into_corpus();
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[0]).unwrap();
+        assert_eq!(doc["tokens"], Layer::L2(vec![(0, 5), (8, 13)]));
+        assert_eq!(doc["pos"], Layer::LS(vec!["N".to_string(), "V".to_string()]));
+    }
+
+    #[test]
+    fn test_concat_docs() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let a = wasm.add_doc(r#"{"text":"Hello world","tokens":[[0,5],[6,11]],"pos":["N","V"]}"#).unwrap();
+        let b = wasm.add_doc(r#"{"text":"Big cat","tokens":[[0,3],[4,7]],"pos":["A","N"]}"#).unwrap();
+        let ids = serde_json::to_string(&vec![a, b]).unwrap();
+        let id = wasm.concat_docs(&ids, Some("text".to_string()), ". ").unwrap();
+        let corpus = wasm.into_corpus();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc["text"], Layer::Characters("Hello world. Big cat".to_string()));
+        assert_eq!(doc["tokens"], Layer::L2(vec![(0, 5), (6, 11), (13, 16), (17, 20)]));
+        assert_eq!(doc["pos"].len(), 4);
+    }
+
+    #[test]
+    fn test_tokenize_stats() {
+        let wasm = TeangaWasm::new();
+        let stats: serde_json::Value = serde_json::from_str(
+            &wasm.tokenize_stats("It costs 42 euro, ok?", "simple")).unwrap();
+        assert_eq!(stats["token_count"], 7);
+        assert_eq!(stats["punctuation_count"], 2);
+        assert_eq!(stats["numeric_count"], 1);
+        let stats: serde_json::Value = serde_json::from_str(
+            &wasm.tokenize_stats("It costs 42 euro, ok?", "whitespace")).unwrap();
+        assert_eq!(stats["token_count"], 5);
+        assert_eq!(stats["punctuation_count"], 0);
+    }
+
+    #[test]
+    fn test_append_to_layer() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"Hello big world","tokens":[[0,5],[10,15]]}"#).unwrap();
+        wasm.append_to_layer(&id, "tokens", "[[6,9]]").unwrap();
+        assert!(wasm.append_to_layer(&id, "tokens", "[4, 5]").is_err());
+        let corpus = wasm.into_corpus();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc["tokens"], Layer::L2(vec![(0, 5), (6, 9), (10, 15)]));
+
+        let mut corpus = tokens_corpus();
+        corpus.build_layer("ner").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("heads").base("tokens").layer_type(LayerType::element)
+            .data(DataType::String).add().unwrap();
+        let mut wasm = TeangaWasm::from_corpus(corpus);
+        let id = wasm.add_doc(r#"{"text":"Hello world","tokens":[[0,5],[6,11]],"ner":[[6,11,"Y"]]}"#).unwrap();
+        wasm.append_to_layer(&id, "ner", r#"[[0,5,"X"]]"#).unwrap();
+        assert!(wasm.append_to_layer(&id, "ner", "[[0,5]]").is_err());
+        wasm.append_to_layer(&id, "heads", r#"[[1,"root"]]"#).unwrap();
+        wasm.append_to_layer(&id, "heads", r#"[[0,"nsubj"]]"#).unwrap();
+        assert!(wasm.append_to_layer(&id, "heads", r#"["nsubj"]"#).is_err());
+        let corpus = wasm.into_corpus();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc["ner"], Layer::L2S(vec![(0, 5, "X".to_string()), (6, 11, "Y".to_string())]));
+        assert_eq!(doc["heads"], Layer::L1S(vec![(1, "root".to_string()), (0, "nsubj".to_string())]));
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let schema: serde_json::Value = serde_json::from_str(&wasm.json_schema()).unwrap();
+        assert_eq!(schema["properties"]["text"]["type"], "string");
+        assert_eq!(schema["properties"]["tokens"]["items"]["prefixItems"].as_array().unwrap().len(), 2);
+        assert_eq!(schema["properties"]["pos"]["items"]["type"], "string");
+    }
+
+    #[test]
+    fn test_annotations_at() {
+        let mut corpus = tokens_corpus();
+        corpus.build_layer("phrases").base("tokens").layer_type(LayerType::span).add().unwrap();
+        let mut wasm = TeangaWasm::from_corpus(corpus);
+        let id = wasm.add_doc(r#"{"text":"Hello big world","tokens":[[0,5],[6,9],[10,15]],"phrases":[[1,3]]}"#).unwrap();
+        let found: serde_json::Value = serde_json::from_str(&wasm.annotations_at(&id, 11).unwrap()).unwrap();
+        assert_eq!(found, serde_json::json!([
+            {"layer": "phrases", "index": 0, "start": 6, "end": 15, "value": null},
+            {"layer": "tokens", "index": 2, "start": 10, "end": 15, "value": null}
+        ]));
+        assert_eq!(wasm.annotations_at(&id, 5).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_concordance() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"The  big\ncat sat on the\tbig mat"}"#).unwrap();
+        let lines: serde_json::Value = serde_json::from_str(
+            &wasm.concordance(Some("text".to_string()), "big", 5, true).unwrap()).unwrap();
+        assert_eq!(lines[0], serde_json::json!({
+            "doc_id": id, "start": 5, "end": 8,
+            "left": "The ", "keyword": "big", "right": " cat "
+        }));
+        assert_eq!(lines[1]["left"], " the ");
+        assert_eq!(lines[1]["right"], " mat");
+        assert!(wasm.concordance(None, "big", 5, false).is_err());
+        wasm.set_default_char_layer("text");
+        let lines: serde_json::Value = serde_json::from_str(
+            &wasm.concordance(None, "big", 5, false).unwrap()).unwrap();
+        assert_eq!(lines[0]["right"], "\ncat ");
+    }
+
+    #[test]
+    fn test_shuffle_docs() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        for text in ["a", "b", "c", "d", "e", "f"] {
+            wasm.add_doc(&format!(r#"{{"text":"{}"}}"#, text)).unwrap();
+        }
+        let original = wasm.get_doc_ids().unwrap();
+        wasm.shuffle_docs(42).unwrap();
+        let shuffled = wasm.get_doc_ids().unwrap();
+        assert_ne!(original, shuffled);
+        let mut again = TeangaWasm::from_corpus(tokens_corpus());
+        for text in ["a", "b", "c", "d", "e", "f"] {
+            again.add_doc(&format!(r#"{{"text":"{}"}}"#, text)).unwrap();
+        }
+        again.shuffle_docs(42).unwrap();
+        assert_eq!(again.get_doc_ids().unwrap(), shuffled);
+    }
+
+    #[test]
+    fn test_to_label_studio() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"Café au lait","tokens":[[0,5],[6,8],[9,13]],"pos":["N","P","N"]}"#).unwrap();
+        let task: serde_json::Value = serde_json::from_str(
+            &wasm.to_label_studio(&id, Some("text".to_string()), "tokens", "pos").unwrap()).unwrap();
+        let result = &task["predictions"][0]["result"];
+        assert_eq!(result.as_array().unwrap().len(), 3);
+        assert_eq!(result[2]["value"], serde_json::json!({
+            "start": 8, "end": 12, "text": "lait", "labels": ["N"]
+        }));
+    }
+
+    #[test]
+    fn test_add_label_studio() {
+        let mut wasm = TeangaWasm::new();
+        let task = r#"{"data": {"text": "Café in Dublin"}, "annotations": [
+            {"result": [{"type": "labels", "value": {"start": 8, "end": 14, "labels": ["LOC"]}}]},
+            {"result": [{"type": "labels", "value": {"start": 0, "end": 4, "labels": ["ORG", "FAC"]}}]}
+        ]}"#;
+        let id = wasm.add_label_studio(task, Some("text".to_string()), "entities").unwrap();
+        let corpus = wasm.into_corpus();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc["entities"], Layer::L2S(vec![
+            (0, 5, "ORG".to_string()), (0, 5, "FAC".to_string()), (9, 15, "LOC".to_string())]));
+    }
+
+    #[test]
+    fn test_duplicate_report() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let a = wasm.add_doc(r#"{"text":"Hello world","tokens":[[0,5],[6,11]]}"#).unwrap();
+        wasm.add_doc(r#"{"text":"Hello world","tokens":[[0,11]]}"#).unwrap();
+        let c = wasm.add_doc(r#"{"text":"Hello world","tokens":[[0,5],[6,11]]}"#).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&wasm.duplicate_report()).unwrap();
+        assert_eq!(report, serde_json::json!({
+            "total": 3, "unique": 2, "duplicate_groups": [[a, c]]
+        }));
+    }
+
+    #[test]
+    fn test_check_links() {
+        let mut corpus = tokens_corpus();
+        corpus.build_layer("head").base("tokens").layer_type(LayerType::seq)
+            .data(DataType::Link).target("tokens").add().unwrap();
+        let mut wasm = TeangaWasm::from_corpus(corpus);
+        let id = wasm.add_doc(r#"{"text":"Hello world","tokens":[[0,5],[6,11]],"head":[1,2]}"#).unwrap();
+        let dangling: serde_json::Value = serde_json::from_str(&wasm.check_links()).unwrap();
+        assert_eq!(dangling, serde_json::json!([
+            {"doc_id": id, "layer": "head", "entry_index": 1, "bad_target": 2}
+        ]));
+    }
+
+    #[test]
+    fn test_search_text() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_doc(r#"{"text":"The cat saw a category of cats, cat!"}"#).unwrap();
+        let all: serde_json::Value = serde_json::from_str(
+            &wasm.search_text(Some("text".to_string()), "cat", false).unwrap()).unwrap();
+        assert_eq!(all["count"], 4);
+        let words: serde_json::Value = serde_json::from_str(
+            &wasm.search_text(Some("text".to_string()), "cat", true).unwrap()).unwrap();
+        assert_eq!(words["count"], 2);
+        assert_eq!(words["matches"][1]["start"], 32);
+    }
+
+    #[test]
+    fn test_compact_doc() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"Hello big world","tokens":[[10,15],[0,5],[6,9],[0,5]],"pos":["N","I","A","I"]}"#).unwrap();
+        wasm.compact_doc(&id).unwrap();
+        let corpus = wasm.into_corpus();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc["tokens"], Layer::L2(vec![(0, 5), (6, 9), (10, 15)]));
+        assert_eq!(doc["pos"], Layer::LS(vec!["I".to_string(), "A".to_string(), "N".to_string()]));
+    }
+
+    #[test]
+    fn test_layer_summary_csv() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_layer_meta("note, free", "characters", None, None).unwrap();
+        wasm.add_doc(r#"{"text":"a b","tokens":[[0,1],[2,3]],"pos":["N","V"]}"#).unwrap();
+        wasm.add_doc(r#"{"text":"c"}"#).unwrap();
+        let csv = wasm.layer_summary_csv();
+        assert!(csv.starts_with("name,type,doc_coverage,total_entries,base\n"), "{}", csv);
+        assert!(csv.contains("tokens,span,0.5,2,text\n"), "{}", csv);
+        assert!(csv.contains("\"note, free\",characters,0,0,\n"), "{}", csv);
+    }
+
+    #[test]
+    fn test_reindex_spans() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"the big cat sat","tokens":[[0,3],[4,7],[8,11],[12,15]],"pos":["D","A","N","V"]}"#).unwrap();
+        // "the big cat sat" -> "the cat sat down"
+        wasm.reindex_spans(&id, Some("text".to_string()),
+            r#"[{"position":4,"deleted":4,"inserted":0},{"position":11,"deleted":0,"inserted":5}]"#).unwrap();
+        assert!(wasm.reindex_spans(&id, Some("text".to_string()),
+            r#"[{"position":4294967295,"deleted":1,"inserted":0}]"#).is_err());
+        assert!(wasm.reindex_spans(&id, Some("text".to_string()),
+            r#"[{"position":0,"deleted":0,"inserted":4294967295}]"#).is_err());
+        let corpus = wasm.into_corpus();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc["tokens"], Layer::L2(vec![(0, 3), (4, 7), (8, 11)]));
+        assert_eq!(doc["pos"], Layer::LS(vec!["D".to_string(), "N".to_string(), "V".to_string()]));
+    }
+
+    #[test]
+    fn test_layers_aligned() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_layer_meta("ner", "seq", Some("tokens".to_string()), Some("string".to_string())).unwrap();
+        let id = wasm.add_doc(r#"{"text":"a b","tokens":[[0,1],[2,3]],"pos":["N","V"],"ner":["O"]}"#).unwrap();
+        assert!(wasm.layers_aligned(&id, "tokens", "pos").unwrap());
+        assert!(!wasm.layers_aligned(&id, "tokens", "ner").unwrap());
+        assert!(wasm.layers_aligned(&id, "tokens", "lemma").is_err());
+    }
+
+    #[test]
+    fn test_vocabulary_size() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_doc(r#"{"text":"The cat saw the dog","tokens":[[0,3],[4,7],[8,11],[12,15],[16,19]]}"#).unwrap();
+        wasm.add_doc(r#"{"text":"A cat","tokens":[[0,1],[2,5]]}"#).unwrap();
+        let text = || Some("text".to_string());
+        assert_eq!(wasm.vocabulary_size("tokens", text(), false).unwrap(), 6);
+        assert_eq!(wasm.vocabulary_size("tokens", text(), true).unwrap(), 5);
+        let vocabulary: serde_json::Value = serde_json::from_str(&wasm.vocabulary("tokens", text(), true).unwrap()).unwrap();
+        assert_eq!(vocabulary.as_array().unwrap().len(), 5);
+        assert_eq!(vocabulary[0], serde_json::json!({ "form": "cat", "count": 2 }));
+    }
+
+    #[test]
+    fn test_required_layers() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.set_required_layers(r#"["text"]"#).unwrap();
+        assert_eq!(wasm.validate_doc(r#"{"text":"a"}"#).unwrap(), "[]");
+        assert_eq!(wasm.validate_doc(r#"{"tokens":[[0,1]],"lemma":["a"]}"#).unwrap(),
+            r#"["Missing required layer text","Layer lemma is not described in meta"]"#);
+        wasm.add_doc(r#"{"_note":"no text"}"#).unwrap();
+        wasm.set_strict(true);
+        let err = wasm.add_doc(r#"{"_note":"no text"}"#).unwrap_err();
+        assert_eq!(err.message, "Missing required layer text");
+    }
+
+    #[test]
+    fn test_join_spans_labels() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"a b","tokens":[[0,1],[2,3]],"pos":["N","V"]}"#).unwrap();
+        assert_eq!(wasm.join_spans_labels(&id, "tokens", "pos").unwrap(), r#"[[0,1,"N"],[2,3,"V"]]"#);
+        assert!(wasm.join_spans_labels(&id, "pos", "tokens").is_err());
+    }
+
+    #[test]
+    fn test_explode_sentences() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_layer_meta("sentences", "span", Some("text".to_string()), None).unwrap();
+        let id = wasm.add_doc(r#"{"text":"Hi there. Bye.","sentences":[[0,9],[10,14]],"tokens":[[0,2],[3,8],[8,9],[10,13],[13,14]],"pos":["I","R","P","I","P"],"_source":"chat"}"#).unwrap();
+        let ids: Vec<String> = serde_json::from_str(&wasm.explode_sentences(&id, Some("text".to_string()), "sentences", true).unwrap()).unwrap();
+        assert_eq!(ids.len(), 2);
+        let corpus = wasm.into_corpus();
+        assert_eq!(corpus.get_docs(), ids);
+        let doc = corpus.get_doc_by_id(&ids[1]).unwrap();
+        assert_eq!(doc["text"], Layer::Characters("Bye.".to_string()));
+        assert_eq!(doc["tokens"], Layer::L2(vec![(0, 3), (3, 4)]));
+        assert_eq!(doc["pos"], Layer::LS(vec!["I".to_string(), "P".to_string()]));
+        assert!(doc.get("sentences").is_none());
+        assert!(doc.get("_source").is_some());
+
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_layer_meta("sents", "span", Some("tokens".to_string()), None).unwrap();
+        wasm.add_layer_meta("paras", "div", Some("tokens".to_string()), None).unwrap();
+        let id = wasm.add_doc(r#"{"text":"Dogs bark. Cats purr.","tokens":[[0,4],[5,9],[9,10],[11,15],[16,20],[20,21]],"sents":[[0,3],[3,6]],"paras":[0,3]}"#).unwrap();
+        let explode = |wasm: &mut TeangaWasm, layer: &str| -> Vec<String> {
+            let ids: Vec<String> = serde_json::from_str(&wasm.explode_sentences(&id, Some("text".to_string()), layer, false).unwrap()).unwrap();
+            ids.iter().map(|id| wasm.get_doc_by_id(id).unwrap()).collect()
+        };
+        assert_eq!(explode(&mut wasm, "sents"), vec![r#"{"text":"Dogs bark.","tokens":[[0,4],[5,9],[9,10]]}"#,
+            r#"{"text":"Cats purr.","tokens":[[0,4],[5,9],[9,10]]}"#]);
+        // Divisions run up to the start of the next one
+        assert_eq!(explode(&mut wasm, "paras"), vec![r#"{"text":"Dogs bark. ","tokens":[[0,4],[5,9],[9,10]]}"#,
+            r#"{"text":"Cats purr.","tokens":[[0,4],[5,9],[9,10]]}"#]);
+        wasm.add_layer_meta("title", "characters", None, None).unwrap();
+        wasm.add_layer_meta("heads", "span", Some("title".to_string()), None).unwrap();
+        let id = wasm.merge_doc(&id, r#"{"title":"Pets","heads":[[0,4]]}"#, false).unwrap();
+        let err = wasm.explode_sentences(&id, Some("text".to_string()), "heads", false).unwrap_err();
+        assert_eq!(err.message, "Layer heads is not based on characters layer text");
+    }
+
+    #[test]
+    fn test_content_id() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id1 = wasm.add_doc(r#"{"text":"a b","tokens":[[0,1],[2,3]]}"#).unwrap();
+        let id2 = wasm.add_doc(r#"{"text":"a b","tokens":[[0,3]]}"#).unwrap();
+        let mut other = TeangaWasm::from_corpus(tokens_corpus());
+        other.add_doc(r#"{"text":"c"}"#).unwrap();
+        let id3 = other.add_doc(r#"{"tokens":[[0,1],[2,3]],"text":"a b"}"#).unwrap();
+        let content_id = wasm.content_id(&id1).unwrap();
+        assert_eq!(content_id.len(), 16);
+        assert!(content_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(other.content_id(&id3).unwrap(), content_id);
+        assert_ne!(wasm.content_id(&id2).unwrap(), content_id);
+    }
+
+    #[test]
+    fn test_add_text() {
+        let mut wasm = TeangaWasm::new();
+        assert!(wasm.add_text("No default", None, None).is_err());
+        let id = wasm.add_text("Hello, world", Some("text".to_string()), Some("tokens".to_string())).unwrap();
+        wasm.set_default_char_layer("text");
+        wasm.add_text("No tokens", None, None).unwrap();
+        assert_eq!(wasm.get_doc_by_id(&id).unwrap(),
+            r#"{"text":"Hello, world","tokens":[[0,5],[5,6],[7,12]]}"#);
+        assert!(wasm.add_text("x", Some("tokens".to_string()), None).is_err());
+    }
+
+    #[test]
+    fn test_char_frequencies() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_doc(r#"{"text":"abba"}"#).unwrap();
+        wasm.add_doc(r#"{"text":"b\u0000c"}"#).unwrap();
+        assert_eq!(wasm.char_frequencies(Some("text".to_string())).unwrap(),
+            r#"{"b":3,"a":2,"\u0000":1,"c":1}"#);
+    }
+
+    #[test]
+    fn test_repair_spans() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"a b c","tokens":[[2,3],[1,0],[4,5]],"pos":["B","A","C"]}"#).unwrap();
+        assert_eq!(wasm.repair_spans(&id, "tokens").unwrap(), 2);
+        assert_eq!(wasm.repair_spans(&id, "tokens").unwrap(), 0);
+        let corpus = wasm.into_corpus();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc["tokens"], Layer::L2(vec![(0, 1), (2, 3), (4, 5)]));
+        assert_eq!(doc["pos"], Layer::LS(vec!["A".to_string(), "B".to_string(), "C".to_string()]));
+    }
+
+    #[test]
+    fn test_element_values() {
+        let mut corpus = tokens_corpus();
+        corpus.build_layer("marks").base("text").layer_type(LayerType::element)
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "a b").unwrap()
+            .layer("marks", vec![(0, "page"), (2, "figure")]).unwrap()
+            .add().unwrap();
+        let wasm = TeangaWasm::from_corpus(corpus);
+        assert_eq!(wasm.element_values(&id, "marks").unwrap(),
+            r#"[{"index":0,"value":"page"},{"index":2,"value":"figure"}]"#);
+        assert!(wasm.element_values(&id, "tokens").is_err());
+    }
+
+    #[test]
+    fn test_char_layer_lengths() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"Café 😀"}"#).unwrap();
+        assert_eq!(wasm.char_layer_lengths(&id, Some("text".to_string())).unwrap(),
+            r#"{"bytes":10,"chars":6,"utf16":7}"#);
+    }
+
+    #[test]
+    fn test_merge_doc() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"a b","tokens":[[0,1],[2,3]]}"#).unwrap();
+        let id = wasm.merge_doc(&id, r#"{"pos":["N","V"]}"#, false).unwrap();
+        assert!(wasm.merge_doc(&id, r#"{"pos":["V","N"]}"#, false).unwrap_err().message
+            .contains("already exists"));
+        let id = wasm.merge_doc(&id, r#"{"pos":["V","N"]}"#, true).unwrap();
+        assert_eq!(wasm.get_doc_by_id(&id).unwrap(),
+            r#"{"text":"a b","tokens":[[0,1],[2,3]],"pos":["V","N"]}"#);
+
+        wasm.set_strict(true);
+        let bare = wasm.add_doc(r#"{"text":"c","tokens":[[0,1]]}"#).unwrap();
+        wasm.set_required_layers(r#"["pos"]"#).unwrap();
+        assert_eq!(wasm.merge_doc(&bare, r#"{"_note":"x"}"#, false).unwrap_err().message,
+            "Missing required layer pos");
+        assert!(wasm.update_doc(&id, r#"{"lemma":["a","b"]}"#).unwrap_err().message
+            .contains("Layer lemma is not described in meta"));
+        wasm.merge_doc(&bare, r#"{"pos":["N"]}"#, false).unwrap();
+    }
+
+    #[test]
+    fn test_yaml_roundtrip_meta() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"a \\ \"b\"","_note":"scanned","_pages":[1,2]}"#).unwrap();
+        let yaml = wasm.to_yaml().unwrap();
+        let mut wasm2 = TeangaWasm::new();
+        wasm2.from_yaml(&yaml).unwrap();
+        let corpus = wasm.into_corpus();
+        let corpus2 = wasm2.into_corpus();
+        assert_eq!(corpus2.get_docs(), vec![id.clone()]);
+        assert_eq!(corpus2.get_doc_by_id(&id).unwrap(), corpus.get_doc_by_id(&id).unwrap());
+        assert!(matches!(corpus2.get_doc_by_id(&id).unwrap()["_note"], Layer::MetaLayer(_)));
+    }
+
+    #[test]
+    fn test_yaml_escaping() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_layer_meta("dep", "seq", Some("tokens".to_string()), Some("link".to_string())).unwrap();
+        wasm.add_layer_meta("tag", "seq", Some("tokens".to_string()), Some(r#"["- a","b: c"]"#.to_string())).unwrap();
+        let text = "- key: value # not a comment\r\n\t\u{1}\u{85}caf\u{e9} \u{1f600}";
+        let doc = serde_json::json!({"text": text, "tokens": [[0, 1]], "dep": [0], "tag": ["b: c"], "_note": "x: y"});
+        let id = wasm.add_doc(&doc.to_string()).unwrap();
+        let yaml = wasm.to_yaml().unwrap();
+        let mut other = TeangaWasm::new();
+        other.from_yaml(&yaml).unwrap();
+        let corpus = wasm.into_corpus();
+        let corpus2 = other.into_corpus();
+        assert_eq!(corpus2.get_meta(), corpus.get_meta());
+        assert_eq!(corpus2.get_docs(), vec![id.clone()]);
+        assert_eq!(corpus2.get_doc_by_id(&id).unwrap(), corpus.get_doc_by_id(&id).unwrap());
+    }
+
+    #[test]
+    fn test_span_overlap() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_layer_meta("pred", "span", Some("text".to_string()), None).unwrap();
+        let id = wasm.add_doc(r#"{"text":"New York is big","tokens":[[0,8],[12,15]],"pred":[[0,3],[12,15],[9,11]]}"#).unwrap();
+        let scores: serde_json::Value = serde_json::from_str(&wasm.span_overlap(&id, "tokens", "pred").unwrap()).unwrap();
+        assert_eq!(scores["exact"]["tp"], 1);
+        assert_eq!(scores["exact"]["fp"], 2);
+        assert_eq!(scores["exact"]["fn"], 1);
+        assert_eq!(scores["partial"]["tp"], 2);
+        assert_eq!(scores["partial"]["recall"], 1.0);
+        assert_eq!(scores["partial"]["f1"], 0.8);
+    }
+
+    #[test]
+    fn test_chunk_bounds() {
+        let text = "Hello, world! This is a longer text.";
+        let bounds = chunk_bounds(text, 8);
+        assert_eq!(bounds, vec![(0, 13), (13, 21), (21, 30), (30, 36)]);
+        let tokens: Vec<(u32, u32)> = bounds.iter()
+            .flat_map(|&(start, end)| simple_tokenize(&text[start..end], false).into_iter()
+                .map(move |(s, e)| (s + start as u32, e + start as u32)))
+            .collect();
+        assert_eq!(tokens, simple_tokenize(text, false));
+    }
+
+    #[test]
+    fn test_get_doc_resolved() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"Hi you","tokens":[[0,2],[3,6]],"pos":["I","P"]}"#).unwrap();
+        assert_eq!(wasm.get_doc_resolved(&id, Some("text".to_string())).unwrap(),
+            r#"{"text":"Hi you","tokens":[[0,2,"Hi"],[3,6,"you"]],"pos":["I","P"]}"#);
+    }
+
+    #[test]
+    fn test_duplicate_layer_keys() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let doc = r#"{"text":"a","tokens":[[0,1]],"tokens":[]}"#;
+        wasm.add_doc(doc).unwrap();
+        wasm.set_strict(true);
+        assert!(wasm.add_doc(doc).unwrap_err().message.contains("Duplicate layer tokens"));
+    }
+
+    #[test]
+    fn test_layer_extent() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"a b c d","tokens":[[2,3],[6,7],[4,5]]}"#).unwrap();
+        assert_eq!(wasm.layer_extent(&id, "tokens").unwrap(), r#"{"count":3,"max_end":7,"min_start":2}"#);
+        let id = wasm.add_doc(r#"{"text":"e"}"#).unwrap();
+        assert_eq!(wasm.layer_extent(&id, "tokens").unwrap(), "null");
+    }
+
+    #[test]
+    fn test_add_csv() {
+        let mut wasm = TeangaWasm::new();
+        let csv = "id,text,source\r\n\r\n1,\"Hello, \"\"world\"\"\",web\r\n2,\"two\nlines\",\n";
+        let pairs: Vec<serde_json::Value> = serde_json::from_str(&wasm.add_csv(csv, Some("text".to_string()), Some("id".to_string())).unwrap()).unwrap();
+        assert_eq!(pairs.iter().map(|p| p["csv_id"].as_str().unwrap()).collect::<Vec<_>>(), vec!["1", "2"]);
+        let ids: Vec<String> = pairs.iter().map(|p| p["id"].as_str().unwrap().to_string()).collect();
+        let corpus = wasm.into_corpus();
+        let doc = corpus.get_doc_by_id(&ids[0]).unwrap();
+        assert_eq!(doc["text"], Layer::Characters("Hello, \"world\"".to_string()));
+        assert_eq!(doc["_id"], Layer::MetaLayer(Some(Value::String("1".to_string()))));
+        let doc = corpus.get_doc_by_id(&ids[1]).unwrap();
+        assert_eq!(doc["text"], Layer::Characters("two\nlines".to_string()));
+        assert_eq!(doc["_metadata"], Layer::MetaLayer(Some(Value::Object(
+            HashMap::from([("source".to_string(), Value::String(String::new()))])))));
+
+        let wasm = TeangaWasm::from_corpus(corpus);
+        assert!(wasm.verify_roundtrip().unwrap());
+        for format in ["json", "yaml", "tcf"] {
+            wasm.assert_exportable(format).unwrap();
+        }
+
+        let mut wasm = TeangaWasm::new();
+        let ids: Vec<String> = serde_json::from_str(&wasm.add_csv("text,n\na,1\na,2\n", Some("text".to_string()), None).unwrap()).unwrap();
+        assert_eq!(ids.len(), 2);
+        assert!(wasm.verify_roundtrip().unwrap());
+    }
+
+    #[test]
+    fn test_rank_by_query() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let cats = wasm.add_text("cats like cats", Some("text".to_string()), Some("tokens".to_string())).unwrap();
+        let dogs = wasm.add_text("dogs like walks", Some("text".to_string()), Some("tokens".to_string())).unwrap();
+        wasm.add_text("birds sing", Some("text".to_string()), Some("tokens".to_string())).unwrap();
+        let ranked: serde_json::Value = serde_json::from_str(
+            &wasm.rank_by_query(Some("text".to_string()), "tokens", "Cats like", 5).unwrap()).unwrap();
+        let ranked = ranked.as_array().unwrap();
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0]["doc_id"], cats);
+        assert_eq!(ranked[1]["doc_id"], dogs);
+    }
+
+    #[test]
+    fn test_enum_validation() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let enum_layer = |values: &str| (Some("tokens".to_string()), Some(values.to_string()));
+        let (base, data) = enum_layer("[]");
+        assert_eq!(wasm.add_layer_meta("upos", "seq", base, data).unwrap_err().message,
+            "Enum of layer upos has no values");
+        let (base, data) = enum_layer(r#"["NOUN","VERB","NOUN","ADJ","VERB"]"#);
+        assert_eq!(wasm.add_layer_meta("upos", "seq", base, data).unwrap_err().message,
+            "Enum of layer upos repeats values: NOUN, VERB");
+        let (base, data) = enum_layer(r#"["NOUN","VERB"]"#);
+        wasm.add_layer_meta("upos", "seq", base, data).unwrap();
+    }
+
+    #[test]
+    fn test_enum_diff() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_layer_meta("upos", "seq", Some("tokens".to_string()),
+            Some(r#"["NOUN","VERB","ADJ"]"#.to_string())).unwrap();
+        assert_eq!(wasm.enum_diff("upos", r#"["VERB","PROPN","NOUN"]"#).unwrap(),
+            r#"{"only_here":["ADJ"],"only_there":["PROPN"],"shared":["NOUN","VERB"]}"#);
+        assert!(wasm.enum_diff("pos", "[]").is_err());
+        assert!(wasm.enum_diff("missing", "[]").is_err());
+    }
+
+    #[test]
+    fn test_add_docs_dry_run() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let docs = r#"[{"text":"a b","tokens":[[0,1],[2,3]]},{"text":"c","lemma":["c"]},{"text":"d","tokens":[[0,"x"]]}]"#;
+        let report: serde_json::Value = serde_json::from_str(&wasm.add_docs_dry_run(docs)).unwrap();
+        assert_eq!(report["would_add"], 0);
+        assert_eq!(report["ids"].as_array().unwrap().len(), 1);
+        assert_eq!(report["failed"].as_array().unwrap().iter().map(|f| f["index"].clone()).collect::<Vec<_>>(),
+            vec![serde_json::json!(1), serde_json::json!(2)]);
+        assert_eq!(report["problems"][0]["problems"][0], "Layer lemma is not described in meta");
+        assert!(wasm.get_doc_ids().unwrap() == "[]");
+        let docs = r#"[{"text":"a b","tokens":[[0,1],[2,3]]},{"text":"c"}]"#;
+        let report: serde_json::Value = serde_json::from_str(&wasm.add_docs_dry_run(docs)).unwrap();
+        assert_eq!(report["would_add"], 2);
+        let ids = wasm.add_docs(docs).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&ids).unwrap(), report["ids"]);
+    }
+
+    #[test]
+    fn test_offset_to_token_map() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"ab  c","tokens":[[0,2],[1,3],[4,5]]}"#).unwrap();
+        assert_eq!(wasm.offset_to_token_map(&id, "tokens", Some("text".to_string())).unwrap(),
+            "[0,0,1,-1,2]");
+    }
+
+    #[test]
+    fn test_token_gaps() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"ab cd, ef  g","tokens":[[0,2],[3,5],[11,12]]}"#).unwrap();
+        assert_eq!(wasm.token_gaps(&id, "tokens", Some("text".to_string()), false).unwrap(),
+            r#"[{"end":6,"start":5,"text":","},{"end":9,"start":7,"text":"ef"}]"#);
+        assert_eq!(wasm.token_gaps(&id, "tokens", Some("text".to_string()), true).unwrap(),
+            r#"[{"end":3,"start":2,"text":" "},{"end":11,"start":5,"text":", ef  "}]"#);
+    }
+
+    #[test]
+    fn test_layer_defaults() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_layer_meta("lang", "characters", None, None).unwrap();
+        wasm.set_layer_default("lang", r#""en""#).unwrap();
+        let id = wasm.add_doc(r#"{"text":"a"}"#).unwrap();
+        let ga = wasm.add_doc(r#"{"text":"b","lang":"ga"}"#).unwrap();
+        assert_eq!(wasm.get_doc_by_id(&id).unwrap(), r#"{"text":"a"}"#);
+        wasm.set_apply_defaults(true);
+        assert_eq!(wasm.get_doc_by_id(&id).unwrap(), r#"{"text":"a","lang":"en"}"#);
+        assert_eq!(wasm.get_doc_by_id(&ga).unwrap(), r#"{"text":"b","lang":"ga"}"#);
+        let corpus = wasm.into_corpus();
+        assert!(corpus.get_doc_by_id(&id).unwrap().get("lang").is_none());
+    }
+
+    #[test]
+    fn test_export_filtered() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_layer_meta("lang", "characters", None, None).unwrap();
+        let en = wasm.add_doc(r#"{"text":"a","lang":"en"}"#).unwrap();
+        wasm.add_doc(r#"{"text":"b","lang":"ga"}"#).unwrap();
+        wasm.add_doc(r#"{"text":"c"}"#).unwrap();
+        assert_eq!(wasm.export_filtered("lang", Some("en".to_string()), "jsonl").unwrap(),
+            "{\"text\":\"a\",\"lang\":\"en\"}\n");
+        let json: serde_json::Value = serde_json::from_str(
+            &wasm.export_filtered("lang", None, "json").unwrap()).unwrap();
+        assert_eq!(json.as_object().unwrap().len(), 3);
+        assert_eq!(json[&en]["lang"], "en");
+        let yaml = wasm.export_filtered("lang", Some("en".to_string()), "yaml").unwrap();
+        assert!(yaml.starts_with("_meta:\n"));
+        assert!(yaml.contains(&format!("{}:\n", en)));
+        assert_eq!(yaml.matches("  text: ").count(), 1);
+        assert!(wasm.export_filtered("lang", None, "xml").is_err());
+    }
+
+    #[test]
+    fn test_corpus_meta() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        assert_eq!(wasm.get_corpus_meta(), "{}");
+        assert!(wasm.set_corpus_meta("[1]").is_err());
+        wasm.set_corpus_meta(r#"{"license":"CC-BY","version":2}"#).unwrap();
+        let id = wasm.add_doc(r#"{"text":"a b"}"#).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&wasm.to_json().unwrap()).unwrap();
+        assert_eq!(json["_corpus_meta"], serde_json::json!({"license": "CC-BY", "version": 2}));
+        let mut corpus = SimpleCorpus::new();
+        teanga::read_json(wasm.to_json().unwrap().as_bytes(), &mut corpus).unwrap();
+        assert_eq!(corpus.get_docs(), vec![id.clone()]);
+        let mut other = TeangaWasm::new();
+        other.from_yaml(&wasm.to_yaml().unwrap()).unwrap();
+        assert_eq!(other.get_corpus_meta(), wasm.get_corpus_meta());
+        assert_eq!(other.corpus.get_docs(), vec![id]);
+    }
+
+    #[test]
+    fn test_dedupe_fuzzy() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let first = wasm.add_doc(r#"{"text":"The cat sat on the mat by the door today."}"#).unwrap();
+        let near = wasm.add_doc(r#"{"text":"the cat  sat on the mat, by the door today!"}"#).unwrap();
+        let other = wasm.add_doc(r#"{"text":"Dogs bark loudly at night in the city."}"#).unwrap();
+        assert!(wasm.dedupe_fuzzy(Some("text".to_string()), 1.5).is_err());
+        assert_eq!(wasm.dedupe_fuzzy(Some("text".to_string()), 0.8).unwrap(),
+            format!("[\"{}\"]", near));
+        assert_eq!(wasm.corpus.get_docs(), vec![first, other]);
+    }
+
+    #[test]
+    fn test_assert_exportable() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_doc(r#"{"text":"a \\ \"b\"\nc","tokens":[[0,1]],"pos":["X"]}"#).unwrap();
+        for format in ["json", "yaml", "tcf"] {
+            wasm.assert_exportable(format).map_err(|e| format!("{}: {}", format, e.message)).unwrap();
+        }
+        assert!(wasm.assert_exportable("xml").is_err());
+        wasm.add_doc(r#"{"text":"d","_note":"kept"}"#).unwrap();
+        wasm.assert_exportable("json").unwrap();
+        wasm.assert_exportable("tcf").unwrap();
+    }
+
+    #[test]
+    fn test_doc_index() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let a = wasm.add_doc(r#"{"text":"a"}"#).unwrap();
+        let b = wasm.add_doc(r#"{"text":"b"}"#).unwrap();
+        let c = wasm.add_doc(r#"{"text":"c"}"#).unwrap();
+        assert_eq!(wasm.doc_index(&b).unwrap(), 1);
+        assert_eq!(wasm.doc_id_at(2).unwrap(), c);
+        assert!(wasm.doc_id_at(3).is_err());
+        assert!(wasm.doc_index("missing").is_err());
+        wasm.corpus.remove_doc(&a).unwrap();
+        assert_eq!(wasm.doc_index(&c).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_update_and_remove_doc() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let a = wasm.add_doc(r#"{"text":"a b","tokens":[[0,1],[2,3]]}"#).unwrap();
+        let b = wasm.add_doc(r#"{"text":"c"}"#).unwrap();
+        assert_eq!(wasm.update_doc(&a, r#"{"tokens":[[0,3]]}"#).unwrap(), a);
+        assert_eq!(wasm.get_doc_by_id(&a).unwrap(), r#"{"text":"a b","tokens":[[0,3]]}"#);
+        let changed = wasm.update_doc(&a, r#"{"text":"a c"}"#).unwrap();
+        assert_ne!(changed, a);
+        assert_eq!(wasm.corpus.get_docs(), vec![changed.clone(), b.clone()]);
+        wasm.remove_doc(&changed).unwrap();
+        assert_eq!(wasm.corpus.get_docs(), vec![b]);
+        assert!(wasm.remove_doc(&changed).is_err());
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_doc(r#"{"text":"big world","tokens":[[0,3],[4,9]],"pos":["A","N"]}"#).unwrap();
+        wasm.add_doc(r#"{"text":"world"}"#).unwrap();
+        let stats: serde_json::Value = serde_json::from_str(&wasm.stats().unwrap()).unwrap();
+        assert_eq!(stats["documents"], 2);
+        assert_eq!(stats["characters"], 14);
+        assert_eq!(stats["layers"]["tokens"]["mean_per_doc"], 1.0);
+        assert_eq!(stats["layers"]["pos"]["values"], serde_json::json!({"A": 1, "N": 1}));
+    }
+
+    #[test]
+    fn test_set_float_precision() {
+        let mut corpus = tokens_corpus();
+        let id = corpus.add_doc(vec![
+            ("text".to_string(), Layer::Characters("Hi".to_string())),
+            ("_scores".to_string(), Layer::MetaLayer(Some(Value::Array(vec![
+                Value::Float(0.123456), Value::Float(f64::NAN),
+                Value::Float(f64::INFINITY), Value::Float(f64::NEG_INFINITY)]))))]).unwrap();
+        let mut wasm = TeangaWasm::from_corpus(corpus);
+        let doc: serde_json::Value = serde_json::from_str(&wasm.get_doc_by_id(&id).unwrap()).unwrap();
+        assert_eq!(doc["_scores"], serde_json::json!([0.123456, "NaN", "Infinity", "-Infinity"]));
+        wasm.set_float_precision(Some(2));
+        let doc: serde_json::Value = serde_json::from_str(&wasm.get_doc_by_id(&id).unwrap()).unwrap();
+        assert_eq!(doc["_scores"], serde_json::json!([0.12, "NaN", "Infinity", "-Infinity"]));
+    }
+
+    #[test]
+    fn test_relabel() {
+        let mut corpus = tokens_corpus();
+        corpus.build_layer("upos").base("tokens").layer_type(LayerType::seq)
+            .data(DataType::Enum(vec!["NN".to_string(), "VB".to_string(), "NOUN".to_string()]))
+            .add().unwrap();
+        let mut wasm = TeangaWasm::from_corpus(corpus);
+        wasm.add_doc(r#"{"text":"Dogs bark","tokens":[[0,4],[5,9]],"pos":["NN","VB"],"upos":["NN","VB"]}"#).unwrap();
+        wasm.add_doc(r#"{"text":"Cats","tokens":[[0,4]],"pos":["NN"],"upos":["NOUN"]}"#).unwrap();
+        assert_eq!(wasm.relabel("pos", r#"{"NN":"NOUN","VB":"VERB"}"#).unwrap(), 3);
+        assert_eq!(wasm.relabel("upos", r#"{"NN":"NOUN","VB":"VERB"}"#).unwrap(), 2);
+        let corpus = wasm.into_corpus();
+        assert_eq!(corpus.get_meta()["upos"].data,
+            Some(DataType::Enum(vec!["NOUN".to_string(), "VERB".to_string()])));
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[0]).unwrap();
+        assert_eq!(doc["pos"], Layer::LS(vec!["NOUN".to_string(), "VERB".to_string()]));
+        assert!(TeangaWasm::new().relabel("pos", "{}").is_err());
+    }
+
+    #[test]
+    fn test_docs_equal() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let a = wasm.add_doc(r#"{"text":"Hello world","tokens":[[0,5],[6,11]]}"#).unwrap();
+        let b = wasm.add_doc(r#"{"text":"Hello world","tokens":[[0,5],[6,11]]}"#).unwrap();
+        let c = wasm.add_doc(r#"{"text":"Hello world","tokens":[[0,11]]}"#).unwrap();
+        assert!(wasm.docs_equal(&a, &b).unwrap());
+        assert!(!wasm.docs_equal(&a, &c).unwrap());
+        assert!(wasm.layer_equal(&a, "text", &c, "text").unwrap());
+        assert!(!wasm.layer_equal(&a, "tokens", &c, "tokens").unwrap());
+        assert!(wasm.layer_equal(&a, "pos", &c, "pos").is_err());
+    }
+
+    #[test]
+    fn test_characters_layer_of_cycle() {
+        let mut corpus = tokens_corpus();
+        let mut meta = corpus.get_meta().clone();
+        meta.get_mut("text").unwrap().base = Some("pos".to_string());
+        corpus.set_meta(meta).unwrap();
+        let wasm = TeangaWasm::from_corpus(corpus);
+        assert_eq!(wasm.characters_layer_of("tokens").unwrap_err().message,
+            "Layer tokens has a cyclic base");
+    }
+
+    #[test]
+    fn test_js_getter_values() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"pos":["N"],"text":"a","tokens":[[0,1]],"_note":"x"}"#).unwrap();
+        let doc = wasm.read_doc(&id).unwrap();
+        assert_eq!(serde_json::to_string(&wasm.ordered_doc(&doc)).unwrap(),
+            wasm.get_doc_by_id(&id).unwrap());
+        assert_eq!(serde_json::to_string(&wasm.ordered_doc(&doc)).unwrap(),
+            r#"{"text":"a","tokens":[[0,1]],"pos":["N"],"_note":"x"}"#);
+        assert_eq!(wasm.meta_to_json_value(),
+            serde_json::from_str::<serde_json::Value>(&wasm.get_meta().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_progress() {
+        let progress = Progress::new(None, 1200);
+        let due: Vec<usize> = (0..=1200).filter(|&n| progress.is_due(n)).collect();
+        assert_eq!(due, vec![500, 1000, 1200]);
+        assert!(Progress::new(None, 0).is_due(0));
+        progress.report(500);
+
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_doc(r#"{"text":"a cat"}"#).unwrap();
+        wasm.add_doc(r#"{"text":"a cat"}"#).unwrap();
+        assert_eq!(wasm.to_json().unwrap(), wasm.to_json_with_progress(None).unwrap());
+        assert_eq!(wasm.duplicate_report(), wasm.duplicate_report_with_progress(None));
+        assert_eq!(wasm.search_text(Some("text".to_string()), "cat", true).unwrap(),
+            wasm.search_text_with_progress(Some("text".to_string()), "cat", true, None).unwrap());
+        assert_eq!(wasm.docs_matching_regex(Some("text".to_string()), "c.t").unwrap(),
+            wasm.docs_matching_regex_with_progress(Some("text".to_string()), "c.t", None).unwrap());
+        wasm.compact_with_progress(None).unwrap();
+    }
+
+    #[test]
+    fn test_map_docs() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let a = wasm.add_doc(r#"{"text":"a"}"#).unwrap();
+        let b = wasm.add_doc(r#"{"text":"b"}"#).unwrap();
+        let err = wasm.map_docs_with(|doc_id, _| Ok(Some(if doc_id == a {
+            r#"{"text":"x"}"#.to_string()
+        } else {
+            r#"{"text":"y","lemma":["y"]}"#.to_string()
+        }))).unwrap_err();
+        assert!(err.message.starts_with(&format!("Document {}: ", b)), "{}", err.message);
+        assert_eq!(wasm.get_doc_ids().unwrap(), serde_json::to_string(&[&a, &b]).unwrap());
+
+        wasm.map_docs_with(|doc_id, doc_json| Ok(if doc_id == b {
+            Some(doc_json.replace("\"b\"", "\"c\""))
+        } else {
+            None
+        })).unwrap();
+        let ids: Vec<String> = serde_json::from_str(&wasm.get_doc_ids().unwrap()).unwrap();
+        assert_eq!(ids[0], a);
+        assert_eq!(wasm.get_doc_by_id(&ids[1]).unwrap(), r#"{"text":"c"}"#);
+    }
+
+    #[test]
+    fn test_map_docs_identity() {
+        let mut corpus = tokens_corpus();
+        corpus.build_layer("ner").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        let mut wasm = TeangaWasm::from_corpus(corpus);
+        let a = wasm.add_doc(r#"{"text":"Hello world","ner":[[0,5,"X"]],"_metadata":{"source":"web","n":2}}"#).unwrap();
+        let b = wasm.add_doc(r#"{"text":"Cork","ner":[[0,4,"LOC"]]}"#).unwrap();
+        let before = wasm.to_json().unwrap();
+        wasm.map_docs_with(|_, doc_json| Ok(Some(doc_json))).unwrap();
+        assert_eq!(wasm.get_doc_ids().unwrap(), serde_json::to_string(&[&a, &b]).unwrap());
+        assert_eq!(wasm.to_json().unwrap(), before);
+
+        wasm.map_docs_with(|_, doc_json| Ok(Some(doc_json.replace("\"X\"", "\"Y\"")))).unwrap();
+        let ids: Vec<String> = serde_json::from_str(&wasm.get_doc_ids().unwrap()).unwrap();
+        assert_ne!(ids[0], a);
+        assert_eq!(ids[1], b);
+        let corpus = wasm.into_corpus();
+        let doc = corpus.get_doc_by_id(&ids[0]).unwrap();
+        assert_eq!(doc["ner"], Layer::L2S(vec![(0, 5, "Y".to_string())]));
+        assert_eq!(corpus.get_docs().len(), 2);
+    }
+
+    #[test]
+    fn test_docs_matching_regex() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let a = wasm.add_doc(r#"{"text":"Call 555-1234"}"#).unwrap();
+        wasm.add_doc(r#"{"text":"No number here"}"#).unwrap();
+        assert_eq!(wasm.docs_matching_regex(Some("text".to_string()), r"\d{3}-\d{4}").unwrap(),
+            serde_json::to_string(&[&a]).unwrap());
+        assert!(wasm.docs_matching_regex(Some("text".to_string()), "(").is_err());
+        assert!(wasm.docs_matching_regex(Some("tokens".to_string()), "a").is_err());
+    }
+
+    #[test]
+    fn test_used_layer_names() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        assert_eq!(wasm.used_layer_names(), "[]");
+        wasm.add_doc(r#"{"text":"a b","tokens":[[0,1],[2,3]]}"#).unwrap();
+        wasm.add_doc(r#"{"text":"c"}"#).unwrap();
+        assert_eq!(wasm.used_layer_names(), r#"["text","tokens"]"#);
+    }
+
+    #[test]
+    fn test_layer_variant() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"a b","tokens":[[0,1],[2,3]],"pos":["D","N"]}"#).unwrap();
+        assert_eq!(wasm.layer_variant(&id, "text").unwrap(), "Characters");
+        assert_eq!(wasm.layer_variant(&id, "tokens").unwrap(), "L2");
+        assert_eq!(wasm.layer_variant(&id, "pos").unwrap(), "LS");
+        assert!(wasm.layer_variant(&id, "lemma").is_err());
+    }
+
+    #[test]
+    fn test_add_seq_labels() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"a b","tokens":[[0,1],[2,3]]}"#).unwrap();
+        assert!(wasm.add_seq_labels(&id, "tokens", "lemma", r#"["a"]"#).is_err());
+        assert!(wasm.add_seq_labels(&id, "tokens", "text", r#"["a","b"]"#).is_err());
+        wasm.add_seq_labels(&id, "tokens", "lemma", r#"["a","b"]"#).unwrap();
+        wasm.add_seq_labels(&id, "tokens", "pos", r#"["D","N"]"#).unwrap();
+        let corpus = wasm.into_corpus();
+        assert_eq!(corpus.get_meta()["lemma"].base.as_deref(), Some("tokens"));
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[0]).unwrap();
+        assert_eq!(doc["lemma"], Layer::LS(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(doc["pos"], Layer::LS(vec!["D".to_string(), "N".to_string()]));
+    }
+
+    #[test]
+    fn test_layers_of_type() {
+        let mut corpus = tokens_corpus();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        let wasm = TeangaWasm::from_corpus(corpus);
+        assert_eq!(wasm.layers_of_type("span"), r#"["tokens","words"]"#);
+        assert_eq!(wasm.layers_of_type("characters"), r#"["text"]"#);
+        assert_eq!(wasm.layers_of_type("div"), "[]");
+    }
+
+    #[test]
+    fn test_for_each_layer() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let a = wasm.add_doc(r#"{"text":"a b","tokens":[[0,1],[2,3]]}"#).unwrap();
+        let b = wasm.add_doc(r#"{"text":"c"}"#).unwrap();
+        let mut seen = Vec::new();
+        wasm.for_each_layer_with(|doc_id, name, layer_json| {
+            seen.push((doc_id.to_string(), name.to_string(), layer_json));
+            Ok(true)
+        }).unwrap();
+        assert_eq!(seen, vec![
+            (a.clone(), "text".to_string(), r#""a b""#.to_string()),
+            (a, "tokens".to_string(), "[[0,1],[2,3]]".to_string()),
+            (b, "text".to_string(), r#""c""#.to_string())]);
+
+        let mut count = 0;
+        wasm.for_each_layer_with(|_, _, _| {
+            count += 1;
+            Ok(count < 2)
+        }).unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_tokenize_chunks() {
+        let text = "Hello, world! This is a longer text.";
+        let mut chunks = Vec::new();
+        tokenize_chunks(text, 8, |tokens| {
+            chunks.push(tokens);
+            Ok(())
+        }).unwrap();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[1], vec![(14, 18), (19, 21)]);
+        assert_eq!(chunks.concat(), simple_tokenize(text, false));
+        assert!(tokenize_chunks(text, 0, |_| Ok(())).is_err());
+        assert!(tokenize_chunks(text, 8, |_| Err(WasmError { message: "stop".to_string() })).is_err());
+    }
+
+    #[test]
+    fn test_trim_spans() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"Hello  big world","tokens":[[0,6],[5,7],[6,10],[11,16]],"pos":["N","X","A","N"]}"#).unwrap();
+        assert_eq!(wasm.trim_spans(&id, "tokens", Some("text".to_string())).unwrap(), 3);
+        let corpus = wasm.into_corpus();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc["tokens"], Layer::L2(vec![(0, 5), (7, 10), (11, 16)]));
+        assert_eq!(doc["pos"], Layer::LS(vec!["N".to_string(), "A".to_string(), "N".to_string()]));
+    }
+
+    #[test]
+    fn test_layer_order() {
+        let mut wasm = TeangaWasm::new();
+        wasm.add_layer_meta("text", "characters", None, None).unwrap();
+        wasm.add_layer_meta("words", "span", Some("text".to_string()), None).unwrap();
+        wasm.add_layer_meta("lemma", "seq", Some("words".to_string()), Some("string".to_string())).unwrap();
+        let id = wasm.add_doc(r#"{"lemma":["a"],"_note":"x","words":[[0,1]],"text":"a"}"#).unwrap();
+        assert_eq!(wasm.get_doc_by_id(&id).unwrap(),
+            r#"{"text":"a","words":[[0,1]],"lemma":["a"],"_note":"x"}"#);
+        let json = wasm.to_json().unwrap();
+        assert!(json.starts_with(r#"{"_meta":{"text":{"type":"characters"},"words":"#), "{}", json);
+        let mut corpus = SimpleCorpus::new();
+        teanga::read_json(json.as_bytes(), &mut corpus).unwrap();
+        assert_eq!(corpus.get_docs(), vec![id.clone()]);
+
+        let wasm = TeangaWasm::from_corpus(corpus);
+        assert_eq!(wasm.layer_order, vec!["text", "words", "lemma"]);
+        assert_eq!(wasm.get_doc_by_id(&id).unwrap(),
+            r#"{"text":"a","words":[[0,1]],"lemma":["a"],"_note":"x"}"#);
+        let mut other = TeangaWasm::new();
+        other.from_yaml("_meta:\n  words:\n    type: span\n    base: text\n  text:\n    type: characters\n  lemma:\n    type: seq\n    base: words\n    data: string\n  alpha:\n    type: characters\n").unwrap();
+        assert_eq!(other.layer_order, vec!["text", "words", "lemma", "alpha"]);
+    }
+
+    #[test]
+    fn test_span_coverage() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"ééé abcdef","tokens":[[0,4],[2,6],[8,10]]}"#).unwrap();
+        let coverage = wasm.span_coverage(&id, "tokens", Some("text".to_string())).unwrap();
+        assert!((coverage - 5.0 / 10.0).abs() < 1e-9);
+        wasm.add_doc(r#"{"text":"0123456789"}"#).unwrap();
+        let coverage = wasm.corpus_span_coverage("tokens", Some("text".to_string())).unwrap();
+        assert!((coverage - 5.0 / 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_mixed_array_error() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let err = wasm.add_doc(r#"{"text":"a b","pos":[1,"N"]}"#).unwrap_err();
+        assert_eq!(err.message, "Layer pos mixes element types: element 0 is a number but element 1 is a string");
+    }
+
+    #[test]
+    fn test_keywords() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        wasm.add_doc(r#"{"text":"The cat and the dog and a cat","tokens":[[0,3],[4,7],[8,11],[12,15],[16,19],[20,23],[24,25],[26,29]]}"#).unwrap();
+        let keywords: serde_json::Value = serde_json::from_str(
+            &wasm.keywords("tokens", Some("text".to_string()), r#"["THE","a"]"#, 2).unwrap()).unwrap();
+        assert_eq!(keywords, serde_json::json!([
+            {"form": "and", "count": 2}, {"form": "cat", "count": 2}
+        ]));
+    }
+
+    #[test]
+    fn test_tokenize_keep_apostrophes() {
+        let wasm = TeangaWasm::new();
+        assert_eq!(wasm.tokenize("it's 'x'", "simple", false).unwrap(),
+            "[[0,2],[2,3],[3,4],[5,6],[6,7],[7,8]]");
+        assert_eq!(wasm.tokenize("it's l’eau 'x'", "simple", true).unwrap(),
+            "[[0,4],[5,12],[13,14],[14,15],[15,16]]");
+        assert!(wasm.tokenize("x", "unknown", true).is_err());
+    }
+
+    #[test]
+    fn test_fuzzy_search() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"cat cart dog act","tokens":[[0,3],[4,8],[9,12],[13,16]]}"#).unwrap();
+        let found: serde_json::Value = serde_json::from_str(
+            &wasm.fuzzy_search("tokens", Some("text".to_string()), "cat", 1).unwrap()).unwrap();
+        assert_eq!(found, serde_json::json!([
+            {"doc_id": id, "index": 0, "form": "cat", "distance": 0},
+            {"doc_id": id, "index": 1, "form": "cart", "distance": 1}
+        ]));
+    }
+
+    #[test]
+    fn test_span_text_at() {
+        let mut wasm = TeangaWasm::from_corpus(tokens_corpus());
+        let id = wasm.add_doc(r#"{"text":"Café au lait","tokens":[[0,5],[6,8],[9,13]],"pos":["N","P","N"]}"#).unwrap();
+        assert_eq!(wasm.span_text_at(&id, "tokens", 0).unwrap(), "Café");
+        assert_eq!(wasm.span_text_at(&id, "pos", 2).unwrap(), "lait");
+        assert!(wasm.span_text_at(&id, "tokens", 3).unwrap_err().message.contains("out of range"));
+    }
+}
@@ -65,6 +65,12 @@ impl WriteableCorpus for ChannelCorpusSender {
         Ok(())
     }
 
+    fn merge_meta(&mut self, meta : HashMap<String, LayerDesc>) -> TeangaResult<()> {
+        crate::merge_compatible_meta(&mut self.meta, meta)?;
+        self.tx2.send(self.meta.clone()).unwrap();
+        Ok(())
+    }
+
     fn set_order(&mut self, order : Vec<String>) -> TeangaResult<()> {
         self.order = order;
         Ok(())
@@ -0,0 +1,174 @@
+//! Zstandard-compressed corpus files.
+//!
+//! A compressed file is a Cuac stream, without string compression, inside a
+//! zstd frame. It is far smaller and faster to read and write than JSON or
+//! YAML, and can also be written and read one document at a time with
+//! `CompressedWriter` and `CompressedReader`.
+//!
+//! # Examples
+//!
+//! ```
+//! use teanga::*;
+//! let mut corpus = SimpleCorpus::new();
+//! corpus.build_layer("text").add().unwrap();
+//! corpus.build_doc().layer("text", "This is a document.").unwrap().add().unwrap();
+//! let mut bytes = Vec::new();
+//! write_compressed(&mut bytes, &corpus).unwrap();
+//! let mut copy = SimpleCorpus::new();
+//! read_compressed(bytes.as_slice(), &mut copy).unwrap();
+//! assert_eq!(copy.get_docs().len(), 1);
+//! ```
+use std::collections::HashMap;
+use std::io::{BufReader, Read, Write};
+use crate::{Document, LayerDesc, ReadableCorpus, WriteableCorpus};
+use crate::cuac::{read_cuac, read_cuac_header, read_cuac_doc, write_cuac_with_config,
+    write_cuac_header_compression, write_cuac_doc, CuacConfig, CuacReadError,
+    CuacWriteError, Index, StringCompressionMethod, SupportedStringCompression};
+
+/// The zstd compression level used by `write_compressed`
+pub const DEFAULT_COMPRESSION_LEVEL : i32 = 3;
+
+/// Write the corpus as a compressed file
+///
+/// # Arguments
+///
+/// * `out` - The output stream
+/// * `corpus` - The corpus to write
+pub fn write_compressed<W : Write, C : ReadableCorpus>(
+    out : W, corpus : &C) -> Result<(), CuacWriteError> {
+    write_compressed_with_level(out, corpus, DEFAULT_COMPRESSION_LEVEL)
+}
+
+/// Write the corpus as a compressed file with a zstd compression level
+///
+/// # Arguments
+///
+/// * `out` - The output stream
+/// * `corpus` - The corpus to write
+/// * `level` - The zstd compression level, from 1 (fastest) to 22 (smallest)
+pub fn write_compressed_with_level<W : Write, C : ReadableCorpus>(
+    out : W, corpus : &C, level : i32) -> Result<(), CuacWriteError> {
+    let mut encoder = zstd::Encoder::new(out, level)?;
+    write_cuac_with_config(&mut encoder, corpus, &CuacConfig {
+        string_compression: StringCompressionMethod::None
+    })?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Read a compressed file
+///
+/// # Arguments
+///
+/// * `input` - The input stream
+/// * `corpus` - The corpus to read into
+pub fn read_compressed<R : Read, C : WriteableCorpus>(
+    input : R, corpus : &mut C) -> Result<(), CuacReadError> {
+    read_cuac(zstd::Decoder::new(input)?, corpus)
+}
+
+/// Writes a compressed file one document at a time
+pub struct CompressedWriter<W : Write> {
+    out: zstd::Encoder<'static, W>,
+    meta: HashMap<String, LayerDesc>,
+    index: Index
+}
+
+impl<W : Write> CompressedWriter<W> {
+    /// Start a compressed file by writing its header
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - The output stream
+    /// * `meta` - The metadata of the corpus
+    /// * `level` - The zstd compression level
+    pub fn new(out : W, meta : HashMap<String, LayerDesc>, level : i32) -> Result<CompressedWriter<W>, CuacWriteError> {
+        let mut out = zstd::Encoder::new(out, level)?;
+        write_cuac_header_compression(&mut out, &meta, &SupportedStringCompression::None)?;
+        Ok(CompressedWriter { out, meta, index: Index::new() })
+    }
+
+    /// Write a document
+    pub fn write_doc(&mut self, doc : Document) -> Result<(), CuacWriteError> {
+        write_cuac_doc(&mut self.out, doc, &mut self.index, &self.meta,
+            &SupportedStringCompression::None)
+    }
+
+    /// Finish the zstd frame and return the output stream
+    pub fn finish(self) -> Result<W, CuacWriteError> {
+        Ok(self.out.finish()?)
+    }
+}
+
+/// Reads the documents of a compressed file one at a time
+pub struct CompressedReader<R : Read> {
+    input: BufReader<zstd::Decoder<'static, BufReader<R>>>,
+    meta: HashMap<String, LayerDesc>,
+    string_compression: SupportedStringCompression,
+    index: Index
+}
+
+impl<R : Read> CompressedReader<R> {
+    /// Open a compressed file by reading its header
+    pub fn new(input : R) -> Result<CompressedReader<R>, CuacReadError> {
+        let mut input = BufReader::new(zstd::Decoder::new(input)?);
+        let (meta, string_compression) = read_cuac_header(&mut input)?;
+        Ok(CompressedReader { input, meta, string_compression, index: Index::new() })
+    }
+
+    /// The metadata of the corpus
+    pub fn meta(&self) -> &HashMap<String, LayerDesc> {
+        &self.meta
+    }
+}
+
+impl<R : Read> Iterator for CompressedReader<R> {
+    type Item = Result<Document, CuacReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        read_cuac_doc(&mut self.input, &self.meta, &self.index, &self.string_compression)
+            .map_err(CuacReadError::from).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Corpus, LayerType, DataType, SimpleCorpus};
+
+    #[test]
+    fn test_compressed_roundtrip() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").layer_type(LayerType::span).base("text").add().unwrap();
+        corpus.build_layer("pos").layer_type(LayerType::seq).base("tokens")
+            .data(DataType::String).add().unwrap();
+        for text in ["The cat sat", "A dog ran", "The cat ran"] {
+            corpus.build_doc()
+                .layer("text", text).unwrap()
+                .layer("tokens", vec![(0, 3), (4, 7), (8, 11)]).unwrap()
+                .layer("pos", vec!["DET", "NOUN", "VERB"]).unwrap()
+                .add().unwrap();
+        }
+        let mut bytes = Vec::new();
+        write_compressed(&mut bytes, &corpus).unwrap();
+        let mut copy = SimpleCorpus::new();
+        read_compressed(bytes.as_slice(), &mut copy).unwrap();
+        assert_eq!(copy.get_meta(), corpus.get_meta());
+        assert_eq!(copy.get_order(), corpus.get_order());
+        for id in corpus.get_order() {
+            assert_eq!(copy.get_doc_by_id(id).unwrap(), corpus.get_doc_by_id(id).unwrap());
+        }
+
+        let mut writer = CompressedWriter::new(Vec::new(), corpus.get_meta().clone(), 1).unwrap();
+        for id in corpus.get_order() {
+            writer.write_doc(corpus.get_doc_by_id(id).unwrap()).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        let reader = CompressedReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.meta(), corpus.get_meta());
+        let docs: Vec<Document> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(docs.len(), 3);
+        assert_eq!(docs[2], corpus.get_doc_by_id(&corpus.get_order()[2]).unwrap());
+    }
+}
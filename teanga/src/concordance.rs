@@ -0,0 +1,104 @@
+//! Keyword-in-context concordances.
+use crate::{IntervalIndex, ReadableCorpus, TeangaError, TeangaResult};
+
+/// An occurrence of a term with its surrounding text
+#[derive(Debug,Clone,PartialEq)]
+pub struct ConcordanceLine {
+    /// The ID of the document
+    pub doc_id: String,
+    /// The byte offset of the start of the match
+    pub start: usize,
+    /// The byte offset of the end of the match
+    pub end: usize,
+    /// The text before the match
+    pub left: String,
+    /// The matched text
+    pub keyword: String,
+    /// The text after the match
+    pub right: String
+}
+
+/// Find every occurrence of a term in a characters layer with up to
+/// `context_chars` characters of context on each side
+///
+/// # Arguments
+///
+/// * `corpus` - The corpus to search
+/// * `layer` - The characters layer to search
+/// * `term` - The exact text to find
+/// * `context_chars` - The number of characters of context on each side
+/// * `overlapping` - If given, only matches overlapping an annotation of this
+///   layer are returned
+///
+/// # Returns
+///
+/// The lines of the concordance in corpus order
+pub fn concordance<C : ReadableCorpus>(corpus : &C, layer : &str, term : &str,
+    context_chars : usize, overlapping : Option<&str>) -> TeangaResult<Vec<ConcordanceLine>> {
+    if term.is_empty() {
+        return Err(TeangaError::ModelError("Term must not be empty".to_string()));
+    }
+    let meta = corpus.get_meta();
+    let mut lines = Vec::new();
+    for res in corpus.iter_doc_ids() {
+        let (doc_id, doc) = res?;
+        let text = match doc.get(layer).and_then(|l| l.characters()) {
+            Some(text) => text,
+            None => continue
+        };
+        let spans = match overlapping {
+            Some(other) if doc.get(other).is_some() =>
+                Some(IntervalIndex::new(&doc.indexes(other, layer, meta)?)),
+            Some(_) => continue,
+            None => None
+        };
+        for (start, matched) in text.match_indices(term) {
+            let end = start + matched.len();
+            if let Some(ref spans) = spans {
+                if !spans.any_overlapping(start..end) {
+                    continue;
+                }
+            }
+            let left_start = text[..start].char_indices().rev().take(context_chars).last()
+                .map_or(start, |(i, _)| i);
+            let right_end = text[end..].char_indices().nth(context_chars)
+                .map_or(text.len(), |(i, _)| end + i);
+            lines.push(ConcordanceLine {
+                doc_id: doc_id.clone(),
+                start,
+                end,
+                left: text[left_start..start].to_string(),
+                keyword: matched.to_string(),
+                right: text[end..right_end].to_string()
+            });
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Corpus, LayerType, SimpleCorpus};
+
+    #[test]
+    fn test_concordance() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("ner").layer_type(LayerType::span).base("text").add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "Paris is in France. paris hilton").unwrap()
+            .layer("ner", vec![(0, 5), (12, 18)]).unwrap()
+            .add().unwrap();
+        let lines = corpus.concordance("text", "aris", 3, None).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1], ConcordanceLine {
+            doc_id: id.clone(), start: 21, end: 25,
+            left: ". p".to_string(), keyword: "aris".to_string(), right: " hi".to_string()
+        });
+        let lines = corpus.concordance("text", "aris", 3, Some("ner")).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!((lines[0].start, lines[0].left.as_str()), (1, "P"));
+        assert!(corpus.concordance("text", "", 3, None).is_err());
+    }
+}
@@ -0,0 +1,392 @@
+//! Reading and writing CoNLL-U treebanks.
+//!
+//! Each word line of a CoNLL-U file becomes a token of a characters layer
+//! rebuilt from the word forms, using `SpaceAfter=No` in the MISC column to
+//! decide on spacing. The words of a multiword token are joined without
+//! spaces and take the spacing of the multiword token line; otherwise
+//! multiword token ranges and empty nodes are skipped. A word with `_` as
+//! its HEAD has no dependency, and as the dependency layer must cover every
+//! word, a document with any such word is read without that layer. A new
+//! document is started at every `# newdoc` comment; a file without any such
+//! comments is read as one document per sentence.
+//!
+//! When writing, each document starts with a `# newdoc` comment and is split
+//! into sentences by the sentences layer. Columns whose layer is missing and
+//! the XPOS, FEATS and DEPS columns are written as `_`.
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use thiserror::Error;
+use crate::{Corpus, DataType, Document, Layer, LayerType, ReadableCorpus, TeangaData,
+    TeangaError, WriteableCorpus};
+
+/// The universal part-of-speech tags of Universal Dependencies
+pub const UPOS_TAGS : [&str; 17] = ["ADJ", "ADP", "ADV", "AUX", "CCONJ", "DET",
+    "INTJ", "NOUN", "NUM", "PART", "PRON", "PROPN", "PUNCT", "SCONJ", "SYM",
+    "VERB", "X"];
+
+/// The names of the layers created when reading CoNLL-U
+#[derive(Debug,Clone)]
+pub struct ConlluLayers {
+    /// The characters layer
+    pub text: String,
+    /// The span layer of words
+    pub tokens: String,
+    /// The div layer of sentences, based on the tokens
+    pub sentences: String,
+    /// The enum seq layer of universal part-of-speech tags
+    pub upos: String,
+    /// The string seq layer of lemmas
+    pub lemma: String,
+    /// The link seq layer of dependency heads, typed by relation. The root
+    /// of each sentence links to itself
+    pub dep: String
+}
+
+impl ConlluLayers {
+    /// Use the default layer names, which are the field names
+    pub fn new() -> ConlluLayers {
+        ConlluLayers {
+            text: "text".to_string(),
+            tokens: "tokens".to_string(),
+            sentences: "sentences".to_string(),
+            upos: "upos".to_string(),
+            lemma: "lemma".to_string(),
+            dep: "dep".to_string()
+        }
+    }
+}
+
+/// An error reading CoNLL-U
+#[derive(Error,Debug)]
+pub enum ConlluError {
+    /// A generic I/O Error
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// An error with the data was encountered
+    #[error("Teanga model error: {0}")]
+    Teanga(#[from] TeangaError),
+    /// A line could not be parsed
+    #[error("CoNLL-U format error on line {0}: {1}")]
+    Format(usize, String),
+}
+
+/// Read a CoNLL-U file into a corpus using the default layer names
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read from
+/// * `corpus` - The corpus to read into
+pub fn read_conllu<R: BufRead, C: Corpus>(reader: R, corpus: &mut C) -> Result<(), ConlluError> {
+    read_conllu_with_layers(reader, corpus, &ConlluLayers::new())
+}
+
+/// Read a CoNLL-U file into a corpus
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read from
+/// * `corpus` - The corpus to read into
+/// * `layers` - The names of the layers to create
+pub fn read_conllu_with_layers<R: BufRead, C: Corpus>(reader: R, corpus: &mut C,
+    layers: &ConlluLayers) -> Result<(), ConlluError> {
+    add_conllu_meta(corpus, layers)?;
+    let mut doc = ConlluDoc::default();
+    let mut sentence = Vec::new();
+    let mut seen_newdoc = false;
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            doc.add_sentence(&sentence)?;
+            sentence.clear();
+            if !seen_newdoc {
+                doc.flush(corpus, layers)?;
+            }
+        } else if line.starts_with('#') {
+            if line.trim_start_matches('#').trim_start().starts_with("newdoc") {
+                doc.add_sentence(&sentence)?;
+                sentence.clear();
+                doc.flush(corpus, layers)?;
+                seen_newdoc = true;
+            }
+        } else {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 10 {
+                return Err(ConlluError::Format(n + 1,
+                    format!("expected 10 columns but found {}", fields.len())));
+            }
+            // Empty nodes
+            if fields[0].contains('.') {
+                continue;
+            }
+            sentence.push((n + 1, fields.iter().map(|f| f.to_string()).collect::<Vec<_>>()));
+        }
+    }
+    doc.add_sentence(&sentence)?;
+    doc.flush(corpus, layers)?;
+    Ok(())
+}
+
+fn add_conllu_meta<C: Corpus>(corpus: &mut C, layers: &ConlluLayers) -> Result<(), TeangaError> {
+    let tokens = Some(layers.tokens.clone());
+    corpus.add_layer_meta(layers.text.clone(), LayerType::characters,
+        None, None, None, None, None, HashMap::new())?;
+    corpus.add_layer_meta(layers.tokens.clone(), LayerType::span,
+        Some(layers.text.clone()), None, None, None, None, HashMap::new())?;
+    corpus.add_layer_meta(layers.sentences.clone(), LayerType::div,
+        tokens.clone(), None, None, None, None, HashMap::new())?;
+    corpus.add_layer_meta(layers.upos.clone(), LayerType::seq,
+        tokens.clone(), Some(DataType::Enum(UPOS_TAGS.iter().map(|t| t.to_string()).collect())),
+        None, None, None, HashMap::new())?;
+    corpus.add_layer_meta(layers.lemma.clone(), LayerType::seq,
+        tokens.clone(), Some(DataType::String), None, None, None, HashMap::new())?;
+    corpus.add_layer_meta(layers.dep.clone(), LayerType::seq,
+        tokens.clone(), Some(DataType::Link), None, Some(layers.tokens.clone()), None, HashMap::new())?;
+    Ok(())
+}
+
+/// Write a corpus as CoNLL-U using the default layer names
+///
+/// # Arguments
+///
+/// * `writer` - The writer to write to
+/// * `corpus` - The corpus to write
+pub fn write_conllu<W: Write, C: ReadableCorpus>(writer: W, corpus: &C) -> Result<(), ConlluError> {
+    write_conllu_with_layers(writer, corpus, &ConlluLayers::new())
+}
+
+/// Write a corpus as CoNLL-U
+///
+/// # Arguments
+///
+/// * `writer` - The writer to write to
+/// * `corpus` - The corpus to write
+/// * `layers` - The layers to take each column from
+pub fn write_conllu_with_layers<W: Write, C: ReadableCorpus>(mut writer: W, corpus: &C,
+    layers: &ConlluLayers) -> Result<(), ConlluError> {
+    let meta = corpus.get_meta();
+    for res in corpus.iter_doc_ids() {
+        let (id, doc) = res?;
+        let forms = doc.text(&layers.tokens, meta)?;
+        let bounds = doc.indexes(&layers.tokens, &layers.text, meta)?;
+        let text = doc.get(&layers.text).and_then(|l| l.characters()).ok_or_else(||
+            TeangaError::LayerNotFoundError(layers.text.clone()))?;
+        let sentences = if doc.get(&layers.sentences).is_some() {
+            doc.indexes(&layers.sentences, &layers.tokens, meta)?
+        } else {
+            vec![(0, forms.len())]
+        };
+        let upos = column_values(&doc, &layers.upos, meta);
+        let lemma = column_values(&doc, &layers.lemma, meta);
+        let dep = doc.data(&layers.dep, meta).unwrap_or_default();
+        writeln!(writer, "# newdoc id = {}", id)?;
+        for (n, &(start, end)) in sentences.iter().enumerate() {
+            if start >= end {
+                continue;
+            }
+            writeln!(writer, "# sent_id = {}-{}", id, n + 1)?;
+            writeln!(writer, "# text = {}", &text[bounds[start].0..bounds[end - 1].1])?;
+            for i in start..end {
+                let (head, deprel) = match dep.get(i) {
+                    Some(TeangaData::Link(t)) => (head_column(*t as usize, i, start, end), "_".to_string()),
+                    Some(TeangaData::TypedLink(t, rel)) => (head_column(*t as usize, i, start, end), rel.clone()),
+                    _ => ("_".to_string(), "_".to_string()),
+                };
+                let misc = if i + 1 < end && bounds[i].1 == bounds[i + 1].0 { "SpaceAfter=No" } else { "_" };
+                writeln!(writer, "{}\t{}\t{}\t{}\t_\t_\t{}\t{}\t_\t{}",
+                    i - start + 1, forms[i],
+                    lemma.get(i).map_or("_", |s| s.as_str()),
+                    upos.get(i).map_or("_", |s| s.as_str()),
+                    head, deprel, misc)?;
+            }
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+// The string values of a seq layer, or nothing if the document lacks it
+fn column_values(doc: &Document, layer: &str, meta: &HashMap<String, crate::LayerDesc>) -> Vec<String> {
+    doc.data(layer, meta).unwrap_or_default().into_iter().map(|d| match d {
+        TeangaData::String(s) => s,
+        _ => "_".to_string(),
+    }).collect()
+}
+
+// The HEAD column for a link from token `i` to token `target` in the sentence
+// `start..end`. A self link is the root
+fn head_column(target: usize, i: usize, start: usize, end: usize) -> String {
+    if target == i {
+        "0".to_string()
+    } else if target >= start && target < end {
+        (target - start + 1).to_string()
+    } else {
+        "_".to_string()
+    }
+}
+
+/// The layers of a document being read
+#[derive(Default)]
+struct ConlluDoc {
+    text: String,
+    tokens: Vec<(u32, u32)>,
+    sentences: Vec<u32>,
+    upos: Vec<String>,
+    lemma: Vec<String>,
+    dep: Vec<Option<(u32, String)>>
+}
+
+impl ConlluDoc {
+    fn add_sentence(&mut self, words: &[(usize, Vec<String>)]) -> Result<(), ConlluError> {
+        if words.is_empty() {
+            return Ok(());
+        }
+        if !self.text.is_empty() {
+            self.text.push(' ');
+        }
+        let first = self.tokens.len() as u32;
+        self.sentences.push(first);
+        let word_count = words.iter().filter(|(_, fields)| !fields[0].contains('-')).count();
+        let mut space_after = false;
+        // The last word of the current multiword token and its spacing
+        let mut multiword: Option<(u32, bool)> = None;
+        for (line, fields) in words {
+            let no_space = fields[9].split('|').any(|m| m == "SpaceAfter=No");
+            if let Some((_, last)) = fields[0].split_once('-') {
+                let last: u32 = last.parse().map_err(|_| ConlluError::Format(*line,
+                    format!("ID {} is not a range", fields[0])))?;
+                multiword = Some((last, !no_space));
+                continue;
+            }
+            if space_after {
+                self.text.push(' ');
+            }
+            let start = self.text.len() as u32;
+            self.text.push_str(&fields[1]);
+            self.tokens.push((start, self.text.len() as u32));
+            self.lemma.push(fields[2].clone());
+            self.upos.push(fields[3].clone());
+            let id: u32 = fields[0].parse().map_err(|_| ConlluError::Format(*line,
+                format!("ID {} is not a number", fields[0])))?;
+            if fields[6] == "_" {
+                self.dep.push(None);
+            } else {
+                let head: u32 = fields[6].parse().map_err(|_| ConlluError::Format(*line,
+                    format!("head {} is not a number", fields[6])))?;
+                let target = if head == 0 { id } else { head };
+                if target == 0 || target as usize > word_count {
+                    return Err(ConlluError::Format(*line,
+                        format!("head {} is outside the sentence", head)));
+                }
+                self.dep.push(Some((first + target - 1, fields[7].clone())));
+            }
+            space_after = match multiword {
+                Some((last, space)) if id == last => { multiword = None; space },
+                Some(_) => false,
+                None => !no_space
+            };
+        }
+        Ok(())
+    }
+
+    fn flush<C: WriteableCorpus>(&mut self, corpus: &mut C, layers: &ConlluLayers) -> Result<(), ConlluError> {
+        if self.tokens.is_empty() {
+            return Ok(());
+        }
+        let doc = std::mem::take(self);
+        let mut content = vec![
+            (layers.text.clone(), Layer::Characters(doc.text)),
+            (layers.tokens.clone(), Layer::L2(doc.tokens)),
+            (layers.sentences.clone(), Layer::L1(doc.sentences)),
+            (layers.upos.clone(), Layer::LS(doc.upos)),
+            (layers.lemma.clone(), Layer::LS(doc.lemma)),
+        ];
+        if let Some(dep) = doc.dep.into_iter().collect::<Option<Vec<_>>>() {
+            content.push((layers.dep.clone(), Layer::L1S(dep)));
+        }
+        corpus.add_doc(content)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Corpus, ReadableCorpus, SimpleCorpus, TeangaData};
+
+    const CONLLU: &str = "# newdoc id = d1
+# text = Dogs bark.
+1\tDogs\tdog\tNOUN\tNNS\t_\t2\tnsubj\t_\t_
+2\tbark\tbark\tVERB\tVBP\t_\t0\troot\t_\tSpaceAfter=No
+3\t.\t.\tPUNCT\t.\t_\t2\tpunct\t_\t_
+
+1-2\tdon't\t_\t_\t_\t_\t_\t_\t_\t_
+1\tdo\tdo\tAUX\tVBP\t_\t3\taux\t_\tSpaceAfter=No
+2\tn't\tnot\tPART\tRB\t_\t3\tadvmod\t_\t_
+3\trun\trun\tVERB\tVB\t_\t0\troot\t_\t_
+
+";
+
+    #[test]
+    fn test_read_conllu() {
+        let mut corpus = SimpleCorpus::new();
+        read_conllu(CONLLU.as_bytes(), &mut corpus).unwrap();
+        assert_eq!(corpus.get_docs().len(), 1);
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[0]).unwrap();
+        assert_eq!(doc.get("text"), Some(&Layer::Characters("Dogs bark. don't run".to_string())));
+        assert_eq!(doc.get("sentences"), Some(&Layer::L1(vec![0, 3])));
+        assert_eq!(doc.text("tokens", corpus.get_meta()).unwrap(),
+            vec!["Dogs", "bark", ".", "do", "n't", "run"]);
+        assert_eq!(doc.data("dep", corpus.get_meta()).unwrap()[4],
+            TeangaData::TypedLink(5, "advmod".to_string()));
+    }
+
+    #[test]
+    fn test_write_conllu() {
+        let mut corpus = SimpleCorpus::new();
+        read_conllu(CONLLU.as_bytes(), &mut corpus).unwrap();
+        let mut out = Vec::new();
+        write_conllu(&mut out, &corpus).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("# text = Dogs bark.\n1\tDogs\tdog\tNOUN\t_\t_\t2\tnsubj\t_\t_\n"));
+        assert!(out.contains("2\tbark\tbark\tVERB\t_\t_\t0\troot\t_\tSpaceAfter=No\n"));
+        assert!(out.contains("# sent_id = "));
+        let mut corpus2 = SimpleCorpus::new();
+        read_conllu(out.as_bytes(), &mut corpus2).unwrap();
+        assert_eq!(corpus2.get_docs(), corpus.get_docs());
+    }
+
+    #[test]
+    fn test_read_conllu_per_sentence() {
+        let mut layers = ConlluLayers::new();
+        layers.upos = "pos".to_string();
+        let mut corpus = SimpleCorpus::new();
+        read_conllu_with_layers(CONLLU.replace("# newdoc id = d1\n", "").as_bytes(),
+            &mut corpus, &layers).unwrap();
+        assert_eq!(corpus.get_docs().len(), 2);
+        assert!(corpus.get_meta().contains_key("pos"));
+        assert!(read_conllu("1\tDogs\tdog\tNOUN\n".as_bytes(), &mut SimpleCorpus::new()).is_err());
+    }
+
+    #[test]
+    fn test_read_conllu_multiword_and_missing_heads() {
+        let mut corpus = SimpleCorpus::new();
+        read_conllu("1-2\tdon't\t_\t_\t_\t_\t_\t_\t_\tSpaceAfter=No
+1\tdo\tdo\tAUX\tVBP\t_\t_\t_\t_\t_
+2\tn't\tnot\tPART\tRB\t_\t_\t_\t_\t_
+3\t!\t!\tPUNCT\t.\t_\t_\t_\t_\t_
+
+1\tGo\tgo\tVERB\tVB\t_\t0\troot\t_\t_
+2\tnow\tnow\tADV\tRB\t_\t1\tadvmod\t_\t_
+".as_bytes(), &mut corpus).unwrap();
+        let docs = corpus.get_docs();
+        assert_eq!(docs.len(), 2);
+        let doc = corpus.get_doc_by_id(&docs[0]).unwrap();
+        assert_eq!(doc.get("text"), Some(&Layer::Characters("don't!".to_string())));
+        assert!(doc.get("dep").is_none());
+        let doc = corpus.get_doc_by_id(&docs[1]).unwrap();
+        assert_eq!(doc.get("text"), Some(&Layer::Characters("Go now".to_string())));
+        assert_eq!(doc.data("dep", corpus.get_meta()).unwrap()[1],
+            TeangaData::TypedLink(0, "advmod".to_string()));
+    }
+}
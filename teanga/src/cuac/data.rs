@@ -19,7 +19,7 @@ impl CuacData {
     pub fn from_iter<'a, I>(iter : I, ld : &LayerDesc,
         idx : &mut Index) -> CuacResult<CuacData> where I : Iterator<Item = &'a String> {
         match ld.data {
-            Some(DataType::String) => {
+            Some(DataType::String) | Some(DataType::Float) | Some(DataType::Int) => {
                 let v = iter.map(|s| idx.idx(&s)).collect();
                 Ok(CuacData::String(v))
             }
@@ -81,7 +81,7 @@ impl CuacData {
 
     pub fn from_bytes<S : StringCompression>(data : &[u8], ld : &LayerDesc, s: &S) -> CuacResult<(CuacData, usize)> {
         match ld.data {
-            Some(DataType::String) => {
+            Some(DataType::String) | Some(DataType::Float) | Some(DataType::Int) => {
                 let (v, len) = bytes_to_index_results(data, s)?;
                 Ok((CuacData::String(v), len))
             }
@@ -101,7 +101,7 @@ impl CuacData {
 
     pub fn from_reader<R: BufRead, S : StringCompression>(input : &mut R, ld : &LayerDesc, s : &S) -> CuacResult<CuacData> {
         match ld.data {
-            Some(DataType::String) => {
+            Some(DataType::String) | Some(DataType::Float) | Some(DataType::Int) => {
                 let v = reader_to_index_results(input, s)?;
                 Ok(CuacData::String(v))
             }
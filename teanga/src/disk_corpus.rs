@@ -263,6 +263,11 @@ impl <DB : DBImpl> WriteableCorpus for DiskCorpus<DB> {
         Ok(())
         
     }
+
+    fn merge_meta(&mut self, meta : HashMap<String, LayerDesc>) -> TeangaResult<()> {
+        merge_compatible_meta(&mut self.meta, meta)
+    }
+
     fn set_order(&mut self, order : Vec<String>) -> TeangaResult<()> {
         self.order = order;
         Ok(())
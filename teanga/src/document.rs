@@ -1,9 +1,10 @@
 //! Documents in the corpus.
 use std::collections::HashMap;
-use crate::layer::{Layer, IntoLayer, LayerDesc, TeangaData};
+use crate::layer::{Layer, IntoLayer, LayerDesc, LayerType, TeangaData};
+use crate::interval::IntervalIndex;
 use serde::{Deserialize, Serialize};
 use crate::{Corpus, TeangaResult, TeangaError};
-use std::ops::Index;
+use std::ops::{Index, Range};
 
 /// Anything that can be understood as a document content
 pub trait DocumentContent<D> : IntoIterator<Item=(String, D)> where D : IntoLayer {
@@ -82,7 +83,9 @@ impl Document {
     }
 
     /// Get the text that is indexed by a particular layer
-    /// divided by the annotations in this layer
+    /// divided by the annotations in this layer. Layers are resolved
+    /// through their bases down to the characters layer, so a seq layer
+    /// gives the text of each element of the span layer it is based on
     ///
     /// # Arguments
     ///
@@ -98,9 +101,16 @@ impl Document {
         if let Some(layer_desc) = meta.get(layer) {
             let mut char_layer = layer;
             let mut char_layer_desc = layer_desc;
-            while char_layer_desc.base.is_some() {
-                char_layer = char_layer_desc.base.as_ref().unwrap();
-                char_layer_desc = meta.get(char_layer).unwrap();
+            let mut depth = 0;
+            while let Some(base) = char_layer_desc.base.as_ref() {
+                depth += 1;
+                if depth > meta.len() {
+                    return Err(TeangaError::ModelError(
+                        format!("Layer {} has a cyclic base", layer)));
+                }
+                char_layer = base;
+                char_layer_desc = meta.get(char_layer).ok_or_else(||
+                    TeangaError::LayerNotFoundError(char_layer.to_string()))?;
             }
             if let Some(character_layer) = self.content.get(char_layer) {
                 if char_layer == layer {
@@ -186,6 +196,37 @@ impl Document {
         }
     }
 
+    /// Build an interval index over the spans of a layer in its characters
+    /// layer, for repeated overlap queries on this document
+    ///
+    /// # Arguments
+    ///
+    /// * `layer` - The layer to index
+    /// * `meta` - The metadata for the document
+    pub fn interval_index(&self, layer: &str,
+        meta : &HashMap<String, LayerDesc>)
+        -> TeangaResult<IntervalIndex> {
+        let char_layer = characters_layer(layer, meta)?;
+        Ok(IntervalIndex::new(&self.indexes(layer, &char_layer, meta)?))
+    }
+
+    /// Find the annotations of a layer that overlap a range of characters
+    ///
+    /// # Arguments
+    ///
+    /// * `layer` - The layer to search
+    /// * `range` - The range in the characters layer
+    /// * `meta` - The metadata for the document
+    ///
+    /// # Returns
+    ///
+    /// The positions of the overlapping annotations in the layer
+    pub fn annotations_overlapping(&self, layer: &str, range: Range<usize>,
+        meta : &HashMap<String, LayerDesc>)
+        -> TeangaResult<Vec<usize>> {
+        Ok(self.interval_index(layer, meta)?.overlapping(range))
+    }
+
     /// Get the names of layers in this document
     pub fn keys(&self) -> Vec<String> {
         self.content.keys().cloned().collect()
@@ -276,6 +317,20 @@ impl<'a, C : Corpus> DocumentBuilder<'a, C> {
     }
 }
 
+// Follow the bases of a layer down to its characters layer
+pub(crate) fn characters_layer(layer : &str, meta : &HashMap<String, LayerDesc>) -> TeangaResult<String> {
+    let mut name = layer;
+    for _ in 0..=meta.len() {
+        let desc = meta.get(name).ok_or_else(|| TeangaError::LayerNotFoundError(name.to_string()))?;
+        if desc.layer_type == LayerType::characters {
+            return Ok(name.to_string());
+        }
+        name = desc.base.as_deref().ok_or_else(|| TeangaError::ModelError(
+            format!("Layer {} has no base", name)))?;
+    }
+    Err(TeangaError::ModelError(format!("Layer {} has a cyclic base", layer)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -296,18 +351,48 @@ mod tests {
             .layer_type(LayerType::span)
             .data(DataType::String)
             .add().unwrap();
+        corpus.build_layer("pos")
+            .base("tokens")
+            .layer_type(LayerType::seq)
+            .data(DataType::String)
+            .add().unwrap();
         let doc = corpus.build_doc()
             .layer("text", "The White House is in Washington.").unwrap()
             .layer("tokens", vec![
                 (0, 3), (4, 9), (10, 15), (16, 18), (19,21), (22,32), (32,33)]).unwrap()
             .layer("entities", vec![
                 (1,3,"LOC"), (5,6,"ORG")]).unwrap()
+            .layer("pos", vec!["DET", "PROPN", "PROPN", "AUX", "ADP", "PROPN", "PUNCT"]).unwrap()
             .add().unwrap();
         let doc = corpus.get_doc_by_id(&doc).unwrap();
         assert_eq!(doc.text("text", corpus.get_meta()).unwrap(), vec!["The White House is in Washington."]);
         assert_eq!(doc.text("tokens", corpus.get_meta()).unwrap(), vec!["The", "White", "House", "is", "in", "Washington", "."]);
         eprintln!("{:?}", doc.indexes("entities", "text", corpus.get_meta()));
         assert_eq!(doc.text("entities", corpus.get_meta()).unwrap(), vec!["White House", "Washington"]);
+        assert_eq!(doc.text("pos", corpus.get_meta()).unwrap(), doc.text("tokens", corpus.get_meta()).unwrap());
+        let mut meta = corpus.get_meta().clone();
+        meta.get_mut("tokens").unwrap().base = Some("missing".to_string());
+        assert!(doc.text("entities", &meta).is_err());
+        meta.get_mut("tokens").unwrap().base = Some("entities".to_string());
+        assert!(doc.text("entities", &meta).is_err());
+
+        assert_eq!(doc.annotations_overlapping("entities", 8..12, corpus.get_meta()).unwrap(), vec![0]);
+        assert_eq!(doc.annotations_overlapping("tokens", 8..12, corpus.get_meta()).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_div_over_tokens() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("sentences").base("tokens").layer_type(LayerType::div).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "Dogs bark. Cats purr.").unwrap()
+            .layer("tokens", vec![(0, 4), (5, 9), (9, 10), (11, 15), (16, 20), (20, 21)]).unwrap()
+            .layer("sentences", vec![0, 3]).unwrap()
+            .add().unwrap();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc.indexes("sentences", "text", corpus.get_meta()).unwrap(), vec![(0, 11), (11, 21)]);
     }
 }
 
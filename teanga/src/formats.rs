@@ -0,0 +1,6 @@
+//! Conversion to and from other annotation formats.
+pub mod brat;
+pub mod label_studio;
+pub mod nif;
+pub mod web_annotation;
+pub mod webanno;
@@ -0,0 +1,288 @@
+//! Reading and writing brat standoff annotations.
+//!
+//! A brat document is a pair of a `.txt` file with the text and an `.ann`
+//! file with the annotations. Text-bound annotations (`T` lines) become a
+//! span layer over the characters layer, labelled with their type, and
+//! relations (`R` lines) become an element layer over the entities, linking
+//! the first argument to the second and typed by the relation. Discontinuous
+//! entities are read as a single span from their first to their last
+//! fragment. Events, attributes, normalizations and notes are skipped, as
+//! are relations whose arguments are not entities.
+//!
+//! brat counts offsets in characters, while Teanga spans count bytes, so
+//! offsets are converted in both directions.
+//!
+//! # Examples
+//!
+//! ```
+//! use teanga::*;
+//! use teanga::formats::brat::{read_brat, write_brat};
+//! let mut corpus = SimpleCorpus::new();
+//! let id = read_brat("Alice met Bob.", "T1\tPerson 0 5\tAlice\nT2\tPerson 10 13\tBob\n\
+//!     R1\tMet Arg1:T1 Arg2:T2\n", &mut corpus).unwrap();
+//! let doc = corpus.get_doc_by_id(&id).unwrap();
+//! assert_eq!(doc.text("entities", corpus.get_meta()).unwrap(), vec!["Alice", "Bob"]);
+//! let (text, ann) = write_brat(&doc, corpus.get_meta()).unwrap();
+//! assert_eq!(text, "Alice met Bob.");
+//! assert!(ann.ends_with("R1\tMet Arg1:T1 Arg2:T2\n"));
+//! ```
+use std::collections::HashMap;
+use thiserror::Error;
+use crate::{Corpus, DataType, Document, Layer, LayerDesc, LayerType, TeangaData, TeangaError};
+
+/// The type written for entities without a label
+pub const DEFAULT_ENTITY_TYPE : &str = "Entity";
+/// The type written for relations without a link type
+pub const DEFAULT_RELATION_TYPE : &str = "Relation";
+
+/// The names of the layers used for brat annotations
+#[derive(Debug,Clone)]
+pub struct BratLayers {
+    /// The characters layer
+    pub text: String,
+    /// The span layer of entities, labelled with their type
+    pub entities: String,
+    /// The element layer of relations between entities, typed by relation
+    pub relations: String
+}
+
+impl BratLayers {
+    /// Use the default layer names: `text`, `entities` and `relations`
+    pub fn new() -> BratLayers {
+        BratLayers {
+            text: "text".to_string(),
+            entities: "entities".to_string(),
+            relations: "relations".to_string()
+        }
+    }
+}
+
+impl Default for BratLayers {
+    fn default() -> BratLayers {
+        BratLayers::new()
+    }
+}
+
+/// An error reading or writing brat
+#[derive(Error,Debug)]
+pub enum BratError {
+    /// An error with the data was encountered
+    #[error("Teanga model error: {0}")]
+    Teanga(#[from] TeangaError),
+    /// A line of the annotation file could not be parsed
+    #[error("brat format error on line {0}: {1}")]
+    Format(usize, String),
+}
+
+/// Read a brat document into a corpus using the default layer names
+///
+/// # Arguments
+///
+/// * `text` - The contents of the `.txt` file
+/// * `ann` - The contents of the `.ann` file
+/// * `corpus` - The corpus to read into
+///
+/// # Returns
+///
+/// The ID of the new document
+pub fn read_brat<C: Corpus>(text: &str, ann: &str, corpus: &mut C) -> Result<String, BratError> {
+    read_brat_with_layers(text, ann, corpus, &BratLayers::new())
+}
+
+/// Read a brat document into a corpus
+///
+/// # Arguments
+///
+/// * `text` - The contents of the `.txt` file
+/// * `ann` - The contents of the `.ann` file
+/// * `corpus` - The corpus to read into
+/// * `layers` - The names of the layers to create
+///
+/// # Returns
+///
+/// The ID of the new document
+pub fn read_brat_with_layers<C: Corpus>(text: &str, ann: &str, corpus: &mut C,
+    layers: &BratLayers) -> Result<String, BratError> {
+    add_brat_meta(corpus, layers)?;
+    // The byte offset of each character, and of the end of the text
+    let offsets: Vec<usize> = text.char_indices().map(|(i, _)| i)
+        .chain(std::iter::once(text.len())).collect();
+    let mut entities = Vec::new();
+    let mut relations = Vec::new();
+    for (n, line) in ann.lines().enumerate() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if line.starts_with('T') {
+            if fields.len() < 2 {
+                return Err(BratError::Format(n + 1, "expected a tab after the ID".to_string()));
+            }
+            let (label, ranges) = fields[1].split_once(' ').ok_or_else(||
+                BratError::Format(n + 1, "expected a type and offsets".to_string()))?;
+            let mut start = usize::MAX;
+            let mut end = 0;
+            for range in ranges.split(';') {
+                let (s, e) = range.split_once(' ').and_then(|(s, e)|
+                    Some((s.parse::<usize>().ok()?, e.parse::<usize>().ok()?)))
+                    .ok_or_else(|| BratError::Format(n + 1, format!("bad offsets {}", range)))?;
+                if s > e || e >= offsets.len() {
+                    return Err(BratError::Format(n + 1,
+                        format!("offsets {} are out of range of the text", range)));
+                }
+                start = start.min(s);
+                end = end.max(e);
+            }
+            entities.push((offsets[start] as u32, offsets[end] as u32, label.to_string(), fields[0]));
+        } else if line.starts_with('R') {
+            let args: Vec<&str> = fields.get(1).map(|f| f.split(' ').collect()).unwrap_or_default();
+            if args.len() != 3 {
+                return Err(BratError::Format(n + 1, "expected a type and two arguments".to_string()));
+            }
+            let arg = |a: &str| a.split_once(':').map(|(_, id)| id.to_string()).ok_or_else(||
+                BratError::Format(n + 1, format!("bad argument {}", a)));
+            relations.push((args[0].to_string(), arg(args[1])?, arg(args[2])?));
+        }
+    }
+    entities.sort_by_key(|&(start, end, _, _)| (start, end));
+    let index: HashMap<&str, u32> = entities.iter().enumerate()
+        .map(|(i, e)| (e.3, i as u32)).collect();
+    let relations: Vec<(u32, u32, String)> = relations.into_iter().filter_map(|(label, arg1, arg2)|
+        Some((*index.get(arg1.as_str())?, *index.get(arg2.as_str())?, label))).collect();
+    let mut content = vec![
+        (layers.text.clone(), Layer::Characters(text.to_string())),
+        (layers.entities.clone(), Layer::L2S(entities.into_iter()
+            .map(|(start, end, label, _)| (start, end, label)).collect()))];
+    if !relations.is_empty() {
+        content.push((layers.relations.clone(), Layer::L2S(relations)));
+    }
+    Ok(corpus.add_doc(content)?)
+}
+
+fn add_brat_meta<C: Corpus>(corpus: &mut C, layers: &BratLayers) -> Result<(), TeangaError> {
+    corpus.merge_meta(HashMap::from([
+        (layers.text.clone(), LayerDesc::new(&layers.text, LayerType::characters,
+            None, None, None, None, None, HashMap::new())?),
+        (layers.entities.clone(), LayerDesc::new(&layers.entities, LayerType::span,
+            Some(layers.text.clone()), Some(DataType::String), None, None, None, HashMap::new())?),
+        (layers.relations.clone(), LayerDesc::new(&layers.relations, LayerType::element,
+            Some(layers.entities.clone()), Some(DataType::Link), None,
+            Some(layers.entities.clone()), None, HashMap::new())?)]))
+}
+
+/// Write a document as brat using the default layer names
+///
+/// # Arguments
+///
+/// * `doc` - The document to write
+/// * `meta` - The metadata of the corpus
+///
+/// # Returns
+///
+/// The contents of the `.txt` and `.ann` files
+pub fn write_brat(doc: &Document, meta: &HashMap<String, LayerDesc>) -> Result<(String, String), BratError> {
+    write_brat_with_layers(doc, meta, &BratLayers::new())
+}
+
+/// Write a document as brat. Entities without a string label are written
+/// with the type `Entity` and untyped relations with the type `Relation`
+///
+/// # Arguments
+///
+/// * `doc` - The document to write
+/// * `meta` - The metadata of the corpus
+/// * `layers` - The names of the layers to write
+///
+/// # Returns
+///
+/// The contents of the `.txt` and `.ann` files
+pub fn write_brat_with_layers(doc: &Document, meta: &HashMap<String, LayerDesc>,
+    layers: &BratLayers) -> Result<(String, String), BratError> {
+    let text = doc.get(&layers.text).and_then(|l| l.characters()).ok_or_else(||
+        TeangaError::LayerNotFoundError(layers.text.clone()))?;
+    let mut ann = String::new();
+    if doc.get(&layers.entities).is_none() {
+        return Ok((text.to_string(), ann));
+    }
+    // The character offset of each byte offset on a character boundary
+    let mut offsets = vec![0; text.len() + 1];
+    for (n, (i, _)) in text.char_indices().enumerate() {
+        offsets[i] = n;
+    }
+    offsets[text.len()] = text.chars().count();
+    let spans = doc.indexes(&layers.entities, &layers.text, meta)?;
+    let labels = doc.data(&layers.entities, meta).unwrap_or_default();
+    for (i, &(start, end)) in spans.iter().enumerate() {
+        let label = match labels.get(i) {
+            Some(TeangaData::String(s)) => s.as_str(),
+            _ => DEFAULT_ENTITY_TYPE
+        };
+        let covered = text.get(start..end).ok_or_else(|| TeangaError::ModelError(
+            format!("Entity ({}, {}) is not on a character boundary", start, end)))?;
+        ann.push_str(&format!("T{}\t{} {} {}\t{}\n", i + 1, label, offsets[start], offsets[end],
+            covered.replace(['\n', '\r'], " ")));
+    }
+    let relations: Vec<(u32, u32, String)> = match doc.get(&layers.relations) {
+        Some(Layer::L2S(v)) => v.clone(),
+        Some(Layer::L2(v)) => v.iter().map(|&(i, j)| (i, j, DEFAULT_RELATION_TYPE.to_string())).collect(),
+        Some(_) => return Err(TeangaError::ModelError(
+            format!("Layer {} is not a layer of links between entities", layers.relations)).into()),
+        None => Vec::new()
+    };
+    for (n, (i, j, label)) in relations.into_iter().enumerate() {
+        ann.push_str(&format!("R{}\t{} Arg1:T{} Arg2:T{}\n", n + 1, label, i + 1, j + 1));
+    }
+    Ok((text.to_string(), ann))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ReadableCorpus, SimpleCorpus};
+
+    const TEXT: &str = "Éamon lives in New\nYork.";
+    const ANN: &str = "T2\tLocation 15 18;19 23\tNew York
+T1\tPerson 0 5\tÉamon
+R1\tLivesIn Arg1:T1 Arg2:T2
+A1\tNegated T1
+E1\tMove:T1
+R2\tCause Arg1:E1 Arg2:T2
+#1\tAnnotatorNotes T1\tA note
+";
+
+    #[test]
+    fn test_brat_roundtrip() {
+        let mut corpus = SimpleCorpus::new();
+        let id = read_brat(TEXT, ANN, &mut corpus).unwrap();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc.get("entities"), Some(&Layer::L2S(vec![
+            (0, 6, "Person".to_string()), (16, 24, "Location".to_string())])));
+        assert_eq!(doc.text("entities", corpus.get_meta()).unwrap(), vec!["Éamon", "New\nYork"]);
+        assert_eq!(doc.data("relations", corpus.get_meta()).unwrap(),
+            vec![TeangaData::TypedLink(1, "LivesIn".to_string())]);
+        assert!(crate::validate_corpus(&corpus).unwrap().is_empty());
+
+        let (text, ann) = write_brat(&doc, corpus.get_meta()).unwrap();
+        assert_eq!(text, TEXT);
+        assert_eq!(ann, "T1\tPerson 0 5\tÉamon\nT2\tLocation 15 23\tNew York\n\
+            R1\tLivesIn Arg1:T1 Arg2:T2\n");
+
+        assert!(matches!(read_brat(TEXT, "T1\tPerson 0 50\tÉamon", &mut corpus),
+            Err(BratError::Format(1, _))));
+    }
+
+    #[test]
+    fn test_brat_declared_layers() {
+        let mut corpus = SimpleCorpus::new();
+        read_brat(TEXT, ANN, &mut corpus).unwrap();
+        read_brat(TEXT, ANN, &mut corpus).unwrap();
+        assert_eq!(corpus.get_docs().len(), 2);
+
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("entities").layer_type(LayerType::div).base("text").add().unwrap();
+        match read_brat(TEXT, ANN, &mut corpus) {
+            Err(BratError::Teanga(TeangaError::ModelError(msg))) =>
+                assert!(msg.starts_with("Conflicting definitions of layer entities"), "{}", msg),
+            r => panic!("Unexpected result {:?}", r)
+        }
+        assert!(corpus.get_docs().is_empty());
+    }
+}
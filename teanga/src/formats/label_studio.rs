@@ -0,0 +1,313 @@
+//! Exporting documents as Label Studio tasks and merging the completed
+//! annotations back.
+//!
+//! Each document becomes a task with the text in `data.text` and the ID of
+//! the document in `data.teanga_id`. The spans of a layer, if the document
+//! has them, are sent as predictions. When reading, the labelled results of
+//! the annotations of each task become a new span layer of the document
+//! with that ID, or of a new document if the task has no ID. Label Studio
+//! counts offsets in code points, so offsets are converted in both
+//! directions.
+//!
+//! # Examples
+//!
+//! ```
+//! use teanga::*;
+//! use teanga::formats::label_studio::{read_label_studio, LabelStudioLayers};
+//! let mut corpus = SimpleCorpus::new();
+//! corpus.build_layer("text").add().unwrap();
+//! let id = corpus.build_doc().layer("text", "Visit Dublin").unwrap().add().unwrap();
+//! let tasks = format!(r#"[{{"data": {{"text": "Visit Dublin", "teanga_id": "{}"}},
+//!     "annotations": [{{"result": [{{"value": {{"start": 6, "end": 12, "labels": ["LOC"]}}}}]}}]}}]"#, id);
+//! let layers = LabelStudioLayers::new("text", "entities");
+//! read_label_studio(tasks.as_bytes(), &mut corpus, &layers).unwrap();
+//! let doc = corpus.get_doc_by_id(&id).unwrap();
+//! assert_eq!(doc.text("entities", corpus.get_meta()).unwrap(), vec!["Dublin"]);
+//! ```
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use serde_json::{json, Value};
+use thiserror::Error;
+use crate::{Corpus, DataType, Document, Layer, LayerDesc, LayerType, ReadableCorpus,
+    TeangaData, TeangaError, TeangaResult};
+
+/// The names of the layers exchanged with Label Studio
+#[derive(Debug,Clone)]
+pub struct LabelStudioLayers {
+    /// The characters layer sent as the text of the task
+    pub text: String,
+    /// The span layer of annotations over the text
+    pub spans: String,
+    /// The layer giving the labels of the spans when exporting, if they are
+    /// not the data of the span layer itself
+    pub labels: Option<String>
+}
+
+impl LabelStudioLayers {
+    /// Exchange the given characters layer and span layer
+    pub fn new(text: &str, spans: &str) -> LabelStudioLayers {
+        LabelStudioLayers {
+            text: text.to_string(),
+            spans: spans.to_string(),
+            labels: None
+        }
+    }
+
+    /// Take the labels of the spans from another layer
+    pub fn labels(mut self, labels: &str) -> Self {
+        self.labels = Some(labels.to_string());
+        self
+    }
+}
+
+/// An error reading or writing Label Studio JSON
+#[derive(Error,Debug)]
+pub enum LabelStudioError {
+    /// A generic I/O Error
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The JSON could not be read or written
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    /// An error with the data was encountered
+    #[error("Teanga model error: {0}")]
+    Teanga(#[from] TeangaError),
+    /// A task does not have the expected form
+    #[error("Label Studio format error: {0}")]
+    Format(String),
+}
+
+/// Write every document of a corpus as a JSON array of Label Studio tasks
+///
+/// # Arguments
+///
+/// * `out` - The output stream
+/// * `corpus` - The corpus to write
+/// * `layers` - The layers to export
+pub fn write_label_studio<W: Write, C: ReadableCorpus>(out: W, corpus: &C,
+    layers: &LabelStudioLayers) -> Result<(), LabelStudioError> {
+    let meta = corpus.get_meta();
+    let mut tasks = Vec::new();
+    for res in corpus.iter_doc_ids() {
+        let (id, doc) = res?;
+        tasks.push(label_studio_task(&id, &doc, meta, layers)?);
+    }
+    serde_json::to_writer(out, &tasks)?;
+    Ok(())
+}
+
+/// Convert a document to a Label Studio task
+///
+/// # Arguments
+///
+/// * `doc_id` - The ID of the document
+/// * `doc` - The document
+/// * `meta` - The metadata of the corpus
+/// * `layers` - The layers to export
+pub fn label_studio_task(doc_id: &str, doc: &Document, meta: &HashMap<String, LayerDesc>,
+    layers: &LabelStudioLayers) -> TeangaResult<Value> {
+    let text = doc.get(&layers.text).and_then(|l| l.characters()).ok_or_else(||
+        TeangaError::LayerNotFoundError(layers.text.clone()))?;
+    let mut task = json!({ "data": { "text": text, "teanga_id": doc_id } });
+    if doc.get(&layers.spans).is_none() {
+        return Ok(task);
+    }
+    let spans = doc.indexes(&layers.spans, &layers.text, meta)?;
+    let label_layer = layers.labels.as_ref().unwrap_or(&layers.spans);
+    let labels = doc.data(label_layer, meta).ok_or_else(||
+        TeangaError::LayerNotFoundError(label_layer.clone()))?;
+    if labels.len() != spans.len() {
+        return Err(TeangaError::ModelError(format!("Layer {} has {} values but {} has {} spans",
+            label_layer, labels.len(), layers.spans, spans.len())));
+    }
+    let char_starts: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    let code_point = |byte: usize| char_starts.partition_point(|&i| i < byte);
+    let mut results = Vec::new();
+    for (index, ((start, end), label)) in spans.into_iter().zip(labels).enumerate() {
+        let label = match label {
+            TeangaData::String(s) => s,
+            TeangaData::TypedLink(_, s) => s,
+            TeangaData::Link(l) => l.to_string(),
+            TeangaData::None => continue,
+        };
+        results.push(json!({
+            "id": format!("{}-{}", layers.spans, index),
+            "from_name": "label",
+            "to_name": "text",
+            "type": "labels",
+            "value": {
+                "start": code_point(start),
+                "end": code_point(end),
+                "text": text.get(start..end).unwrap_or(""),
+                "labels": [label]
+            }
+        }));
+    }
+    task["predictions"] = json!([{ "model_version": "teanga", "result": results }]);
+    Ok(task)
+}
+
+/// The text of a task and its labelled spans
+pub type TaskSpans = (String, Vec<(u32, u32, String)>);
+
+/// Read the text and labelled spans of the annotations of a Label Studio
+/// task. Spans are given as byte offsets and sorted. A span that is reversed
+/// or goes past the end of the text is an error
+pub fn label_studio_spans(task: &Value) -> Result<TaskSpans, LabelStudioError> {
+    let text = task["data"]["text"].as_str().ok_or_else(||
+        LabelStudioError::Format("task has no data.text field".to_string()))?;
+    let offsets: Vec<usize> = text.char_indices().map(|(i, _)| i)
+        .chain(std::iter::once(text.len())).collect();
+    let mut spans = Vec::new();
+    for key in ["annotations", "completions"] {
+        for annotation in task[key].as_array().into_iter().flatten() {
+            for result in annotation["result"].as_array().into_iter().flatten() {
+                let value = &result["value"];
+                let (start, end) = match (value["start"].as_u64(), value["end"].as_u64()) {
+                    (Some(start), Some(end)) => (start, end),
+                    _ => continue,
+                };
+                if start > end || end as usize >= offsets.len() {
+                    return Err(LabelStudioError::Format(format!(
+                        "span {}-{} does not fit a text of {} characters",
+                        start, end, offsets.len() - 1)));
+                }
+                for label in value["labels"].as_array().into_iter().flatten() {
+                    if let Some(label) = label.as_str() {
+                        spans.push((offsets[start as usize] as u32, offsets[end as usize] as u32,
+                            label.to_string()));
+                    }
+                }
+            }
+        }
+    }
+    spans.sort_by_key(|(s, e, _)| (*s, *e));
+    Ok((text.to_string(), spans))
+}
+
+/// Merge completed Label Studio tasks into a corpus. The input is either a
+/// single task or an array of them, as exported by Label Studio. The
+/// annotations of a task with a `teanga_id` are added to that document,
+/// whose text must be unchanged, and any other task is added as a new
+/// document. All tasks are checked before the corpus is changed
+///
+/// # Arguments
+///
+/// * `input` - The input stream
+/// * `corpus` - The corpus to read into
+/// * `layers` - The characters layer and the span layer to create
+///
+/// # Returns
+///
+/// The IDs of the updated and new documents, in the order of the tasks
+pub fn read_label_studio<R: Read, C: Corpus>(input: R, corpus: &mut C,
+    layers: &LabelStudioLayers) -> Result<Vec<String>, LabelStudioError> {
+    let tasks = match serde_json::from_reader(input)? {
+        Value::Array(tasks) => tasks,
+        task => vec![task]
+    };
+    let mut checked = Vec::new();
+    for task in tasks.iter() {
+        let (text, spans) = label_studio_spans(task)?;
+        let id = task["data"]["teanga_id"].as_str();
+        if let Some(id) = id {
+            let doc = corpus.get_doc_by_id(id)?;
+            if doc.get(&layers.text).and_then(|l| l.characters()) != Some(text.as_str()) {
+                return Err(LabelStudioError::Format(
+                    format!("the text of task {} differs from the document", id)));
+            }
+        }
+        checked.push((id, text, spans));
+    }
+    corpus.merge_meta(HashMap::from([
+        (layers.text.clone(), LayerDesc::new(&layers.text, LayerType::characters,
+            None, None, None, None, None, HashMap::new())?),
+        (layers.spans.clone(), LayerDesc::new(&layers.spans, LayerType::span,
+            Some(layers.text.clone()), Some(DataType::String), None, None, None, HashMap::new())?)]))?;
+    let mut ids = Vec::new();
+    for (id, text, spans) in checked {
+        match id {
+            Some(id) => ids.push(corpus.update_doc(id, vec![(layers.spans.clone(), Layer::L2S(spans))])?),
+            None => ids.push(corpus.add_doc(vec![
+                (layers.text.clone(), Layer::Characters(text)),
+                (layers.spans.clone(), Layer::L2S(spans))])?)
+        }
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimpleCorpus;
+
+    #[test]
+    fn test_label_studio_roundtrip() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").layer_type(LayerType::span).base("text").add().unwrap();
+        corpus.build_layer("pos").layer_type(LayerType::seq).base("tokens")
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "Café au lait").unwrap()
+            .layer("tokens", vec![(0, 5), (6, 8), (9, 13)]).unwrap()
+            .layer("pos", vec!["N", "P", "N"]).unwrap()
+            .add().unwrap();
+        corpus.build_doc().layer("text", "Dublin").unwrap().add().unwrap();
+        let mut out = Vec::new();
+        write_label_studio(&mut out, &corpus, &LabelStudioLayers::new("text", "tokens").labels("pos")).unwrap();
+        let mut tasks: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(tasks[0]["predictions"][0]["result"][2]["value"], json!({
+            "start": 8, "end": 12, "text": "lait", "labels": ["N"]
+        }));
+        assert!(tasks[1].get("predictions").is_none());
+
+        tasks[0]["annotations"] = json!([{ "result": [
+            { "value": { "start": 5, "end": 12, "labels": ["DISH"] } },
+            { "value": { "start": 0, "end": 4, "labels": ["DRINK"] } }] }]);
+        tasks[1]["data"] = json!({ "text": "Cork" });
+        tasks[1]["annotations"] = json!([{ "result": [
+            { "value": { "start": 0, "end": 4, "labels": ["LOC"] } }] }]);
+        let ids = read_label_studio(tasks.to_string().as_bytes(), &mut corpus,
+            &LabelStudioLayers::new("text", "food")).unwrap();
+        assert_eq!(ids[0], id);
+        assert_eq!(corpus.get_docs().len(), 3);
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc.get("food"), Some(&Layer::L2S(vec![
+            (0, 5, "DRINK".to_string()), (6, 13, "DISH".to_string())])));
+        assert_eq!(doc.text("tokens", corpus.get_meta()).unwrap(), vec!["Café", "au", "lait"]);
+        let new = corpus.get_doc_by_id(&ids[1]).unwrap();
+        assert_eq!(new.text("food", corpus.get_meta()).unwrap(), vec!["Cork"]);
+
+        tasks[0]["data"]["text"] = json!("Tea");
+        assert!(matches!(read_label_studio(tasks.to_string().as_bytes(), &mut corpus,
+            &LabelStudioLayers::new("text", "food")), Err(LabelStudioError::Format(_))));
+        assert_eq!(corpus.get_docs().len(), 3);
+    }
+
+    #[test]
+    fn test_label_studio_bad_tasks() {
+        let mut corpus = SimpleCorpus::new();
+        let layers = LabelStudioLayers::new("text", "ner");
+        for value in [json!({ "start": 3, "end": 1, "labels": ["X"] }),
+            json!({ "start": 0, "end": 5, "labels": ["X"] })] {
+            let tasks = json!([
+                { "data": { "text": "Tea" } },
+                { "data": { "text": "Cork" }, "annotations": [{ "result": [{ "value": value }] }] }]);
+            assert!(matches!(read_label_studio(tasks.to_string().as_bytes(), &mut corpus, &layers),
+                Err(LabelStudioError::Format(_))));
+        }
+        let tasks = json!([
+            { "data": { "text": "Tea" } },
+            { "data": { "text": "Cork", "teanga_id": "missing" } }]);
+        assert!(read_label_studio(tasks.to_string().as_bytes(), &mut corpus, &layers).is_err());
+        assert!(corpus.get_docs().is_empty());
+        assert!(corpus.get_meta().is_empty());
+
+        let tasks = json!([{ "data": { "text": "Cork" }, "annotations": [{ "result": [
+            { "value": { "start": 0, "end": 4, "labels": ["LOC"] } }] }] }]);
+        let ids = read_label_studio(tasks.to_string().as_bytes(), &mut corpus, &layers).unwrap();
+        assert_eq!(corpus.get_doc_by_id(&ids[0]).unwrap().get("ner"),
+            Some(&Layer::L2S(vec![(0, 4, "LOC".to_string())])));
+    }
+}
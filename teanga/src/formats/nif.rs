@@ -0,0 +1,252 @@
+//! Writing documents and span annotations as NIF Turtle.
+//!
+//! Each document becomes a `nif:Context` holding its characters layer,
+//! identified by the base URI followed by the document ID and an RFC 5147
+//! fragment covering the whole text. Every span of an exported layer becomes
+//! a string of that context with the class given for its layer, and its data,
+//! if any, is written as the value of the property given for its layer.
+//! Spans at the same offsets share a URI, as NIF requires. Offsets are
+//! counted in code points.
+//!
+//! # Examples
+//!
+//! ```
+//! use teanga::*;
+//! use teanga::formats::nif::{write_nif, NifConfig};
+//! let mut corpus = SimpleCorpus::new();
+//! corpus.build_layer("text").add().unwrap();
+//! corpus.build_layer("ner").layer_type(LayerType::span).base("text")
+//!     .data(DataType::String).add().unwrap();
+//! let id = corpus.build_doc()
+//!     .layer("text", "Visit Dublin").unwrap()
+//!     .layer("ner", vec![(6, 12, "LOC")]).unwrap()
+//!     .add().unwrap();
+//! let config = NifConfig::new("http://example.com/corpus/")
+//!     .layer("ner", "nif:Phrase", "itsrdf:taClassRef");
+//! let mut out = Vec::new();
+//! write_nif(&mut out, &corpus, &config).unwrap();
+//! let turtle = String::from_utf8(out).unwrap();
+//! assert!(turtle.contains(&format!("<http://example.com/corpus/{}#char=6,12>", id)));
+//! assert!(turtle.contains("itsrdf:taClassRef \"LOC\""));
+//! ```
+use std::collections::HashMap;
+use std::io::Write;
+use thiserror::Error;
+use crate::{Document, LayerDesc, ReadableCorpus, TeangaData, TeangaError};
+
+/// The prefixes declared at the start of the output
+pub const NIF_PREFIXES : [(&str, &str); 4] = [
+    ("nif", "http://persistence.uni-leipzig.org/nlp2rdf/ontologies/nif-core#"),
+    ("itsrdf", "http://www.w3.org/2005/11/its/rdf#"),
+    ("olia", "http://purl.org/olia/olia.owl#"),
+    ("xsd", "http://www.w3.org/2001/XMLSchema#")];
+
+/// A span layer to export
+#[derive(Debug,Clone)]
+pub struct NifLayer {
+    /// The name of the layer
+    pub name: String,
+    /// The class of its strings, as a Turtle prefixed name or IRI
+    pub class: String,
+    /// The property linking a string to its data, as a Turtle prefixed name
+    /// or IRI
+    pub property: String
+}
+
+/// The layers to export and how to identify them
+#[derive(Debug,Clone)]
+pub struct NifConfig {
+    /// The characters layer of the contexts
+    pub text: String,
+    /// The URI prefix of the documents, each of which is identified by this
+    /// prefix followed by its ID
+    pub base_uri: String,
+    /// The span layers to export
+    pub layers: Vec<NifLayer>
+}
+
+impl NifConfig {
+    /// Export the `text` layer under a base URI, with no span layers until
+    /// they are added with `layer`
+    pub fn new(base_uri: &str) -> NifConfig {
+        NifConfig {
+            text: "text".to_string(),
+            base_uri: base_uri.to_string(),
+            layers: Vec::new()
+        }
+    }
+
+    /// Use another characters layer as the text of the contexts
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = text.to_string();
+        self
+    }
+
+    /// Export a span layer with a class for its strings and a property for
+    /// its data, for example `nif:Word` and `nif:posTag`
+    pub fn layer(mut self, name: &str, class: &str, property: &str) -> Self {
+        self.layers.push(NifLayer {
+            name: name.to_string(),
+            class: class.to_string(),
+            property: property.to_string()
+        });
+        self
+    }
+}
+
+/// An error writing NIF
+#[derive(Error,Debug)]
+pub enum NifError {
+    /// A generic I/O Error
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// An error with the data was encountered
+    #[error("Teanga model error: {0}")]
+    Teanga(#[from] TeangaError),
+}
+
+/// Write every document of a corpus as NIF Turtle
+///
+/// # Arguments
+///
+/// * `out` - The output stream
+/// * `corpus` - The corpus to write
+/// * `config` - The layers to export
+pub fn write_nif<W: Write, C: ReadableCorpus>(mut out: W, corpus: &C,
+    config: &NifConfig) -> Result<(), NifError> {
+    for (prefix, iri) in NIF_PREFIXES.iter() {
+        writeln!(out, "@prefix {}: <{}> .", prefix, iri)?;
+    }
+    let meta = corpus.get_meta();
+    for res in corpus.iter_doc_ids() {
+        let (id, doc) = res?;
+        writeln!(out)?;
+        write_nif_doc(&mut out, &id, &doc, meta, config)?;
+    }
+    Ok(())
+}
+
+/// Write a single document as NIF Turtle, without the prefix declarations
+///
+/// # Arguments
+///
+/// * `out` - The output stream
+/// * `doc_id` - The ID of the document
+/// * `doc` - The document
+/// * `meta` - The metadata of the corpus
+/// * `config` - The layers to export
+pub fn write_nif_doc<W: Write>(mut out: W, doc_id: &str, doc: &Document,
+    meta: &HashMap<String, LayerDesc>, config: &NifConfig) -> Result<(), NifError> {
+    let text = doc.get(&config.text).and_then(|l| l.characters()).ok_or_else(||
+        TeangaError::LayerNotFoundError(config.text.clone()))?;
+    let uri = format!("{}{}", config.base_uri, iri_escape(doc_id));
+    let char_starts: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    let code_point = |byte: usize| char_starts.partition_point(|&i| i < byte);
+    let length = char_starts.len();
+    let context = format!("<{}#char=0,{}>", uri, length);
+    writeln!(out, "{}\n    a nif:Context, nif:RFC5147String ;", context)?;
+    writeln!(out, "    nif:beginIndex \"0\"^^xsd:nonNegativeInteger ;")?;
+    writeln!(out, "    nif:endIndex \"{}\"^^xsd:nonNegativeInteger ;", length)?;
+    writeln!(out, "    nif:isString {} .", literal(text))?;
+    for layer in config.layers.iter() {
+        if doc.get(&layer.name).is_none() {
+            continue;
+        }
+        for (start, end, data) in doc.indexes_data(&layer.name, &config.text, meta)? {
+            let (begin, finish) = (code_point(start), code_point(end));
+            writeln!(out, "\n<{}#char={},{}>\n    a {}, nif:RFC5147String ;", uri, begin, finish, layer.class)?;
+            writeln!(out, "    nif:referenceContext {} ;", context)?;
+            writeln!(out, "    nif:beginIndex \"{}\"^^xsd:nonNegativeInteger ;", begin)?;
+            writeln!(out, "    nif:endIndex \"{}\"^^xsd:nonNegativeInteger ;", finish)?;
+            let value = match data {
+                TeangaData::String(s) => Some(s),
+                TeangaData::TypedLink(_, s) => Some(s),
+                TeangaData::Link(l) => Some(l.to_string()),
+                TeangaData::None => None
+            };
+            let anchor = literal(text.get(start..end).unwrap_or(""));
+            match value {
+                Some(value) => {
+                    writeln!(out, "    nif:anchorOf {} ;", anchor)?;
+                    writeln!(out, "    {} {} .", layer.property, literal(&value))?;
+                },
+                None => writeln!(out, "    nif:anchorOf {} .", anchor)?
+            }
+        }
+    }
+    Ok(())
+}
+
+// A Turtle string literal
+fn literal(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+// Percent-encode the characters that may not appear in a Turtle IRI
+fn iri_escape(s: &str) -> String {
+    let mut result = String::new();
+    for c in s.chars() {
+        if c <= ' ' || "<>\"{}|^`\\#%".contains(c) {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                result.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Corpus, DataType, Layer, LayerType, SimpleCorpus};
+
+    #[test]
+    fn test_write_nif_doc() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").layer_type(LayerType::span).base("text").add().unwrap();
+        corpus.build_layer("pos").layer_type(LayerType::seq).base("tokens")
+            .data(DataType::String).add().unwrap();
+        let mut doc = Document::new(vec![
+            ("text".to_string(), Layer::Characters("Sé \"ag\" rith".to_string())),
+            ("tokens".to_string(), Layer::L2(vec![(0, 3), (4, 8), (9, 13)])),
+        ], corpus.get_meta()).unwrap();
+        let config = NifConfig::new("http://example.com/").layer("tokens", "nif:Word", "nif:posTag");
+        let mut out = Vec::new();
+        write_nif_doc(&mut out, "a b", &doc, corpus.get_meta(), &config).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().take(11).collect::<Vec<_>>(), vec![
+            "<http://example.com/a%20b#char=0,12>",
+            "    a nif:Context, nif:RFC5147String ;",
+            "    nif:beginIndex \"0\"^^xsd:nonNegativeInteger ;",
+            "    nif:endIndex \"12\"^^xsd:nonNegativeInteger ;",
+            "    nif:isString \"Sé \\\"ag\\\" rith\" .",
+            "",
+            "<http://example.com/a%20b#char=0,2>",
+            "    a nif:Word, nif:RFC5147String ;",
+            "    nif:referenceContext <http://example.com/a%20b#char=0,12> ;",
+            "    nif:beginIndex \"0\"^^xsd:nonNegativeInteger ;",
+            "    nif:endIndex \"2\"^^xsd:nonNegativeInteger ;"]);
+
+        doc.set("pos", Layer::LS(vec!["VERB".to_string(), "PART".to_string(), "NOUN".to_string()]));
+        let config = NifConfig::new("http://example.com/").layer("pos", "nif:Word", "nif:posTag");
+        let mut out = Vec::new();
+        write_nif_doc(&mut out, "a", &doc, corpus.get_meta(), &config).unwrap();
+        let turtle = String::from_utf8(out).unwrap();
+        assert!(turtle.contains("    nif:anchorOf \"\\\"ag\\\"\" ;\n    nif:posTag \"PART\" .\n"));
+    }
+}
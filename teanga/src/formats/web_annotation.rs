@@ -0,0 +1,208 @@
+//! Exporting span layers as W3C Web Annotation JSON-LD.
+//!
+//! Every span of the exported layers becomes an `Annotation` targeting the
+//! document, identified by the base URI followed by its ID, with a
+//! `TextPositionSelector` and a `TextQuoteSelector` on its characters
+//! layer. The data of the span, if any, becomes a `TextualBody` tagging the
+//! target. The annotations of a corpus are written as a single
+//! `AnnotationCollection`. Positions are counted in code points, as the
+//! Web Annotation Data Model requires.
+//!
+//! # Examples
+//!
+//! ```
+//! use teanga::*;
+//! use teanga::formats::web_annotation::{web_annotations, WebAnnotationConfig};
+//! let mut corpus = SimpleCorpus::new();
+//! corpus.build_layer("text").add().unwrap();
+//! corpus.build_layer("ner").layer_type(LayerType::span).base("text")
+//!     .data(DataType::String).add().unwrap();
+//! let id = corpus.build_doc()
+//!     .layer("text", "Visit Dublin").unwrap()
+//!     .layer("ner", vec![(6, 12, "LOC")]).unwrap()
+//!     .add().unwrap();
+//! let doc = corpus.get_doc_by_id(&id).unwrap();
+//! let config = WebAnnotationConfig::new("http://example.com/corpus/", &["ner"]);
+//! let annotations = web_annotations(&id, &doc, corpus.get_meta(), &config).unwrap();
+//! assert_eq!(annotations[0]["body"]["value"], "LOC");
+//! assert_eq!(annotations[0]["target"]["source"], format!("http://example.com/corpus/{}", id));
+//! ```
+use std::collections::HashMap;
+use std::io::Write;
+use serde_json::{json, Value};
+use thiserror::Error;
+use crate::{Document, LayerDesc, ReadableCorpus, TeangaData, TeangaError, TeangaResult};
+
+/// The JSON-LD context of the Web Annotation Data Model
+pub const WEB_ANNOTATION_CONTEXT : &str = "http://www.w3.org/ns/anno.jsonld";
+
+/// The layers to export and how to identify them
+#[derive(Debug,Clone)]
+pub struct WebAnnotationConfig {
+    /// The characters layer that is the source of the targets
+    pub text: String,
+    /// The span layers to export
+    pub layers: Vec<String>,
+    /// The URI prefix of the documents, each of which is identified by this
+    /// prefix followed by its ID
+    pub base_uri: String
+}
+
+impl WebAnnotationConfig {
+    /// Export the given span layers of the `text` layer under a base URI
+    pub fn new(base_uri: &str, layers: &[&str]) -> WebAnnotationConfig {
+        WebAnnotationConfig {
+            text: "text".to_string(),
+            layers: layers.iter().map(|l| l.to_string()).collect(),
+            base_uri: base_uri.to_string()
+        }
+    }
+
+    /// Use another characters layer as the source of the targets
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = text.to_string();
+        self
+    }
+}
+
+/// An error writing Web Annotation JSON-LD
+#[derive(Error,Debug)]
+pub enum WebAnnotationError {
+    /// The JSON could not be written
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    /// An error with the data was encountered
+    #[error("Teanga model error: {0}")]
+    Teanga(#[from] TeangaError),
+}
+
+/// Write the annotations of every document of a corpus as an
+/// `AnnotationCollection`
+///
+/// # Arguments
+///
+/// * `out` - The output stream
+/// * `corpus` - The corpus to write
+/// * `config` - The layers to export
+pub fn write_web_annotation<W: Write, C: ReadableCorpus>(out: W, corpus: &C,
+    config: &WebAnnotationConfig) -> Result<(), WebAnnotationError> {
+    let meta = corpus.get_meta();
+    let mut items = Vec::new();
+    for res in corpus.iter_doc_ids() {
+        let (id, doc) = res?;
+        items.extend(web_annotations(&id, &doc, meta, config)?);
+    }
+    let collection = json!({
+        "@context": WEB_ANNOTATION_CONTEXT,
+        "id": format!("{}annotations", config.base_uri),
+        "type": "AnnotationCollection",
+        "total": items.len(),
+        "first": {
+            "id": format!("{}annotations/page1", config.base_uri),
+            "type": "AnnotationPage",
+            "startIndex": 0,
+            "items": items
+        }
+    });
+    serde_json::to_writer_pretty(out, &collection)?;
+    Ok(())
+}
+
+/// Convert the spans of a document to Web Annotations. Layers that the
+/// document does not have are skipped
+///
+/// # Arguments
+///
+/// * `doc_id` - The ID of the document
+/// * `doc` - The document
+/// * `meta` - The metadata of the corpus
+/// * `config` - The layers to export
+pub fn web_annotations(doc_id: &str, doc: &Document, meta: &HashMap<String, LayerDesc>,
+    config: &WebAnnotationConfig) -> TeangaResult<Vec<Value>> {
+    let text = doc.get(&config.text).and_then(|l| l.characters()).ok_or_else(||
+        TeangaError::LayerNotFoundError(config.text.clone()))?;
+    let source = format!("{}{}", config.base_uri, doc_id);
+    let char_starts: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    let code_point = |byte: usize| char_starts.partition_point(|&i| i < byte);
+    let mut annotations = Vec::new();
+    for layer in config.layers.iter() {
+        if doc.get(layer).is_none() {
+            continue;
+        }
+        for (index, (start, end, data)) in doc.indexes_data(layer, &config.text, meta)?
+            .into_iter().enumerate() {
+            let mut annotation = json!({
+                "@context": WEB_ANNOTATION_CONTEXT,
+                "id": format!("{}/{}/{}", source, layer, index),
+                "type": "Annotation",
+                "target": {
+                    "source": source,
+                    "selector": [{
+                        "type": "TextPositionSelector",
+                        "start": code_point(start),
+                        "end": code_point(end)
+                    }, {
+                        "type": "TextQuoteSelector",
+                        "exact": text.get(start..end).unwrap_or("")
+                    }]
+                }
+            });
+            let value = match data {
+                TeangaData::String(s) => Some(s),
+                TeangaData::TypedLink(_, s) => Some(s),
+                TeangaData::Link(l) => Some(l.to_string()),
+                TeangaData::None => None
+            };
+            if let Some(value) = value {
+                annotation["body"] = json!({
+                    "type": "TextualBody",
+                    "value": value,
+                    "purpose": "tagging"
+                });
+            }
+            annotations.push(annotation);
+        }
+    }
+    Ok(annotations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Corpus, DataType, LayerType, SimpleCorpus};
+
+    #[test]
+    fn test_write_web_annotation() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").layer_type(LayerType::span).base("text").add().unwrap();
+        corpus.build_layer("ner").layer_type(LayerType::span).base("tokens")
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "Café in Dún Laoghaire").unwrap()
+            .layer("tokens", vec![(0, 5), (6, 8), (9, 13), (14, 23)]).unwrap()
+            .layer("ner", vec![(2, 4, "LOC")]).unwrap()
+            .add().unwrap();
+        corpus.build_doc().layer("text", "No annotations").unwrap().add().unwrap();
+        let mut out = Vec::new();
+        write_web_annotation(&mut out, &corpus,
+            &WebAnnotationConfig::new("http://example.com/", &["tokens", "ner"])).unwrap();
+        let collection: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(collection["total"], 5);
+        let items = &collection["first"]["items"];
+        assert!(items[0].get("body").is_none());
+        assert_eq!(items[4], json!({
+            "@context": WEB_ANNOTATION_CONTEXT,
+            "id": format!("http://example.com/{}/ner/0", id),
+            "type": "Annotation",
+            "body": { "type": "TextualBody", "value": "LOC", "purpose": "tagging" },
+            "target": {
+                "source": format!("http://example.com/{}", id),
+                "selector": [
+                    { "type": "TextPositionSelector", "start": 8, "end": 21 },
+                    { "type": "TextQuoteSelector", "exact": "Dún Laoghaire" }
+                ]
+            }
+        }));
+    }
+}
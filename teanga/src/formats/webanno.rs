@@ -0,0 +1,487 @@
+//! Reading WebAnno and INCEpTION TSV 3 files.
+//!
+//! Each TSV file is read as one document. The tokens become a span layer
+//! over a characters layer rebuilt from the `#Text` lines, and the sentences
+//! a div layer over the tokens. Every span layer of the file (`#T_SP`)
+//! becomes a span layer over the tokens, labelled with its `value` feature,
+//! or its first feature if it has none called `value`. Each further feature
+//! becomes a string seq layer over the span layer. Every relation layer
+//! (`#T_RL`) becomes an element layer over the tokens, linking the token of
+//! the dependent to the token of the governor and typed by its first
+//! feature. Chain layers and sub-token annotations are skipped.
+//!
+//! Offsets in the file are counted in characters and converted to bytes.
+use std::collections::HashMap;
+use std::io::BufRead;
+use thiserror::Error;
+use crate::{Corpus, DataType, Layer, LayerDesc, LayerType, TeangaError};
+
+/// The names of the layers created when reading WebAnno TSV
+#[derive(Debug,Clone)]
+pub struct WebAnnoLayers {
+    /// The characters layer
+    pub text: String,
+    /// The span layer of tokens
+    pub tokens: String,
+    /// The div layer of sentences, based on the tokens
+    pub sentences: String,
+    /// The names of the layers for TSV layers, keyed by their type name, and
+    /// for features, keyed by `type|feature`. An unmapped layer is named by
+    /// the last part of its type name and an unmapped feature by the layer
+    /// name and the feature joined with `_`
+    pub names: HashMap<String, String>
+}
+
+impl WebAnnoLayers {
+    /// Use the default layer names: `text`, `tokens` and `sentences`
+    pub fn new() -> WebAnnoLayers {
+        WebAnnoLayers {
+            text: "text".to_string(),
+            tokens: "tokens".to_string(),
+            sentences: "sentences".to_string(),
+            names: HashMap::new()
+        }
+    }
+
+    /// Map a TSV layer type, or a `type|feature` pair, to a Teanga layer name
+    pub fn map(mut self, tsv_name: &str, layer: &str) -> Self {
+        self.names.insert(tsv_name.to_string(), layer.to_string());
+        self
+    }
+
+    fn layer_name(&self, type_name: &str) -> String {
+        self.names.get(type_name).cloned().unwrap_or_else(||
+            type_name.rsplit('.').next().unwrap_or(type_name).to_string())
+    }
+
+    fn feature_name(&self, type_name: &str, feature: &str) -> String {
+        self.names.get(&format!("{}|{}", type_name, feature)).cloned().unwrap_or_else(||
+            format!("{}_{}", self.layer_name(type_name), feature))
+    }
+}
+
+impl Default for WebAnnoLayers {
+    fn default() -> WebAnnoLayers {
+        WebAnnoLayers::new()
+    }
+}
+
+/// An error reading WebAnno TSV
+#[derive(Error,Debug)]
+pub enum WebAnnoError {
+    /// A generic I/O Error
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// An error with the data was encountered
+    #[error("Teanga model error: {0}")]
+    Teanga(#[from] TeangaError),
+    /// A line could not be parsed
+    #[error("WebAnno TSV format error on line {0}: {1}")]
+    Format(usize, String),
+}
+
+#[derive(Debug,Clone,PartialEq)]
+enum TsvLayerKind {
+    Span,
+    Relation,
+    Chain
+}
+
+// A layer declared in the header of the file
+#[derive(Debug)]
+struct TsvLayer {
+    kind: TsvLayerKind,
+    type_name: String,
+    features: Vec<String>,
+    // The index of the feature used as the label
+    primary: usize,
+    // The first column of the layer, after the token columns
+    column: usize
+}
+
+impl TsvLayer {
+    fn columns(&self) -> usize {
+        match self.kind {
+            TsvLayerKind::Relation => self.features.len() + 1,
+            _ => self.features.len().max(1)
+        }
+    }
+}
+
+// A span annotation by its first and last token
+struct SpanAnnotation {
+    first: usize,
+    last: usize,
+    features: Vec<Option<String>>
+}
+
+// A relation by the address of its governor, the token of its dependent,
+// its label and the line it was read from
+type TsvRelation = (String, usize, Option<String>, usize);
+
+/// Read a WebAnno TSV file into a corpus using the default layer names
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read from
+/// * `corpus` - The corpus to read into
+///
+/// # Returns
+///
+/// The ID of the new document
+pub fn read_webanno_tsv<R: BufRead, C: Corpus>(reader: R, corpus: &mut C) -> Result<String, WebAnnoError> {
+    read_webanno_tsv_with_layers(reader, corpus, &WebAnnoLayers::new())
+}
+
+/// Read a WebAnno TSV file into a corpus
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read from
+/// * `corpus` - The corpus to read into
+/// * `layers` - The names of the layers to create
+///
+/// # Returns
+///
+/// The ID of the new document
+pub fn read_webanno_tsv_with_layers<R: BufRead, C: Corpus>(reader: R, corpus: &mut C,
+    layers: &WebAnnoLayers) -> Result<String, WebAnnoError> {
+    let mut tsv_layers: Vec<TsvLayer> = Vec::new();
+    let mut columns = 0;
+    let mut text = String::new();
+    let mut text_len = 0;
+    let mut sentence_text: Vec<String> = Vec::new();
+    let mut tokens: Vec<(usize, usize)> = Vec::new();
+    let mut addresses: HashMap<String, usize> = HashMap::new();
+    let mut sentences: Vec<u32> = Vec::new();
+    let mut last_sentence = String::new();
+    let mut spans: Vec<Vec<SpanAnnotation>> = Vec::new();
+    let mut span_ids: Vec<HashMap<String, usize>> = Vec::new();
+    let mut relations: Vec<Vec<TsvRelation>> = Vec::new();
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end_matches(['\r', '\n']);
+        if let Some(format) = line.strip_prefix("#FORMAT=") {
+            if !format.starts_with("WebAnno TSV 3") {
+                return Err(WebAnnoError::Format(n + 1, format!("unsupported format {}", format)));
+            }
+        } else if let Some(decl) = line.strip_prefix("#T_") {
+            let (kind, decl) = match decl.split_once('=') {
+                Some(("SP", decl)) => (TsvLayerKind::Span, decl),
+                Some(("RL", decl)) => (TsvLayerKind::Relation, decl),
+                Some(("CH", decl)) => (TsvLayerKind::Chain, decl),
+                _ => return Err(WebAnnoError::Format(n + 1, format!("unknown layer declaration {}", line)))
+            };
+            let mut parts = decl.split('|');
+            let type_name = parts.next().unwrap_or_default().to_string();
+            let features: Vec<String> = parts.filter(|f| !f.starts_with("BT_"))
+                .map(|f| f.to_string()).collect();
+            let primary = features.iter().position(|f| f == "value").unwrap_or(0);
+            let layer = TsvLayer { kind, type_name, features, primary, column: columns };
+            columns += layer.columns();
+            tsv_layers.push(layer);
+            spans.push(Vec::new());
+            span_ids.push(HashMap::new());
+            relations.push(Vec::new());
+        } else if let Some(t) = line.strip_prefix("#Text=") {
+            sentence_text.push(unescape(t));
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < columns + 3 {
+                return Err(WebAnnoError::Format(n + 1,
+                    format!("expected {} columns but found {}", columns + 3, fields.len())));
+            }
+            // Sub-tokens
+            if fields[0].contains('.') {
+                continue;
+            }
+            let (start, end) = fields[1].split_once('-').and_then(|(s, e)|
+                Some((s.parse::<usize>().ok()?, e.parse::<usize>().ok()?)))
+                .filter(|(s, e)| s <= e)
+                .ok_or_else(|| WebAnnoError::Format(n + 1, format!("bad offsets {}", fields[1])))?;
+            let sentence = fields[0].split('-').next().unwrap_or_default();
+            if sentence != last_sentence {
+                sentences.push(tokens.len() as u32);
+                last_sentence = sentence.to_string();
+                if !sentence_text.is_empty() {
+                    pad(&mut text, &mut text_len, start, n)?;
+                    let t = sentence_text.join("\n");
+                    text_len += t.chars().count();
+                    text.push_str(&t);
+                    sentence_text.clear();
+                }
+            }
+            // Tokens not covered by the text of their sentence
+            if end > text_len {
+                pad(&mut text, &mut text_len, start, n)?;
+                let form = unescape(fields[2]);
+                text_len += form.chars().count();
+                text.push_str(&form);
+            }
+            if end > text_len {
+                return Err(WebAnnoError::Format(n + 1,
+                    format!("offsets {} go past the text of the token", fields[1])));
+            }
+            let token = tokens.len();
+            tokens.push((start, end));
+            addresses.insert(fields[0].to_string(), token);
+            for (l, layer) in tsv_layers.iter().enumerate() {
+                let cells: Vec<Vec<(Option<String>, Option<String>)>> =
+                    fields[3 + layer.column..3 + layer.column + layer.columns()]
+                    .iter().map(|c| split_cell(c)).collect();
+                let stacked = cells.iter().map(|c| c.len()).max().unwrap_or(0);
+                match layer.kind {
+                    TsvLayerKind::Span => for k in 0..stacked {
+                        let id = cells.iter().find_map(|c| c.get(k).and_then(|v| v.1.clone()));
+                        let features: Vec<Option<String>> = cells.iter()
+                            .map(|c| c.get(k).and_then(|v| v.0.clone())).collect();
+                        match id.as_ref().and_then(|id| span_ids[l].get(id)) {
+                            Some(&i) => spans[l][i].last = token,
+                            None => {
+                                if let Some(id) = id {
+                                    span_ids[l].insert(id, spans[l].len());
+                                }
+                                spans[l].push(SpanAnnotation { first: token, last: token, features });
+                            }
+                        }
+                    },
+                    TsvLayerKind::Relation => {
+                        let governors = &cells[layer.features.len()];
+                        for (k, (governor, _)) in governors.iter().enumerate() {
+                            if let Some(governor) = governor {
+                                let label = cells.get(layer.primary).filter(|_| !layer.features.is_empty())
+                                    .and_then(|c| c.get(k)).and_then(|v| v.0.clone());
+                                relations[l].push((governor.clone(), token, label, n + 1));
+                            }
+                        }
+                    },
+                    TsvLayerKind::Chain => {}
+                }
+            }
+        }
+    }
+    // The byte offset of each character, and of the end of the text
+    let offsets: Vec<usize> = text.char_indices().map(|(i, _)| i)
+        .chain(std::iter::once(text.len())).collect();
+    let mut content = vec![
+        (layers.text.clone(), Layer::Characters(text.clone())),
+        (layers.tokens.clone(), Layer::L2(tokens.iter()
+            .map(|&(s, e)| (offsets[s] as u32, offsets[e] as u32)).collect())),
+        (layers.sentences.clone(), Layer::L1(sentences))];
+    for ((layer, mut annotations), relations) in tsv_layers.iter().zip(spans).zip(relations) {
+        let name = layers.layer_name(&layer.type_name);
+        match layer.kind {
+            TsvLayerKind::Span => {
+                annotations.sort_by_key(|a| (a.first, a.last));
+                let value = |a: &SpanAnnotation, f: usize|
+                    a.features.get(f).cloned().flatten().unwrap_or_default();
+                if layer.features.is_empty() {
+                    content.push((name, Layer::L2(annotations.iter()
+                        .map(|a| (a.first as u32, a.last as u32 + 1)).collect())));
+                } else {
+                    content.push((name, Layer::L2S(annotations.iter()
+                        .map(|a| (a.first as u32, a.last as u32 + 1, value(a, layer.primary))).collect())));
+                }
+                for (f, feature) in layer.features.iter().enumerate() {
+                    if f != layer.primary {
+                        content.push((layers.feature_name(&layer.type_name, feature),
+                            Layer::LS(annotations.iter().map(|a| value(a, f)).collect())));
+                    }
+                }
+            },
+            TsvLayerKind::Relation => {
+                let mut links = Vec::new();
+                for (governor, dependent, label, line) in relations {
+                    let governor = addresses.get(&governor).ok_or_else(||
+                        WebAnnoError::Format(line, format!("unknown token {}", governor)))?;
+                    links.push((dependent as u32, *governor as u32, label.unwrap_or_default()));
+                }
+                content.push((name, Layer::L2S(links)));
+            },
+            TsvLayerKind::Chain => {}
+        }
+    }
+    add_webanno_meta(corpus, layers, &tsv_layers)?;
+    Ok(corpus.add_doc(content)?)
+}
+
+fn add_webanno_meta<C: Corpus>(corpus: &mut C, layers: &WebAnnoLayers,
+    tsv_layers: &[TsvLayer]) -> Result<(), TeangaError> {
+    let mut meta = HashMap::new();
+    let mut declare = |name: String, layer_type: LayerType, base: Option<&String>,
+        data: Option<DataType>, target: Option<&String>| -> Result<(), TeangaError> {
+        let desc = LayerDesc::new(&name, layer_type, base.cloned(), data, None,
+            target.cloned(), None, HashMap::new())?;
+        meta.insert(name, desc);
+        Ok(())
+    };
+    declare(layers.text.clone(), LayerType::characters, None, None, None)?;
+    declare(layers.tokens.clone(), LayerType::span, Some(&layers.text), None, None)?;
+    declare(layers.sentences.clone(), LayerType::div, Some(&layers.tokens), None, None)?;
+    for layer in tsv_layers {
+        let name = layers.layer_name(&layer.type_name);
+        match layer.kind {
+            TsvLayerKind::Span => {
+                let data = if layer.features.is_empty() { None } else { Some(DataType::String) };
+                declare(name.clone(), LayerType::span, Some(&layers.tokens), data, None)?;
+                for (f, feature) in layer.features.iter().enumerate() {
+                    if f != layer.primary {
+                        declare(layers.feature_name(&layer.type_name, feature),
+                            LayerType::seq, Some(&name), Some(DataType::String), None)?;
+                    }
+                }
+            },
+            TsvLayerKind::Relation => {
+                declare(name, LayerType::element, Some(&layers.tokens),
+                    Some(DataType::Link), Some(&layers.tokens))?;
+            },
+            TsvLayerKind::Chain => {}
+        }
+    }
+    corpus.merge_meta(meta)
+}
+
+// Pad the text with spaces up to a character offset
+fn pad(text: &mut String, text_len: &mut usize, offset: usize, line: usize) -> Result<(), WebAnnoError> {
+    if offset < *text_len {
+        return Err(WebAnnoError::Format(line + 1,
+            format!("offset {} overlaps the text before it", offset)));
+    }
+    text.push_str(&" ".repeat(offset - *text_len));
+    *text_len = offset;
+    Ok(())
+}
+
+// Split a cell into its stacked values, each with the ID that links the
+// tokens of a multi-token annotation. `_` and `*` are values without a label
+fn split_cell(cell: &str) -> Vec<(Option<String>, Option<String>)> {
+    if cell == "_" || cell.is_empty() {
+        return Vec::new();
+    }
+    let mut values = Vec::new();
+    let mut current = String::new();
+    let mut chars = cell.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            current.push(c);
+            current.extend(chars.next());
+        } else if c == '|' {
+            values.push(split_id(&current));
+            current.clear();
+        } else {
+            current.push(c);
+        }
+    }
+    values.push(split_id(&current));
+    values
+}
+
+// Separate an unescaped `[id]` suffix from a value
+fn split_id(value: &str) -> (Option<String>, Option<String>) {
+    let (value, id) = match value.strip_suffix(']').and_then(|v| v.rfind('[').map(|i| (v, i))) {
+        Some((v, i)) if !v[..i].ends_with('\\') => (&v[..i], Some(v[i + 1..].to_string())),
+        _ => (value, None)
+    };
+    let value = match value {
+        "_" | "*" | "" => None,
+        v => Some(unescape(v))
+    };
+    (value, id)
+}
+
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('r') => result.push('\r'),
+                Some('t') => result.push('\t'),
+                Some(c) => result.push(c),
+                None => result.push('\\')
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ReadableCorpus, SimpleCorpus, TeangaData};
+
+    const TSV: &str = "#FORMAT=WebAnno TSV 3.3
+#T_SP=de.tudarmstadt.ukp.dkpro.core.api.lexmorph.type.pos.POS|PosValue|coarseValue
+#T_SP=de.tudarmstadt.ukp.dkpro.core.api.ner.type.NamedEntity|identifier|value
+#T_RL=de.tudarmstadt.ukp.dkpro.core.api.syntax.type.dependency.Dependency|DependencyType|flavor|BT_de.tudarmstadt.ukp.dkpro.core.api.lexmorph.type.pos.POS
+
+
+#Text=New York sleeps.
+1-1\t0-3\tNew\tPROPN\t_\tQ60[1]\tLOC[1]\tcompound\tbasic\t1-2\t
+1-2\t4-8\tYork\tPROPN\t_\tQ60[1]|_\tLOC[1]|GPE\tnsubj\tbasic\t1-3\t
+1-3\t9-15\tsleeps\tVERB\t_\t_\t_\t_\t_\t_\t
+1-4\t15-16\t.\tPUNCT\t_\t_\t_\tpunct\tbasic\t1-3\t
+
+#Text=Dún Laoghaire
+#Text=is near.
+2-1\t17-20\tDún\t_\t_\t_\tLOC[2]\t_\t_\t_\t
+2-2\t21-30\tLaoghaire\t_\t_\t_\tLOC[2]\t_\t_\t_\t
+2-3\t31-33\tis\t_\t_\t_\t_\t_\t_\t_\t
+2-4\t34-38\tnear\t_\t_\t_\t*\t_\t_\t_\t
+2-5\t38-39\t.\t_\t_\t_\t_\t_\t_\t_\t
+";
+
+    #[test]
+    fn test_read_webanno_tsv() {
+        let mut corpus = SimpleCorpus::new();
+        let layers = WebAnnoLayers::new()
+            .map("de.tudarmstadt.ukp.dkpro.core.api.ner.type.NamedEntity", "ner")
+            .map("de.tudarmstadt.ukp.dkpro.core.api.ner.type.NamedEntity|identifier", "wikidata")
+            .map("de.tudarmstadt.ukp.dkpro.core.api.syntax.type.dependency.Dependency", "dep");
+        let id = read_webanno_tsv_with_layers(TSV.as_bytes(), &mut corpus, &layers).unwrap();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        let meta = corpus.get_meta();
+        assert_eq!(doc.get("text"), Some(&Layer::Characters(
+            "New York sleeps. Dún Laoghaire\nis near.".to_string())));
+        assert_eq!(doc.text("tokens", meta).unwrap(),
+            vec!["New", "York", "sleeps", ".", "Dún", "Laoghaire", "is", "near", "."]);
+        assert_eq!(doc.get("sentences"), Some(&Layer::L1(vec![0, 4])));
+        assert_eq!(doc.get("ner"), Some(&Layer::L2S(vec![
+            (0, 2, "LOC".to_string()), (1, 2, "GPE".to_string()),
+            (4, 6, "LOC".to_string()), (7, 8, "".to_string())])));
+        assert_eq!(doc.get("wikidata"), Some(&Layer::LS(vec![
+            "Q60".to_string(), "".to_string(), "".to_string(), "".to_string()])));
+        assert_eq!(doc.text("POS", meta).unwrap(), vec!["New", "York", "sleeps", "."]);
+        assert_eq!(doc.get("POS_coarseValue").map(|l| l.len()), Some(4));
+        assert_eq!(doc.data("dep", meta).unwrap(), vec![
+            TeangaData::TypedLink(1, "compound".to_string()),
+            TeangaData::TypedLink(2, "nsubj".to_string()),
+            TeangaData::TypedLink(2, "punct".to_string())]);
+        assert!(crate::validate_corpus(&corpus).unwrap().is_empty());
+
+        read_webanno_tsv_with_layers(TSV.as_bytes(), &mut corpus, &layers).unwrap();
+        assert_eq!(corpus.get_docs().len(), 2);
+    }
+
+    #[test]
+    fn test_read_webanno_tsv_errors() {
+        let mut corpus = SimpleCorpus::new();
+        assert!(matches!(read_webanno_tsv("#FORMAT=WebAnno TSV 3.3\n\n1-1\t0-5\tab\t\n".as_bytes(),
+            &mut corpus), Err(WebAnnoError::Format(3, _))));
+        let tsv = "#FORMAT=WebAnno TSV 3.3
+#T_RL=webanno.custom.Relation|label|BT_webanno.custom.Span
+
+
+#Text=a b
+1-1\t0-1\ta\tx\t1-3\t
+1-2\t2-3\tb\t_\t_\t
+";
+        assert!(matches!(read_webanno_tsv(tsv.as_bytes(), &mut corpus),
+            Err(WebAnnoError::Format(6, _))));
+        assert!(corpus.get_docs().is_empty());
+        assert!(corpus.get_meta().is_empty());
+    }
+}
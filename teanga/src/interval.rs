@@ -0,0 +1,134 @@
+//! Interval indexes for finding the annotations of a layer that overlap a
+//! range of characters.
+//!
+//! # Examples
+//!
+//! ```
+//! use teanga::*;
+//! let mut corpus = SimpleCorpus::new();
+//! corpus.build_layer("text").add().unwrap();
+//! corpus.build_layer("ner").layer_type(LayerType::span).base("text").add().unwrap();
+//! let id = corpus.build_doc()
+//!     .layer("text", "The White House is in Washington.").unwrap()
+//!     .layer("ner", vec![(4, 15), (22, 32)]).unwrap()
+//!     .add().unwrap();
+//! let doc = corpus.get_doc_by_id(&id).unwrap();
+//! let index = doc.interval_index("ner", corpus.get_meta()).unwrap();
+//! assert_eq!(index.overlapping(10..25), vec![0, 1]);
+//! assert_eq!(index.overlapping(16..20), Vec::<usize>::new());
+//! ```
+use std::ops::Range;
+
+/// A static interval tree over the spans of a layer. Queries take
+/// O(log n + k) time, where k is the number of matches
+#[derive(Debug,Clone)]
+pub struct IntervalIndex {
+    // Spans and their position in the layer, sorted by start
+    spans: Vec<(usize, usize, usize)>,
+    // The greatest end in the subtree rooted at each position, where the
+    // tree over a range of positions is rooted at its midpoint
+    max_end: Vec<usize>
+}
+
+impl IntervalIndex {
+    /// Create an index from the spans of a layer, in layer order
+    pub fn new(spans : &[(usize, usize)]) -> IntervalIndex {
+        let mut sorted: Vec<(usize, usize, usize)> = spans.iter().enumerate()
+            .map(|(i, &(start, end))| (start, end, i)).collect();
+        sorted.sort();
+        let mut max_end = vec![0; sorted.len()];
+        build(&sorted, &mut max_end, 0, sorted.len());
+        IntervalIndex { spans: sorted, max_end }
+    }
+
+    /// The number of spans in the index
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Whether the index has no spans
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Find the spans that overlap a range of characters
+    ///
+    /// # Returns
+    ///
+    /// The positions in the layer of the overlapping spans, in ascending order
+    pub fn overlapping(&self, range : Range<usize>) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.collect(&range, 0, self.spans.len(), &mut found);
+        found.sort();
+        found
+    }
+
+    /// Whether any span overlaps a range of characters
+    pub fn any_overlapping(&self, range : Range<usize>) -> bool {
+        self.find_any(&range, 0, self.spans.len())
+    }
+
+    fn collect(&self, range : &Range<usize>, lo : usize, hi : usize, found : &mut Vec<usize>) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if self.max_end[mid] <= range.start {
+            return;
+        }
+        self.collect(range, lo, mid, found);
+        let (start, end, index) = self.spans[mid];
+        if start < range.end {
+            if range.start < end {
+                found.push(index);
+            }
+            self.collect(range, mid + 1, hi, found);
+        }
+    }
+
+    fn find_any(&self, range : &Range<usize>, lo : usize, hi : usize) -> bool {
+        if lo >= hi {
+            return false;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if self.max_end[mid] <= range.start {
+            return false;
+        }
+        let (start, end, _) = self.spans[mid];
+        self.find_any(range, lo, mid) ||
+            (start < range.end && (range.start < end || self.find_any(range, mid + 1, hi)))
+    }
+}
+
+// Fill in the greatest end of the subtree over `lo..hi` and return it
+fn build(spans : &[(usize, usize, usize)], max_end : &mut [usize], lo : usize, hi : usize) -> usize {
+    if lo >= hi {
+        return 0;
+    }
+    let mid = lo + (hi - lo) / 2;
+    let left = build(spans, max_end, lo, mid);
+    let right = build(spans, max_end, mid + 1, hi);
+    max_end[mid] = spans[mid].1.max(left).max(right);
+    max_end[mid]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlapping() {
+        let spans = vec![(10, 20), (0, 5), (3, 12), (30, 40), (15, 16), (50, 60), (12, 12)];
+        let index = IntervalIndex::new(&spans);
+        for start in 0..65 {
+            for end in start..65 {
+                let expected: Vec<usize> = spans.iter().enumerate()
+                    .filter(|(_, &(s, e))| s < end && start < e)
+                    .map(|(i, _)| i).collect();
+                assert_eq!(index.overlapping(start..end), expected);
+                assert_eq!(index.any_overlapping(start..end), !expected.is_empty());
+            }
+        }
+        assert!(IntervalIndex::new(&[]).overlapping(0..10).is_empty());
+    }
+}
@@ -264,6 +264,64 @@ impl LayerDesc {
             meta
          })
     }
+
+    /// List the fields in which this description differs from another
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The description to compare against
+    ///
+    /// # Returns
+    ///
+    /// A description of each differing field, empty if the descriptions are identical
+    pub fn diff(&self, other: &LayerDesc) -> Vec<String> {
+        let mut diffs = Vec::new();
+        if self.layer_type != other.layer_type {
+            diffs.push(format!("type ({} != {})", self.layer_type, other.layer_type));
+        }
+        if self.base != other.base {
+            diffs.push(format!("base ({:?} != {:?})", self.base, other.base));
+        }
+        if self.data != other.data {
+            diffs.push(format!("data ({:?} != {:?})", self.data, other.data));
+        }
+        if self.link_types != other.link_types {
+            diffs.push(format!("link_types ({:?} != {:?})", self.link_types, other.link_types));
+        }
+        if self.target != other.target {
+            diffs.push(format!("target ({:?} != {:?})", self.target, other.target));
+        }
+        if self.default != other.default {
+            diffs.push(format!("default ({:?} != {:?})", self.default, other.default));
+        }
+        if self.meta != other.meta {
+            diffs.push(format!("meta ({:?} != {:?})", self.meta, other.meta));
+        }
+        diffs
+    }
+}
+
+/// Merge layer descriptions into an existing metadata map. A layer that is
+/// described identically in both is left unchanged, while a layer with a
+/// conflicting description is an error that lists the differing fields.
+///
+/// # Arguments
+///
+/// * `existing` - The metadata to merge into
+/// * `incoming` - The new layer descriptions
+pub fn merge_compatible_meta(existing: &mut HashMap<String, LayerDesc>,
+    incoming: HashMap<String, LayerDesc>) -> TeangaResult<()> {
+    for (name, desc) in incoming.iter().sorted_by_key(|(name, _)| *name) {
+        if let Some(current) = existing.get(name) {
+            let diffs = current.diff(desc);
+            if !diffs.is_empty() {
+                return Err(TeangaError::ModelError(
+                    format!("Conflicting definitions of layer {}: {}", name, diffs.join(", "))));
+            }
+        }
+    }
+    existing.extend(incoming);
+    Ok(())
 }
 
 /// A layer in a document
@@ -398,7 +456,8 @@ impl Layer {
                             last = Some(i as usize);
                         }
                         if let Some(l) = last {
-                            pairwise.push((subindexes[l].0, subindexes[l].1));
+                            let end = subindexes.last().map_or(subindexes[l].1, |s| s.1);
+                            pairwise.push((subindexes[l].0, end));
                         }
                         Ok(pairwise)
                     }
@@ -564,7 +623,11 @@ pub enum DataType {
     /// A value for a set of enumerated values
     Enum(Vec<String>),
     /// A link to another annotation in this layer or another layer in the documnent
-    Link
+    Link,
+    /// A floating point number, stored in its string form
+    Float,
+    /// An integer, stored in its string form
+    Int
 }
 
 impl Serialize for DataType {
@@ -578,7 +641,9 @@ impl Serialize for DataType {
                 }
                 seq.end()
             },
-            DataType::Link => serializer.serialize_str("link")
+            DataType::Link => serializer.serialize_str("link"),
+            DataType::Float => serializer.serialize_str("float"),
+            DataType::Int => serializer.serialize_str("int")
         }
     }
 }
@@ -599,6 +664,10 @@ impl<'de> Deserialize<'de> for DataType {
                     "String" => Ok(DataType::String),
                     "link" => Ok(DataType::Link),
                     "Link" => Ok(DataType::Link),
+                    "float" => Ok(DataType::Float),
+                    "Float" => Ok(DataType::Float),
+                    "int" => Ok(DataType::Int),
+                    "Int" => Ok(DataType::Int),
                     _ => Err(serde::de::Error::invalid_value(serde::de::Unexpected::Str(value), &self))
                 }
             }
@@ -621,6 +690,8 @@ impl Display for DataType {
             DataType::String => write!(f, "string"),
             DataType::Enum(vals) => write!(f, "enum({})", vals.iter().join(",")),
             DataType::Link => write!(f, "link"),
+            DataType::Float => write!(f, "float"),
+            DataType::Int => write!(f, "int"),
         }
     }
 }
@@ -24,31 +24,48 @@ use fjall;
 use redb;
 use sha2::{Digest, Sha256};
 use base64::Engine;
-use base64::engine::general_purpose::STANDARD;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
 use itertools::Itertools;
 use serde::{Serialize,Deserialize};
 use thiserror::Error;
 
 pub mod channel_corpus;
+#[cfg(feature = "zstd")]
+pub mod compressed;
+pub mod concordance;
+pub mod conllu;
 #[cfg(any(feature = "sled", feature = "fjall", feature = "redb"))]
 pub mod disk_corpus;
 pub mod document;
+pub mod formats;
+pub mod interval;
 pub mod layer;
 pub mod layer_builder;
 pub mod query;
 pub mod serialization;
+pub mod stats;
+pub mod text_index;
+pub mod validate;
 pub mod match_condition;
 mod cuac;
 
 pub use document::{Document, DocumentContent, DocumentBuilder};
 #[cfg(any(feature = "sled", feature = "fjall", feature = "redb"))]
 pub use disk_corpus::{DiskCorpus, PathAsDB};
-pub use layer::{IntoLayer, Layer, LayerDesc, DataType, LayerType, TeangaData};
+pub use layer::{IntoLayer, Layer, LayerDesc, DataType, LayerType, TeangaData, merge_compatible_meta};
 pub use layer_builder::build_layer;
 pub use query::Query;
-pub use serialization::{read_json, read_yaml, write_json, write_yaml, read_yaml_with_config, read_json_with_config, read_jsonl, SerializationSettings};
+pub use serialization::{read_json, read_yaml, write_json, write_yaml, read_yaml_with_config, read_yaml_docs, YamlDocReader, read_json_with_config, read_jsonl, read_jsonl_line, write_jsonl, SerializationSettings};
 pub use cuac::{write_cuac, write_cuac_with_config, read_cuac, write_cuac_header, write_cuac_config, write_cuac_doc, doc_content_to_bytes, bytes_to_doc, Index, IndexResult, CuacReadError, CuacWriteError, CuacConfig, StringCompression, StringCompressionError, StringCompressionMethod, NoCompression, SmazCompression, ShocoCompression};
 pub use match_condition::{TextMatchCondition, DataMatchCondition};
+pub use text_index::TextIndex;
+pub use interval::IntervalIndex;
+pub use validate::{validate_corpus, validate_doc, ValidationError};
+#[cfg(feature = "zstd")]
+pub use compressed::{read_compressed, write_compressed, write_compressed_with_level, CompressedReader, CompressedWriter};
+pub use concordance::ConcordanceLine;
+pub use stats::{CorpusStats, LayerStats};
+pub use conllu::{read_conllu, read_conllu_with_layers, write_conllu, write_conllu_with_layers, ConlluLayers, ConlluError};
 
 /// Trait that defines a corpus according to the Teanga Data Model
 pub trait Corpus : WriteableCorpus + ReadableCorpus {
@@ -64,6 +81,9 @@ pub trait Corpus : WriteableCorpus + ReadableCorpus {
     /// * `target` - The target layer for this layer (if using link data)
     /// * `default` - The default values for this layer
     /// * `meta` - The metadata for this layer
+    ///
+    /// A layer that is already described is replaced. Use `merge_meta` to
+    /// check a description against the existing one instead
     fn add_layer_meta(&mut self, name: String, layer_type: LayerType, 
         base: Option<String>, data: Option<DataType>, link_types: Option<Vec<String>>, 
         target: Option<String>, default: Option<Layer>,
@@ -201,12 +221,53 @@ pub trait Corpus : WriteableCorpus + ReadableCorpus {
             Err(_) => false
         }))
     }
+
+    /// Build a keyword-in-context concordance of a term in a characters layer
+    ///
+    /// # Arguments
+    ///
+    /// * `layer` - The characters layer to search
+    /// * `term` - The exact text to find
+    /// * `context_chars` - The number of characters of context on each side
+    /// * `overlapping` - If given, only matches overlapping an annotation of
+    ///   this layer are returned
+    ///
+    /// # Returns
+    ///
+    /// The lines of the concordance in corpus order
+    fn concordance(&self, layer : &str, term : &str, context_chars : usize,
+        overlapping : Option<&str>) -> TeangaResult<Vec<ConcordanceLine>> where Self : Sized {
+        concordance::concordance(self, layer, term, context_chars, overlapping)
+    }
+
+    /// Compute summary statistics of the corpus: the number of documents and
+    /// characters, and the annotation counts and value frequencies of each
+    /// layer
+    fn stats(&self) -> TeangaResult<CorpusStats> where Self : Sized {
+        stats::corpus_stats(self)
+    }
+
+    /// Search the corpus for individual annotations
+    ///
+    /// # Returns
+    ///
+    /// A query builder, run with `AnnotationQuery::run`
+    fn query(&self) -> crate::query::AnnotationQuery<'_, Self> where Self : Sized {
+        crate::query::AnnotationQuery::new(self)
+    }
 }
 
 /// A corpus where the metadata and order can be changed
 pub trait WriteableCorpus {
     /// Set the metadata of the corpus
     fn set_meta(&mut self, meta : HashMap<String, LayerDesc>) -> TeangaResult<()>;
+    /// Add layer descriptions to the metadata of the corpus. Descriptions
+    /// identical to existing ones are ignored and conflicting ones are an
+    /// error. The default implementation cannot see the existing metadata and
+    /// replaces it, so corpora that can should use `merge_compatible_meta`
+    fn merge_meta(&mut self, meta : HashMap<String, LayerDesc>) -> TeangaResult<()> {
+        self.set_meta(meta)
+    }
     /// Set the order of the documents in the corpus
     fn set_order(&mut self, order : Vec<String>) -> TeangaResult<()>;
     /// Add a document to this corpus
@@ -254,9 +315,170 @@ impl SimpleCorpus {
         Ok(crate::serialization::read_yaml_with_config(r, self, SerializationSettings::new().header_only())?)
     }
 
+    /// Read a corpus from a YAML stream. The `_meta` block is read first and
+    /// documents are then parsed and added one at a time, so the whole file
+    /// is never held in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader to read from
+    ///
+    /// # Returns
+    ///
+    /// A new corpus containing the documents in the stream
+    pub fn from_yaml_reader<R: std::io::Read>(reader: R) -> TeangaResult<SimpleCorpus> {
+        let mut corpus = SimpleCorpus::new();
+        crate::serialization::read_yaml(reader, &mut corpus).map_err(|e| match e {
+            crate::serialization::SerializeError::Teanga(e) => e,
+            e => TeangaError::ModelError(e.to_string())
+        })?;
+        Ok(corpus)
+    }
+
+    /// Read a corpus from a directory holding a `_meta.json` file with the
+    /// layer descriptions and one `.json` file per document. Each document
+    /// is keyed by the stem of its file name, decoded as written by `to_dir`,
+    /// rather than by a hash of its content. Documents are added in the
+    /// sorted order of their file names.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The directory to read from
+    ///
+    /// # Returns
+    ///
+    /// A new corpus containing the documents in the directory
+    pub fn from_dir(path: &std::path::Path) -> TeangaResult<SimpleCorpus> {
+        let file_error = |file: &std::path::Path, e: &dyn std::fmt::Display| TeangaError::ModelError(
+            format!("Could not read {}: {}", file.display(), e));
+        let mut corpus = SimpleCorpus::new();
+        let meta_path = path.join("_meta.json");
+        let meta_file = std::fs::File::open(&meta_path).map_err(|e| file_error(&meta_path, &e))?;
+        let meta : HashMap<String, LayerDesc> = serde_json::from_reader(std::io::BufReader::new(meta_file))
+            .map_err(|e| file_error(&meta_path, &e))?;
+        corpus.set_meta(meta)?;
+
+        let mut doc_paths = Vec::new();
+        for entry in std::fs::read_dir(path).map_err(|e| file_error(path, &e))? {
+            let doc_path = entry.map_err(|e| file_error(path, &e))?.path();
+            if doc_path.extension().map_or(false, |ext| ext == "json") && doc_path != meta_path {
+                doc_paths.push(doc_path);
+            }
+        }
+        doc_paths.sort();
+        for doc_path in doc_paths {
+            let doc_file = std::fs::File::open(&doc_path).map_err(|e| file_error(&doc_path, &e))?;
+            let doc : HashMap<String, Layer> = serde_json::from_reader(std::io::BufReader::new(doc_file))
+                .map_err(|e| file_error(&doc_path, &e))?;
+            let doc = Document::new(doc, &corpus.meta).map_err(|e| file_error(&doc_path, &e))?;
+            let id = doc_path.file_stem().map(|s| id_from_file_name(&s.to_string_lossy())).unwrap_or_default();
+            if corpus.content.contains_key(&id) {
+                return Err(file_error(&doc_path, &format!("duplicate document ID {}", id)));
+            }
+            corpus.order.push(id.clone());
+            corpus.content.insert(id, doc);
+        }
+        Ok(corpus)
+    }
+
+    /// Write the corpus to a directory as a `_meta.json` file with the layer
+    /// descriptions and one `<doc_id>.json` file per document. Keys are written
+    /// in sorted order so that the files are stable under version control.
+    /// Characters in IDs that cannot appear in file names are percent-encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The directory to write to, which is created if needed
+    pub fn to_dir(&self, path: &std::path::Path) -> TeangaResult<()> {
+        let file_error = |file: &std::path::Path, e: &dyn std::fmt::Display| TeangaError::ModelError(
+            format!("Could not write {}: {}", file.display(), e));
+        std::fs::create_dir_all(path).map_err(|e| file_error(path, &e))?;
+        let meta_path = path.join("_meta.json");
+        let meta : std::collections::BTreeMap<&String, &LayerDesc> = self.meta.iter().collect();
+        write_canonical_json(&meta_path, &meta).map_err(|e| file_error(&meta_path, &e))?;
+        for id in &self.order {
+            let doc = self.content.get(id).ok_or(TeangaError::DocumentNotFoundError)?;
+            let doc_path = path.join(format!("{}.json", id_to_file_name(id)));
+            let layers : std::collections::BTreeMap<&String, &Layer> = doc.content.iter().collect();
+            write_canonical_json(&doc_path, &layers).map_err(|e| file_error(&doc_path, &e))?;
+        }
+        Ok(())
+    }
+
+    /// Read a corpus from the Teanga binary format (TCF), which is the format
+    /// implemented by the Cuac reader
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded corpus
+    ///
+    /// # Returns
+    ///
+    /// A new corpus containing the metadata and documents of the input
+    pub fn from_tcf(bytes: &[u8]) -> TeangaResult<SimpleCorpus> {
+        let mut corpus = SimpleCorpus::new();
+        read_cuac(bytes, &mut corpus).map_err(|e| TeangaError::ModelError(
+            format!("Could not read TCF: {}", e)))?;
+        Ok(corpus)
+    }
+
+    /// Write this corpus in the Teanga binary format (TCF) with the default
+    /// Cuac configuration. Only layers declared in the metadata are part of
+    /// the format, so undeclared document metadata is not written.
+    ///
+    /// # Returns
+    ///
+    /// The encoded corpus
+    pub fn to_tcf(&self) -> TeangaResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        write_cuac(&mut bytes, self).map_err(|e| TeangaError::ModelError(
+            format!("Could not write TCF: {}", e)))?;
+        Ok(bytes)
+    }
+}
+
+/// Write a value as compact JSON followed by a newline
+fn write_canonical_json<T: Serialize>(path: &std::path::Path, value: &T) -> Result<(), TeangaJsonError> {
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    serde_json::to_writer(&mut out, value)?;
+    std::io::Write::write_all(&mut out, b"\n")?;
+    Ok(())
+}
+
+/// Percent-encode the characters of a document ID that are not safe in file names
+fn id_to_file_name(id: &str) -> String {
+    id.replace('%', "%25").replace('/', "%2F")
+}
+
+/// Reverse `id_to_file_name`
+fn id_from_file_name(name: &str) -> String {
+    name.replace("%2F", "/").replace("%25", "%")
 }
 
 impl Corpus for SimpleCorpus {
+    /// Add multiple documents to the corpus. All documents are validated
+    /// before any is added and their IDs are hashed in parallel
+    fn add_docs<D : IntoLayer, DC : DocumentContent<D>>(&mut self, content : Vec<DC>) -> TeangaResult<Vec<String>> {
+        let docs = content.into_iter()
+            .map(|c| Document::new(c, &self.meta))
+            .collect::<TeangaResult<Vec<_>>>()?;
+        let codes = content_codes(&docs);
+        let mut taken : std::collections::HashSet<String> = self.order.iter().cloned().collect();
+        let mut ids = Vec::with_capacity(docs.len());
+        for (doc, code) in docs.into_iter().zip(codes) {
+            let mut n = 4;
+            while taken.contains(&code[..n]) && n < code.len() {
+                n += 1;
+            }
+            let id = code[..n].to_string();
+            taken.insert(id.clone());
+            self.order.push(id.clone());
+            self.content.insert(id.clone(), doc);
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
     fn add_layer_meta(&mut self, name: String, layer_type: LayerType, 
         base: Option<String>, data: Option<DataType>, link_types: Option<Vec<String>>, 
         target: Option<String>, default: Option<Layer>,
@@ -334,6 +556,10 @@ impl WriteableCorpus for SimpleCorpus {
         Ok(())
     }
 
+    fn merge_meta(&mut self, meta : HashMap<String, LayerDesc>) -> TeangaResult<()> {
+        merge_compatible_meta(&mut self.meta, meta)
+    }
+
     fn set_order(&mut self, order : Vec<String>) -> TeangaResult<()> {
         self.order = order;
         Ok(())
@@ -389,19 +615,7 @@ pub enum Value {
 ///
 /// A unique ID for the document
 pub fn teanga_id(existing_keys : &Vec<String>, doc : &Document) -> String {
-let mut hasher = Sha256::new();
-for key in doc.content.keys().sorted() {
-    match doc.content.get(key).unwrap() {
-        Layer::Characters(val) => {
-            hasher.update(key.as_bytes());
-            hasher.update(vec![0u8]);
-            hasher.update(val.as_bytes());
-            hasher.update(vec![0u8]);
-        }
-        _ => ()
-    }
-}
-let code = STANDARD.encode(hasher.finalize().as_slice());
+let code = content_code(doc);
 let mut n = 4;
 while existing_keys.contains(&code[..n].to_string()) && n < code.len() {
     n += 1;
@@ -423,24 +637,72 @@ return code[..n].to_string();
 ///
 /// A unique ID for the document
 pub fn teanga_id_update(prev_val : &str, existing_keys: &Vec<String>, doc : &Document) -> String {
-let mut hasher = Sha256::new();
-for key in doc.content.keys().sorted() {
-    match doc.content.get(key).unwrap() {
-        Layer::Characters(val) => {
+let code = content_code(doc);
+let mut n = 4;
+while *prev_val != code[..n] && existing_keys.contains(&code[..n].to_string()) && n < code.len() {
+    n += 1;
+}
+return code[..n].to_string();
+}
+
+/// The base64 SHA-256 hash of the characters layers of a document. A
+/// document ID is the shortest unused prefix of at least four characters
+fn content_code(doc : &Document) -> String {
+    let mut hasher = Sha256::new();
+    for key in doc.content.keys().sorted() {
+        if let Some(Layer::Characters(val)) = doc.content.get(key) {
             hasher.update(key.as_bytes());
             hasher.update(vec![0u8]);
             hasher.update(val.as_bytes());
             hasher.update(vec![0u8]);
         }
-        _ => ()
     }
+    STANDARD.encode(hasher.finalize().as_slice())
 }
-let code = STANDARD.encode(hasher.finalize().as_slice());
-let mut n = 4;
-while *prev_val != code[..n] && existing_keys.contains(&code[..n].to_string()) && n < code.len() {
-    n += 1;
+
+/// A URL-safe SHA-256 hash of every layer of a document. Unlike the
+/// document ID it covers all layers and does not depend on the other
+/// documents of the corpus, so identical documents in different corpora
+/// get the same value
+///
+/// # Arguments
+///
+/// * `doc` - The document
+///
+/// # Returns
+///
+/// The hash as 43 URL-safe base64 characters
+pub fn content_id(doc : &Document) -> TeangaResult<String> {
+    let mut hasher = Sha256::new();
+    for key in doc.content.keys().sorted() {
+        // Going through `serde_json::Value` sorts the keys of any objects
+        let value = serde_json::to_value(&doc.content[key])
+            .map_err(|e| TeangaError::ModelError(e.to_string()))?;
+        hasher.update(key.as_bytes());
+        hasher.update(vec![0u8]);
+        hasher.update(value.to_string().as_bytes());
+        hasher.update(vec![0u8]);
+    }
+    Ok(URL_SAFE_NO_PAD.encode(hasher.finalize().as_slice()))
 }
-return code[..n].to_string();
+
+/// Hash many documents with `content_code`, spread over the available
+/// threads where the platform has them
+fn content_codes(docs : &[Document]) -> Vec<String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        if threads > 1 && docs.len() >= 2 * threads {
+            let chunk_size = (docs.len() + threads - 1) / threads;
+            return std::thread::scope(|scope| {
+                let handles: Vec<_> = docs.chunks(chunk_size)
+                    .map(|chunk| scope.spawn(move || chunk.iter().map(content_code).collect::<Vec<_>>()))
+                    .collect();
+                handles.into_iter().flat_map(|h| h.join().expect("Hashing thread panicked")).collect()
+            });
+        }
+    }
+    docs.iter().map(content_code).collect()
 }
 
 /// An error type for Teanga
@@ -597,4 +859,119 @@ mod test {
         assert!(doc.get("words").is_some());
         assert!(doc.get("pos").is_some());
     }
+
+    #[test]
+    fn test_add_layer_meta_replaces() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.add_layer_meta("text".to_string(), LayerType::characters, None, None, None, None, None, HashMap::new()).unwrap();
+        corpus.add_layer_meta("words".to_string(), LayerType::span, Some("text".to_string()), None, None, None, None, HashMap::new()).unwrap();
+        corpus.add_layer_meta("words".to_string(), LayerType::div, Some("text".to_string()), None, None, None, None, HashMap::new()).unwrap();
+        assert_eq!(corpus.get_meta()["words"].layer_type, LayerType::div);
+        let words = LayerDesc::new("words", LayerType::span, Some("text".to_string()), None, None, None, None, HashMap::new()).unwrap();
+        let err = corpus.merge_meta(HashMap::from([("words".to_string(), words)])).unwrap_err();
+        assert!(format!("{}", err).contains("type (div != span)"));
+    }
+
+    #[test]
+    fn test_from_yaml_reader() {
+        let data = "_meta:\n  text:\n    type: characters\nKjco:\n  text: This is a document.\n";
+        let corpus = SimpleCorpus::from_yaml_reader(data.as_bytes()).unwrap();
+        assert_eq!(corpus.get_docs(), vec!["Kjco".to_string()]);
+        assert!(corpus.get_meta().contains_key("text"));
+        assert!(matches!(SimpleCorpus::from_yaml_reader("_meta: [".as_bytes()),
+            Err(TeangaError::ModelError(_))));
+    }
+
+    #[test]
+    fn test_content_id() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        let id1 = corpus.build_doc().layer("text", "a b").unwrap()
+            .layer("words", vec![(0, 1), (2, 3)]).unwrap().add().unwrap();
+        let id2 = corpus.build_doc().layer("text", "a b").unwrap()
+            .layer("words", vec![(0, 3)]).unwrap().add().unwrap();
+        let code = content_id(&corpus.get_doc_by_id(&id1).unwrap()).unwrap();
+        assert_eq!(code.len(), 43);
+        assert!(code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_ne!(content_id(&corpus.get_doc_by_id(&id2).unwrap()).unwrap(), code);
+    }
+
+    #[test]
+    fn test_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("_meta.json"), r#"{"text":{"type":"characters"}}"#).unwrap();
+        std::fs::write(dir.path().join("Kjco.json"), r#"{"text":"This is a document."}"#).unwrap();
+        std::fs::write(dir.path().join("abcé.json"), r#"{"text":"Named by hand"}"#).unwrap();
+        let corpus = SimpleCorpus::from_dir(dir.path()).unwrap();
+        assert_eq!(corpus.get_docs(), vec!["Kjco".to_string(), "abcé".to_string()]);
+        assert_eq!(corpus.get_doc_by_id("abcé").unwrap().get("text"),
+            Some(&Layer::Characters("Named by hand".to_string())));
+
+        std::fs::write(dir.path().join("bad.json"), r#"{"text":"#).unwrap();
+        let err = SimpleCorpus::from_dir(dir.path()).unwrap_err();
+        assert!(format!("{}", err).contains("bad.json"));
+    }
+
+    #[test]
+    fn test_to_dir() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        let mut ids = Vec::new();
+        for text in ["This is a document.", "Another one", "A third document"] {
+            ids.push(corpus.build_doc().layer("text", text).unwrap()
+                .layer("words", vec![(0, 1)]).unwrap().add().unwrap());
+        }
+        let dir = tempfile::tempdir().unwrap();
+        corpus.to_dir(dir.path()).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("Kjco.json")).unwrap(),
+            "{\"text\":\"This is a document.\",\"words\":[[0,1]]}\n");
+        let corpus2 = SimpleCorpus::from_dir(dir.path()).unwrap();
+        ids.sort_by_key(|id| id_to_file_name(id));
+        assert_eq!(corpus2.get_docs(), ids);
+        assert_eq!(id_from_file_name(&id_to_file_name("a/b%c")), "a/b%c");
+    }
+
+    #[test]
+    fn test_tcf_roundtrip() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_doc()
+            .layer("text", "This is a document.").unwrap()
+            .layer("words", vec![(0, 4), (5, 7), (8, 9), (10, 18)]).unwrap()
+            .layer("pos", vec!["DT", "VBZ", "DT", "NN"]).unwrap()
+            .add().unwrap();
+        corpus.build_layer("source").add().unwrap();
+        corpus.add_doc(vec![
+            ("text".to_string(), Layer::Characters("Another".to_string())),
+            ("source".to_string(), Layer::MetaLayer(Some(Value::String("web".to_string())))),
+        ]).unwrap();
+        let corpus2 = SimpleCorpus::from_tcf(&corpus.to_tcf().unwrap()).unwrap();
+        assert_eq!(corpus2.get_meta(), corpus.get_meta());
+        assert_eq!(corpus2.get_docs(), corpus.get_docs());
+        for id in corpus.get_docs() {
+            assert_eq!(corpus2.get_doc_by_id(&id).unwrap(), corpus.get_doc_by_id(&id).unwrap());
+        }
+    }
+    #[test]
+    fn test_add_docs_batch() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let texts: Vec<String> = (0..200).map(|i| format!("Document number {}", i % 150)).collect();
+        let mut one_by_one = corpus.clone();
+        let expected: Vec<String> = texts.iter()
+            .map(|t| one_by_one.add_doc(vec![("text".to_string(), t.clone())]).unwrap())
+            .collect();
+        let ids = corpus.add_docs(texts.iter().map(|t| vec![("text".to_string(), t.clone())]).collect()).unwrap();
+        assert_eq!(ids, expected);
+        assert_eq!(corpus, one_by_one);
+        let before = corpus.clone();
+        assert!(corpus.add_docs(vec![vec![("text".to_string(), "ok".to_string())],
+            vec![("missing".to_string(), "bad".to_string())]]).is_err());
+        assert_eq!(corpus, before);
+    }
 }
@@ -11,8 +11,21 @@
 //!     .text("words", "fox")
 //!     .build();
 //! ```
+//!
+//! Individual annotations can be searched with `Corpus::query`, which
+//! returns the document ID, annotation index and character span of each
+//! match.
+//!
+//! ```
+//! use teanga::*;
+//! let mut corpus = SimpleCorpus::new();
+//! corpus.build_layer("text").add().unwrap();
+//! let matches = corpus.query().layer("text").run().unwrap();
+//! assert!(matches.is_empty());
+//! ```
 use std::collections::{HashMap, HashSet};
-use crate::{Document, LayerDesc, TeangaData};
+use crate::{Corpus, Document, LayerDesc, TeangaData, TeangaError, TeangaResult};
+use crate::document::characters_layer;
 use regex::Regex;
 
 /// A query for searching a corpus
@@ -318,6 +331,82 @@ impl QueryBuilder {
     }
 }
 
+/// A search for individual annotations of a layer. Created with
+/// `Corpus::query`
+pub struct AnnotationQuery<'a, C : Corpus> {
+    corpus: &'a C,
+    layer: Option<String>,
+    equals: Option<TeangaData>,
+    overlapping: Option<String>
+}
+
+impl<'a, C : Corpus> AnnotationQuery<'a, C> {
+    /// Start a search over a corpus
+    pub fn new(corpus : &'a C) -> AnnotationQuery<'a, C> {
+        AnnotationQuery { corpus, layer: None, equals: None, overlapping: None }
+    }
+
+    /// Set the layer whose annotations are searched
+    pub fn layer(mut self, layer : &str) -> Self {
+        self.layer = Some(layer.to_string());
+        self
+    }
+
+    /// Only match annotations with this data value
+    pub fn equals<T : Into<TeangaData>>(mut self, value : T) -> Self {
+        self.equals = Some(value.into());
+        self
+    }
+
+    /// Only match annotations whose text overlaps an annotation of this layer
+    pub fn overlapping(mut self, layer : &str) -> Self {
+        self.overlapping = Some(layer.to_string());
+        self
+    }
+
+    /// Run the search
+    ///
+    /// # Returns
+    ///
+    /// The document ID, the index of the annotation in the layer and its
+    /// span in the underlying characters layer for each match, in corpus order
+    pub fn run(&self) -> TeangaResult<Vec<(String, usize, (usize, usize))>> {
+        let layer = self.layer.as_ref().ok_or_else(|| TeangaError::ModelError(
+            "No layer given for the query".to_string()))?;
+        let meta = self.corpus.get_meta();
+        let char_layer = characters_layer(layer, meta)?;
+        let mut matches = Vec::new();
+        for res in self.corpus.iter_doc_ids() {
+            let (id, doc) = res?;
+            if doc.get(layer).is_none() {
+                continue;
+            }
+            let spans = doc.indexes(layer, &char_layer, meta)?;
+            let data = doc.data(layer, meta);
+            let others = match self.overlapping {
+                Some(ref other) if doc.get(other).is_some() =>
+                    Some(doc.interval_index(other, meta)?),
+                Some(_) => continue,
+                None => None
+            };
+            for (index, &(start, end)) in spans.iter().enumerate() {
+                if let Some(ref value) = self.equals {
+                    if data.as_ref().and_then(|d| d.get(index)) != Some(value) {
+                        continue;
+                    }
+                }
+                if let Some(ref others) = others {
+                    if !others.any_overlapping(start..end) {
+                        continue;
+                    }
+                }
+                matches.push((id.clone(), index, (start, end)));
+            }
+        }
+        Ok(matches)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -375,5 +464,33 @@ mod test {
         let mut iter = corpus.search(query);
         assert!(iter.next().is_some());
     }
+
+    #[test]
+    fn test_annotation_query() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words")
+            .layer_type(LayerType::span)
+            .base("text").add().unwrap();
+        corpus.build_layer("pos")
+            .layer_type(LayerType::seq)
+            .base("words")
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("ner")
+            .layer_type(LayerType::span)
+            .base("words")
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "Dublin is a city in Ireland").unwrap()
+            .layer("words", vec![(0, 6), (7, 9), (10, 11), (12, 16), (17, 19), (20, 27)]).unwrap()
+            .layer("pos", vec!["PROPN", "AUX", "DET", "NOUN", "ADP", "PROPN"]).unwrap()
+            .layer("ner", vec![(5, 6, "LOC")]).unwrap()
+            .add().unwrap();
+        assert_eq!(corpus.query().layer("pos").equals("PROPN").run().unwrap(),
+            vec![(id.clone(), 0, (0, 6)), (id.clone(), 5, (20, 27))]);
+        assert_eq!(corpus.query().layer("pos").equals("PROPN").overlapping("ner").run().unwrap(),
+            vec![(id.clone(), 5, (20, 27))]);
+        assert!(corpus.query().run().is_err());
+    }
 }
 
@@ -1,5 +1,5 @@
 //! Serialization support for Teanga
-use crate::{WriteableCorpus, ReadableCorpus, LayerDesc, Layer, TeangaJsonError, Document};
+use crate::{WriteableCorpus, ReadableCorpus, LayerDesc, Layer, DataType, TeangaJsonError, Document, merge_compatible_meta};
 use itertools::Itertools;
 use serde::Deserializer;
 use serde::de::Visitor;
@@ -52,8 +52,11 @@ impl <'de,'a, C: WriteableCorpus> Visitor<'de> for TeangaVisitor2<'a, C> {
         while let Some(ref key) = map.next_key::<String>()? {
             if key == "_meta" {
                 let data = map.next_value::<HashMap<String, LayerDesc>>()?;
-                self.0.set_meta(data)
+                self.0.merge_meta(data)
                     .map_err(serde::de::Error::custom)?;
+            } else if key == "_corpus_meta" {
+                // Corpus-level metadata is not part of the corpus model
+                map.next_value::<serde::de::IgnoredAny>()?;
             } else if !self.1.header_only && key == "_order" {
                 order = Some(map.next_value::<Vec<String>>()?);
             } else if !self.1.header_only {
@@ -114,7 +117,7 @@ pub fn pretty_yaml_serialize<W : Write, C: ReadableCorpus>(corpus: &C, mut write
     for name in corpus.get_meta().keys().sorted() {
         let meta = &corpus.get_meta()[name];
         writer.write_all(b"    ")?;
-        writer.write_all(name.as_bytes())?;
+        writer.write_all(yaml_key(name).as_bytes())?;
         writer.write_all(b":\n")?;
         writer.write_all(b"        type: ")?;
         writer.write_all(serde_yml::to_string(&meta.layer_type)?.as_bytes())?;
@@ -124,7 +127,13 @@ pub fn pretty_yaml_serialize<W : Write, C: ReadableCorpus>(corpus: &C, mut write
         }
         if let Some(ref data) = meta.data {
             writer.write_all(b"        data: ")?;
-            writer.write_all(serde_yml::to_string(data)?.as_bytes())?;
+            if let DataType::Enum(_) = data {
+                // A block sequence cannot follow the key on the same line
+                writer.write_all(serde_json::to_string(data)?.as_bytes())?;
+                writer.write_all(b"\n")?;
+            } else {
+                writer.write_all(serde_yml::to_string(data)?.as_bytes())?;
+            }
         }
         if let Some(ref values) = meta.link_types {
             writer.write_all(b"        link_types: ")?;
@@ -140,22 +149,31 @@ pub fn pretty_yaml_serialize<W : Write, C: ReadableCorpus>(corpus: &C, mut write
             writer.write_all(serde_json::to_string(default)?.as_bytes())?;
             writer.write_all(b"\n")?;
         }
+        if !meta.meta.is_empty() {
+            writer.write_all(b"        meta: ")?;
+            writer.write_all(serde_json::to_string(&meta.meta)?.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
     }
     for res in corpus.iter_doc_ids() {
         let (id, doc) = res?;
-        writer.write_all(id.as_bytes())?;
+        writer.write_all(yaml_key(&id).as_bytes())?;
         writer.write_all(b":\n")?;
         for name in doc.keys().iter().sorted() {
             let layer = &doc[name];
-            if let Layer::Characters(_) = layer {
-                writer.write_all(b"    ")?;
-                writer.write_all(name.as_bytes())?;
-                writer.write_all(b": ")?;
-                writer.write_all(serde_yml::to_string(layer)?.as_bytes())?;
+            writer.write_all(b"    ")?;
+            writer.write_all(yaml_key(name).as_bytes())?;
+            writer.write_all(b": ")?;
+            if let Layer::Characters(text) = layer {
+                let scalar = serde_yml::to_string(layer)?;
+                if scalar.trim_end().contains('\n') {
+                    // A block scalar would need to be indented under the key
+                    writer.write_all(yaml_quote(text).as_bytes())?;
+                    writer.write_all(b"\n")?;
+                } else {
+                    writer.write_all(scalar.as_bytes())?;
+                }
             } else {
-                writer.write_all(b"    ")?;
-                writer.write_all(name.as_bytes())?;
-                writer.write_all(b": ")?;
                 writer.write_all(serde_json::to_string(layer)?.as_bytes())?;
                 writer.write_all(b"\n")?;
             }
@@ -164,6 +182,38 @@ pub fn pretty_yaml_serialize<W : Write, C: ReadableCorpus>(corpus: &C, mut write
     Ok(())
 }
 
+/// Quote a string as a YAML double-quoted scalar, escaping every character
+/// that YAML does not allow to appear literally
+fn yaml_quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() || matches!(c, '\u{2028}' | '\u{2029}' | '\u{feff}' | '\u{fffe}' | '\u{ffff}') =>
+                quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Write a mapping key plainly when it is safe to do so
+fn yaml_key(key: &str) -> std::borrow::Cow<'_, str> {
+    let plain = key.chars().next().map_or(false, |c| c.is_ascii_alphanumeric() || c == '_') &&
+        key.chars().all(|c| c.is_ascii_alphanumeric() || "_+/=.-".contains(c));
+    if plain {
+        key.into()
+    } else {
+        yaml_quote(key).into()
+    }
+}
+
 /// Read a corpus from JSON
 ///
 /// # Arguments
@@ -206,14 +256,14 @@ pub fn read_yaml<'de, R: Read, C: WriteableCorpus>(reader: R, corpus : &mut C) -
 // * `corpus` - The corpus to read into
 // * `settings` - The settings to use
 pub fn read_yaml_with_config<'de, R: Read, C: WriteableCorpus>(reader: R, corpus : &mut C, settings : SerializationSettings) -> Result<(), SerializeError> {
-    let char_iter = reader.bytes().filter_map(Result::ok).map(|b| b as char);
-    let parser = yaml_rust::parser::Parser::new(char_iter);
-    let mut reader = YamlStreamReader { parser };
+    let mut reader = YamlStreamReader::new(reader);
     while let Some((key, value)) = reader.next_entry()? {
         if key == "_meta" {
-            corpus.set_meta(serde_json::from_value(value)?)?;
+            corpus.merge_meta(serde_json::from_value(value)?)?;
         } else if key == "_order" {
             corpus.set_order(serde_json::from_value(value)?)?;
+        } else if key == "_corpus_meta" {
+            // Corpus-level metadata is not part of the corpus model
         } else if !settings.header_only {
             let doc : HashMap<String, Layer> = serde_json::from_value(value)?;
             let id = corpus.add_doc(doc)?;
@@ -226,9 +276,65 @@ pub fn read_yaml_with_config<'de, R: Read, C: WriteableCorpus>(reader: R, corpus
     Ok(())
 }
 
+/// Read the documents of a YAML corpus one at a time, without loading the
+/// whole file. The `_meta` entry must come before the documents that use it.
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read from
+///
+/// # Returns
+///
+/// An iterator over the ID, as given in the file, and the content of each
+/// document
+pub fn read_yaml_docs<R: Read>(reader: R) -> YamlDocReader<R> {
+    YamlDocReader { reader: YamlStreamReader::new(reader), meta: HashMap::new() }
+}
+
+/// An iterator over the documents of a YAML corpus. Created with
+/// `read_yaml_docs`
+pub struct YamlDocReader<R: Read> {
+    reader: YamlStreamReader<Utf8Chars<YamlBytes<R>>>,
+    meta: HashMap<String, LayerDesc>
+}
+
+impl<R: Read> YamlDocReader<R> {
+    /// The metadata read so far
+    pub fn meta(&self) -> &HashMap<String, LayerDesc> {
+        &self.meta
+    }
+}
+
+impl<R: Read> Iterator for YamlDocReader<R> {
+    type Item = Result<(String, Document), SerializeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key, value) = match self.reader.next_entry() {
+                Ok(Some(entry)) => entry,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e))
+            };
+            if key == "_meta" {
+                let res = serde_json::from_value(value).map_err(SerializeError::from)
+                    .and_then(|m| Ok(merge_compatible_meta(&mut self.meta, m)?));
+                if let Err(e) = res {
+                    return Some(Err(e));
+                }
+            } else if key != "_order" && key != "_corpus_meta" {
+                return Some(serde_json::from_value::<HashMap<String, Layer>>(value)
+                    .map_err(SerializeError::from)
+                    .and_then(|content| Ok(Document::new(content, &self.meta)?))
+                    .map(|doc| (key, doc)));
+            }
+        }
+    }
+}
+
 /// Read a corpus from JSONL. That is a file with one JSON document per line. 
 /// As this format does not have metadata, the corpus must have already been
-/// initialized with metadata.
+/// initialized with metadata. Documents are added one line at a time and
+/// blank lines are skipped.
 ///
 /// # Arguments
 ///
@@ -236,7 +342,11 @@ pub fn read_yaml_with_config<'de, R: Read, C: WriteableCorpus>(reader: R, corpus
 /// * `corpus` - The corpus to read into
 pub fn read_jsonl<'de, R: BufRead, C : WriteableCorpus>(reader: R, corpus : &mut C) -> Result<(), TeangaJsonError> {
     for line in reader.lines() {
-        let doc : HashMap<String, Layer> = serde_json::from_str(&line?)?;
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let doc : HashMap<String, Layer> = serde_json::from_str(&line)?;
         corpus.add_doc(doc)?;
     }
     Ok(())
@@ -296,10 +406,54 @@ use yaml_rust::parser::{Event, Parser};
 use yaml_rust::scanner::{TScalarStyle, TokenType};
 use yaml_rust::yaml::Yaml;
 
+/// Decodes a stream of UTF-8 bytes into characters, replacing invalid
+/// sequences with U+FFFD
+struct Utf8Chars<I : Iterator<Item=u8>>(std::iter::Peekable<I>);
+
+impl<I : Iterator<Item=u8>> Iterator for Utf8Chars<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let first = self.0.next()?;
+        let (len, mut code) = match first {
+            0x00..=0x7f => return Some(first as char),
+            0xc2..=0xdf => (1, (first & 0x1f) as u32),
+            0xe0..=0xef => (2, (first & 0x0f) as u32),
+            0xf0..=0xf4 => (3, (first & 0x07) as u32),
+            _ => return Some(char::REPLACEMENT_CHARACTER)
+        };
+        for _ in 0..len {
+            match self.0.peek() {
+                Some(&b) if b & 0xc0 == 0x80 => {
+                    code = (code << 6) | (b & 0x3f) as u32;
+                    self.0.next();
+                },
+                _ => return Some(char::REPLACEMENT_CHARACTER)
+            }
+        }
+        if code < [0x80, 0x800, 0x10000][len - 1] {
+            // Overlong encoding
+            return Some(char::REPLACEMENT_CHARACTER);
+        }
+        Some(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+}
+
 struct YamlStreamReader<T : Iterator<Item=char>> {
     parser : Parser<T>
 }
 
+type YamlBytes<R> = std::iter::FilterMap<std::io::Bytes<std::io::BufReader<R>>,
+    fn(std::io::Result<u8>) -> Option<u8>>;
+
+impl<R : Read> YamlStreamReader<Utf8Chars<YamlBytes<R>>> {
+    fn new(reader : R) -> Self {
+        let bytes: YamlBytes<R> = std::io::BufReader::new(reader).bytes()
+            .filter_map(Result::ok as fn(std::io::Result<u8>) -> Option<u8>);
+        YamlStreamReader { parser: Parser::new(Utf8Chars(bytes.peekable())) }
+    }
+}
+
 impl <T : Iterator<Item=char>> YamlStreamReader<T> {
     fn next_entry(&mut self) -> Result<Option<(String, serde_json::Value)>, SerializeError> {
         loop {
@@ -594,6 +748,40 @@ ecWc:
         read_json(doc.as_bytes(), &mut corpus).unwrap();
     }
 
+    #[test]
+    fn test_skip_corpus_meta() {
+        let mut corpus = SimpleCorpus::new();
+        read_json(r#"{"_meta":{"text":{"type":"characters"}},"_corpus_meta":{"license":"CC-BY"},"ecWc":{"text":"This is an example"}}"#.as_bytes(),
+            &mut corpus).unwrap();
+        assert_eq!(corpus.get_docs().len(), 1);
+        let mut corpus = SimpleCorpus::new();
+        read_yaml("_meta:\n  text:\n    type: characters\n_corpus_meta: {\"license\": \"CC-BY\"}\necWc:\n  text: This is an example\n".as_bytes(),
+            &mut corpus).unwrap();
+        assert_eq!(corpus.get_docs().len(), 1);
+    }
+
+    #[test]
+    fn test_jsonl_roundtrip() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.add_layer_meta("text".to_string(), crate::LayerType::characters,
+           None, None, None, None, None, HashMap::new()).unwrap();
+        corpus.add_layer_meta("tokens".to_string(), crate::LayerType::span,
+            Some("text".to_string()), None, None, None, None, HashMap::new()).unwrap();
+        corpus.add_doc(vec![("text".to_string(), Layer::Characters("This is an example".to_string())),
+            ("tokens".to_string(), Layer::L2(vec![(0, 4), (5, 7)]))]).unwrap();
+        corpus.add_doc(vec![("text".to_string(), Layer::Characters("Another".to_string()))]).unwrap();
+        let mut out = Vec::new();
+        write_jsonl(&mut out, &corpus).unwrap();
+        out.extend(b"\n");
+        let mut corpus2 = SimpleCorpus::new();
+        corpus2.set_meta(corpus.get_meta().clone()).unwrap();
+        read_jsonl(out.as_slice(), &mut corpus2).unwrap();
+        assert_eq!(corpus2.get_docs(), corpus.get_docs());
+        for id in corpus.get_docs() {
+            assert_eq!(corpus2.get_doc_by_id(&id).unwrap(), corpus.get_doc_by_id(&id).unwrap());
+        }
+    }
+
     #[test]
     fn test_serialize_yaml() {
         let mut corpus = SimpleCorpus::new();
@@ -623,6 +811,64 @@ ecWc:
         assert_eq!(String::from_utf8(out).unwrap(),
             "_meta:\n    text:\n        type: characters\n    tokens:\n        type: span\n        base: text\necWc:\n    text: This is an example\n    tokens: [[0,4],[5,7],[8,10],[11,18]]\n");
     }
+
+    #[test]
+    fn test_read_yaml_docs() {
+        let yaml = "_meta:\n  text:\n    type: characters\n  words:\n    type: span\n    base: text\nKjco:\n  text: This is a document.\n  words: [[0, 4], [5, 7]]\n_order: [Kjco, Abcd]\nAbcd:\n  text: Another\n";
+        let mut docs = read_yaml_docs(yaml.as_bytes());
+        let (id, doc) = docs.next().unwrap().unwrap();
+        assert_eq!(id, "Kjco");
+        assert_eq!(doc["words"], Layer::L2(vec![(0, 4), (5, 7)]));
+        assert_eq!(docs.meta().len(), 2);
+        let (id, doc) = docs.next().unwrap().unwrap();
+        assert_eq!(id, "Abcd");
+        assert_eq!(doc.text("text", docs.meta()).unwrap(), vec!["Another"]);
+        assert!(docs.next().is_none());
+        let mut docs = read_yaml_docs("Kjco:\n  text: No meta\n".as_bytes());
+        assert!(docs.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_yaml_utf8() {
+        let mut corpus = SimpleCorpus::new();
+        read_yaml("_meta:\n  text:\n    type: characters\nrUnc:\n  text: \"Caf\u{e9} \u{1f600}\"\n".as_bytes(),
+            &mut corpus).unwrap();
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[0]).unwrap();
+        assert_eq!(doc["text"], Layer::Characters("Caf\u{e9} \u{1f600}".to_string()));
+        let chars: String = Utf8Chars(b"a\xe9b\xc0\x80".iter().copied().peekable()).collect();
+        assert_eq!(chars, "a\u{fffd}b\u{fffd}\u{fffd}");
+    }
+
+    #[test]
+    fn test_pretty_yaml_enum() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.add_layer_meta("text".to_string(), crate::LayerType::characters,
+           None, None, None, None, None, HashMap::new()).unwrap();
+        corpus.add_layer_meta("tag".to_string(), crate::LayerType::seq,
+            Some("text".to_string()), Some(DataType::Enum(vec!["A".to_string(), "B".to_string()])),
+            None, None, None, HashMap::new()).unwrap();
+        corpus.add_doc(vec![("text".to_string(), Layer::Characters("ab".to_string())),
+            ("tag".to_string(), Layer::LS(vec!["A".to_string(), "B".to_string()]))]).unwrap();
+        let mut out = Vec::new();
+        pretty_yaml_serialize(&corpus, &mut out).unwrap();
+        let mut copy = SimpleCorpus::new();
+        read_yaml(out.as_slice(), &mut copy).unwrap();
+        assert_eq!(copy.get_meta(), corpus.get_meta());
+    }
+
+    #[test]
+    fn test_pretty_yaml_roundtrip() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.add_layer_meta("text: main".to_string(), crate::LayerType::characters,
+           None, None, None, None, None,
+           HashMap::from_iter(vec![("lang".to_string(), crate::Value::String("ga".to_string()))])).unwrap();
+        corpus.add_doc(vec![("text: main".to_string(), Layer::Characters("- a: b\nc".to_string()))]).unwrap();
+        let mut out = Vec::new();
+        pretty_yaml_serialize(&corpus, &mut out).unwrap();
+        let mut copy = SimpleCorpus::new();
+        read_yaml(out.as_slice(), &mut copy).unwrap();
+        assert_eq!(copy, corpus);
+    }
  
     #[test]
     fn test_1() {
@@ -658,6 +904,81 @@ aeW7:
         read_yaml(data.as_bytes(), &mut corpus).unwrap();
     }
 
+    #[test]
+    fn test_numeric_data_types() {
+        let data = "_meta:
+  text:
+    type: characters
+  tokens:
+    type: span
+    base: text
+  score:
+    type: seq
+    base: tokens
+    data: float
+  rank:
+    type: seq
+    base: tokens
+    data: int
+";
+        let mut corpus = SimpleCorpus::new();
+        read_yaml(data.as_bytes(), &mut corpus).unwrap();
+        assert_eq!(corpus.get_meta()["score"].data, Some(crate::DataType::Float));
+        assert_eq!(corpus.get_meta()["rank"].data, Some(crate::DataType::Int));
+        let mut out = Vec::new();
+        write_json(&mut out, &corpus).unwrap();
+        let json = String::from_utf8(out).unwrap();
+        assert!(json.contains("\"data\":\"float\""));
+        assert!(json.contains("\"data\":\"int\""));
+    }
+
+    #[test]
+    fn test_duplicate_meta() {
+        let data = "_meta:
+  text:
+    type: characters
+aeW7:
+  text: Foobar
+_meta:
+  text:
+    type: characters
+  tokens:
+    type: span
+    base: text
+";
+        let mut corpus = SimpleCorpus::new();
+        read_yaml(data.as_bytes(), &mut corpus).unwrap();
+        assert_eq!(corpus.get_meta().len(), 2);
+
+        let data = "_meta:
+  tokens:
+    type: span
+    base: text
+_meta:
+  tokens:
+    type: seq
+    base: text
+";
+        let err = read_yaml(data.as_bytes(), &mut SimpleCorpus::new()).unwrap_err();
+        assert!(format!("{}", err).contains("type (span != seq)"));
+    }
+
+    #[test]
+    fn test_duplicate_meta_two_files() {
+        let mut corpus = SimpleCorpus::new();
+        read_yaml("_meta:\n  text:\n    type: characters\n".as_bytes(), &mut corpus).unwrap();
+        read_json(r#"{"_meta":{"text":{"type":"characters"},"tokens":{"type":"span","base":"text"}}}"#
+            .as_bytes(), &mut corpus).unwrap();
+        assert_eq!(corpus.get_meta().len(), 2);
+        let err = read_yaml("_meta:\n  tokens:\n    type: div\n    base: text\n".as_bytes(),
+            &mut corpus).unwrap_err();
+        assert!(format!("{}", err).contains("type (span != div)"));
+        let err = read_json(r#"{"_meta":{"text":{"type":"span","base":"tokens"}}}"#.as_bytes(),
+            &mut corpus).unwrap_err();
+        assert!(format!("{}", err).contains("Conflicting definitions of layer text"));
+        assert_eq!(corpus.get_meta()["tokens"].layer_type, crate::LayerType::span);
+    }
+
     #[test]
     fn test_twitter() {
         let data = "_meta:
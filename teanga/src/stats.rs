@@ -0,0 +1,108 @@
+//! Summary statistics of a corpus.
+use std::collections::BTreeMap;
+use serde::Serialize;
+use crate::{DataType, Layer, ReadableCorpus, TeangaData, TeangaResult};
+
+/// Statistics of a whole corpus
+#[derive(Debug,Clone,PartialEq,Serialize)]
+pub struct CorpusStats {
+    /// The number of documents
+    pub documents: usize,
+    /// The total number of characters in all characters layers
+    pub characters: usize,
+    /// The statistics of each declared layer
+    pub layers: BTreeMap<String, LayerStats>
+}
+
+/// Statistics of a single layer
+#[derive(Debug,Clone,PartialEq,Serialize)]
+pub struct LayerStats {
+    /// The number of documents containing this layer
+    pub documents: usize,
+    /// The total number of annotations, or characters for a characters layer
+    pub annotations: usize,
+    /// The mean number of annotations over all documents of the corpus
+    pub mean_per_doc: f64,
+    /// How often each value occurs, for layers with string or enum data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<BTreeMap<String, usize>>
+}
+
+/// Compute the statistics of a corpus
+///
+/// # Arguments
+///
+/// * `corpus` - The corpus to summarize
+pub fn corpus_stats<C : ReadableCorpus>(corpus : &C) -> TeangaResult<CorpusStats> {
+    let meta = corpus.get_meta();
+    let mut layers: BTreeMap<String, LayerStats> = meta.iter().map(|(name, desc)| {
+        let values = match desc.data {
+            Some(DataType::String) | Some(DataType::Enum(_)) => Some(BTreeMap::new()),
+            _ => None
+        };
+        (name.clone(), LayerStats { documents: 0, annotations: 0, mean_per_doc: 0.0, values })
+    }).collect();
+    let mut documents = 0;
+    let mut characters = 0;
+    for res in corpus.iter_docs() {
+        let doc = res?;
+        documents += 1;
+        for (name, layer) in doc.content.iter() {
+            let (stats, desc) = match (layers.get_mut(name), meta.get(name)) {
+                (Some(stats), Some(desc)) => (stats, desc),
+                _ => continue
+            };
+            stats.documents += 1;
+            if let Layer::Characters(text) = layer {
+                let n = text.chars().count();
+                characters += n;
+                stats.annotations += n;
+            } else {
+                stats.annotations += layer.len();
+            }
+            if let Some(ref mut values) = stats.values {
+                for value in layer.data(desc) {
+                    if let TeangaData::String(s) = value {
+                        *values.entry(s).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+    }
+    for stats in layers.values_mut() {
+        if documents > 0 {
+            stats.mean_per_doc = stats.annotations as f64 / documents as f64;
+        }
+    }
+    Ok(CorpusStats { documents, characters, layers })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Corpus, LayerType, SimpleCorpus};
+
+    #[test]
+    fn test_corpus_stats() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").layer_type(LayerType::span).base("text").add().unwrap();
+        corpus.build_layer("pos").layer_type(LayerType::seq).base("tokens")
+            .data(DataType::Enum(vec!["NOUN".to_string(), "VERB".to_string()])).add().unwrap();
+        corpus.build_doc()
+            .layer("text", "Dogs bark").unwrap()
+            .layer("tokens", vec![(0, 4), (5, 9)]).unwrap()
+            .layer("pos", vec!["NOUN", "VERB"]).unwrap()
+            .add().unwrap();
+        corpus.build_doc().layer("text", "Café").unwrap().add().unwrap();
+        let stats = corpus.stats().unwrap();
+        assert_eq!(stats.documents, 2);
+        assert_eq!(stats.characters, 13);
+        assert_eq!(stats.layers["tokens"].documents, 1);
+        assert_eq!(stats.layers["tokens"].annotations, 2);
+        assert_eq!(stats.layers["tokens"].mean_per_doc, 1.0);
+        assert_eq!(stats.layers["tokens"].values, None);
+        assert_eq!(stats.layers["pos"].values, Some(BTreeMap::from([
+            ("NOUN".to_string(), 1), ("VERB".to_string(), 1)])));
+    }
+}
@@ -0,0 +1,179 @@
+//! Full-text search over characters layers.
+//!
+//! A `TextIndex` is an inverted index from lowercased words to their
+//! positions in a characters layer. It can be built on demand from any
+//! readable corpus, including disk corpora, and kept up to date by calling
+//! `add_doc` and `remove_doc` as documents change.
+//!
+//! # Examples
+//!
+//! ```
+//! use teanga::*;
+//! let mut corpus = SimpleCorpus::new();
+//! corpus.build_layer("text").add().unwrap();
+//! let id = corpus.build_doc().layer("text", "Climate change is real").unwrap().add().unwrap();
+//! let index = TextIndex::build(&corpus, "text").unwrap();
+//! assert_eq!(index.search_text("climate change"), vec![(id, 0, 14)]);
+//! ```
+use std::collections::{HashMap, HashSet};
+use crate::{Document, ReadableCorpus, TeangaError, TeangaResult};
+
+/// An inverted index over one characters layer of a corpus
+#[derive(Debug,Clone)]
+pub struct TextIndex {
+    layer: String,
+    docs: Vec<Option<String>>,
+    doc_numbers: HashMap<String, usize>,
+    postings: HashMap<String, Vec<Posting>>
+}
+
+/// An occurrence of a word: the document number, the word position in the
+/// document and the byte offsets of the word
+#[derive(Debug,Clone,Copy)]
+struct Posting {
+    doc: usize,
+    position: usize,
+    start: usize,
+    end: usize
+}
+
+impl TextIndex {
+    /// Create an empty index of a characters layer
+    pub fn new(layer : &str) -> TextIndex {
+        TextIndex {
+            layer: layer.to_string(),
+            docs: Vec::new(),
+            doc_numbers: HashMap::new(),
+            postings: HashMap::new()
+        }
+    }
+
+    /// Build the index for all documents of a corpus
+    ///
+    /// # Arguments
+    ///
+    /// * `corpus` - The corpus to index
+    /// * `layer` - The characters layer to index
+    pub fn build<C : ReadableCorpus>(corpus : &C, layer : &str) -> TeangaResult<TextIndex> {
+        let mut index = TextIndex::new(layer);
+        for res in corpus.iter_doc_ids() {
+            let (id, doc) = res?;
+            index.add_doc(&id, &doc)?;
+        }
+        Ok(index)
+    }
+
+    /// Add a document to the index, replacing any earlier entry with the
+    /// same ID. Documents without the indexed layer are ignored
+    pub fn add_doc(&mut self, id : &str, doc : &Document) -> TeangaResult<()> {
+        self.remove_doc(id);
+        let text = match doc.get(&self.layer) {
+            Some(layer) => layer.characters().ok_or_else(|| TeangaError::ModelError(
+                format!("Layer {} is not a characters layer", self.layer)))?,
+            None => return Ok(())
+        };
+        let number = self.docs.len();
+        self.docs.push(Some(id.to_string()));
+        self.doc_numbers.insert(id.to_string(), number);
+        for (position, (start, end)) in words(text).into_iter().enumerate() {
+            self.postings.entry(text[start..end].to_lowercase()).or_default()
+                .push(Posting { doc: number, position, start, end });
+        }
+        Ok(())
+    }
+
+    /// Remove a document from the index
+    pub fn remove_doc(&mut self, id : &str) {
+        if let Some(number) = self.doc_numbers.remove(id) {
+            self.docs[number] = None;
+            for postings in self.postings.values_mut() {
+                postings.retain(|p| p.doc != number);
+            }
+            self.postings.retain(|_, postings| !postings.is_empty());
+        }
+    }
+
+    /// Find a word or phrase, ignoring case. Words in the phrase must appear
+    /// consecutively in the text, separated by anything that is not a letter
+    /// or digit.
+    ///
+    /// # Returns
+    ///
+    /// The document ID and byte offsets in the characters layer of each
+    /// match, in the order the documents were indexed
+    pub fn search_text(&self, phrase : &str) -> Vec<(String, usize, usize)> {
+        let terms: Vec<String> = words(phrase).into_iter()
+            .map(|(s, e)| phrase[s..e].to_lowercase()).collect();
+        let first = match terms.first().and_then(|t| self.postings.get(t)) {
+            Some(first) => first,
+            None => return Vec::new()
+        };
+        let mut following = Vec::new();
+        for term in &terms[1..] {
+            match self.postings.get(term) {
+                Some(postings) => following.push(postings.iter()
+                    .map(|p| ((p.doc, p.position), p.end)).collect::<HashMap<_, _>>()),
+                None => return Vec::new()
+            }
+        }
+        let mut matches = Vec::new();
+        for p in first {
+            let mut end = p.end;
+            let found = following.iter().enumerate().all(|(i, next)| {
+                match next.get(&(p.doc, p.position + i + 1)) {
+                    Some(&e) => { end = e; true },
+                    None => false
+                }
+            });
+            if found {
+                if let Some(Some(id)) = self.docs.get(p.doc) {
+                    matches.push((id.clone(), p.start, end));
+                }
+            }
+        }
+        matches
+    }
+
+    /// The distinct words in the index
+    pub fn vocabulary(&self) -> HashSet<&str> {
+        self.postings.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+// The byte offsets of the runs of letters and digits in a text
+fn words(text : &str) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        if c.is_alphanumeric() {
+            start.get_or_insert(i);
+        } else if let Some(s) = start.take() {
+            words.push((s, i));
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, text.len()));
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Corpus, SimpleCorpus};
+
+    #[test]
+    fn test_search_text() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let a = corpus.build_doc().layer("text", "Climate change, and climate   CHANGE.").unwrap().add().unwrap();
+        let b = corpus.build_doc().layer("text", "The climate is changing").unwrap().add().unwrap();
+        let mut index = TextIndex::build(&corpus, "text").unwrap();
+        assert_eq!(index.search_text("climate change"), vec![(a.clone(), 0, 14), (a.clone(), 20, 36)]);
+        assert_eq!(index.search_text("Climate").len(), 3);
+        assert!(index.search_text("climate changing").is_empty());
+        index.remove_doc(&a);
+        assert_eq!(index.search_text("climate"), vec![(b, 4, 11)]);
+        assert!(!index.vocabulary().contains("change"));
+    }
+}
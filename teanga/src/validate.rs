@@ -0,0 +1,266 @@
+//! Validation of documents against the layer metadata of a corpus.
+//!
+//! # Examples
+//!
+//! ```
+//! use teanga::*;
+//! let mut corpus = SimpleCorpus::new();
+//! corpus.build_layer("text").add().unwrap();
+//! corpus.build_layer("tokens").layer_type(LayerType::span).base("text").add().unwrap();
+//! corpus.build_doc()
+//!     .layer("text", "Short").unwrap()
+//!     .layer("tokens", vec![(0, 5), (3, 9)]).unwrap()
+//!     .add().unwrap();
+//! let errors = validate_corpus(&corpus).unwrap();
+//! assert_eq!(errors.len(), 1);
+//! assert_eq!(errors[0].layer, "tokens");
+//! assert_eq!(errors[0].index, Some(1));
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use serde::Serialize;
+use crate::{DataType, Document, Layer, LayerDesc, LayerType, ReadableCorpus, TeangaData, TeangaResult};
+
+/// A problem with a document found by validation
+#[derive(Debug,Clone,PartialEq,Serialize)]
+pub struct ValidationError {
+    /// The ID of the document
+    pub doc_id: String,
+    /// The layer with the problem
+    pub layer: String,
+    /// The index of the annotation in the layer, if the problem is with a
+    /// single annotation
+    pub index: Option<usize>,
+    /// A description of the problem
+    pub message: String
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "{}: {}[{}]: {}", self.doc_id, self.layer, index, self.message),
+            None => write!(f, "{}: {}: {}", self.doc_id, self.layer, self.message)
+        }
+    }
+}
+
+/// Check every document of a corpus against its metadata
+///
+/// # Returns
+///
+/// The problems found, in corpus order. The list is empty if the corpus
+/// is valid
+pub fn validate_corpus<C : ReadableCorpus>(corpus : &C) -> TeangaResult<Vec<ValidationError>> {
+    let meta = corpus.get_meta();
+    let mut errors = Vec::new();
+    for res in corpus.iter_doc_ids() {
+        let (id, doc) = res?;
+        errors.extend(validate_doc(&id, &doc, meta));
+    }
+    Ok(errors)
+}
+
+/// Check a single document against the metadata of a corpus. Span and
+/// division indexes must be in range of their base layer and
+/// non-decreasing, seq layers must match the length of their base, enum
+/// values must be allowed and links must point at an existing annotation
+///
+/// # Arguments
+///
+/// * `doc_id` - The ID of the document, used in the report
+/// * `doc` - The document to check
+/// * `meta` - The metadata of the corpus
+pub fn validate_doc(doc_id : &str, doc : &Document,
+    meta : &HashMap<String, LayerDesc>) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    let mut names: Vec<&String> = doc.content.keys().filter(|k| !k.starts_with('_')).collect();
+    names.sort();
+    for name in names {
+        let mut error = |index : Option<usize>, message : String| errors.push(ValidationError {
+            doc_id: doc_id.to_string(),
+            layer: name.to_string(),
+            index,
+            message
+        });
+        let layer = &doc.content[name];
+        let desc = match meta.get(name) {
+            Some(desc) => desc,
+            None => {
+                error(None, "layer is not declared in the metadata".to_string());
+                continue;
+            }
+        };
+        if !has_valid_shape(layer, &desc.layer_type) {
+            error(None, format!("layer does not have the form of a {} layer", desc.layer_type));
+            continue;
+        }
+        if let Some(ref base) = desc.base {
+            match doc.content.get(base) {
+                Some(base_layer) => check_indexes(layer, &desc.layer_type, base_layer, &mut error),
+                None => error(None, format!("base layer {} is missing", base))
+            }
+        }
+        if let Some(ref data) = desc.data {
+            check_data(name, doc, desc, data, layer.data(desc), &mut error);
+        }
+    }
+    errors
+}
+
+// Whether the layer is stored in a form that matches its type
+fn has_valid_shape(layer : &Layer, layer_type : &LayerType) -> bool {
+    match layer_type {
+        LayerType::characters => matches!(layer, Layer::Characters(_)),
+        LayerType::span => matches!(layer, Layer::L2(_) | Layer::L2S(_) | Layer::L3(_) | Layer::L3S(_)),
+        LayerType::div | LayerType::element =>
+            matches!(layer, Layer::L1(_) | Layer::L1S(_) | Layer::L2(_) | Layer::L2S(_)),
+        LayerType::seq => matches!(layer, Layer::L1(_) | Layer::LS(_) | Layer::L1S(_))
+    }
+}
+
+fn check_indexes<F : FnMut(Option<usize>, String)>(layer : &Layer, layer_type : &LayerType,
+    base : &Layer, error : &mut F) {
+    let base_len = base.len();
+    match layer_type {
+        LayerType::span => {
+            let spans: Vec<(u32, u32)> = match layer {
+                Layer::L2(v) => v.clone(),
+                Layer::L2S(v) => v.iter().map(|&(s, e, _)| (s, e)).collect(),
+                Layer::L3(v) => v.iter().map(|&(s, e, _)| (s, e)).collect(),
+                Layer::L3S(v) => v.iter().map(|&(s, e, _, _)| (s, e)).collect(),
+                _ => Vec::new()
+            };
+            let mut last = 0;
+            for (i, (start, end)) in spans.into_iter().enumerate() {
+                let (start, end) = (start as usize, end as usize);
+                if start > end {
+                    error(Some(i), format!("span ({}, {}) ends before it starts", start, end));
+                } else if end > base_len {
+                    error(Some(i), format!("span ({}, {}) is out of range of the base layer of length {}",
+                        start, end, base_len));
+                } else if let Some(text) = base.characters() {
+                    if !text.is_char_boundary(start) || !text.is_char_boundary(end) {
+                        error(Some(i), format!("span ({}, {}) is not on a character boundary", start, end));
+                    }
+                }
+                if start < last {
+                    error(Some(i), format!("span starts at {} before the previous span", start));
+                }
+                last = start;
+            }
+        },
+        LayerType::div | LayerType::element => {
+            let indexes: Vec<u32> = match layer {
+                Layer::L1(v) => v.clone(),
+                Layer::L1S(v) => v.iter().map(|&(i, _)| i).collect(),
+                Layer::L2(v) => v.iter().map(|&(i, _)| i).collect(),
+                Layer::L2S(v) => v.iter().map(|&(i, _, _)| i).collect(),
+                _ => Vec::new()
+            };
+            let mut last = 0;
+            for (i, index) in indexes.into_iter().enumerate() {
+                let index = index as usize;
+                if index >= base_len {
+                    error(Some(i), format!("index {} is out of range of the base layer of length {}",
+                        index, base_len));
+                }
+                if *layer_type == LayerType::div && index < last {
+                    error(Some(i), format!("division starts at {} before the previous division", index));
+                }
+                last = index;
+            }
+        },
+        LayerType::seq => {
+            if layer.len() != base_len {
+                error(None, format!("layer has {} values but the base layer has {}", layer.len(), base_len));
+            }
+        },
+        LayerType::characters => {}
+    }
+}
+
+fn check_data<F : FnMut(Option<usize>, String)>(name : &str, doc : &Document, desc : &LayerDesc,
+    data_type : &DataType, data : Vec<TeangaData>, error : &mut F) {
+    let target_len = match data_type {
+        DataType::Link => {
+            let target = desc.target.as_deref().unwrap_or(name);
+            match doc.content.get(target) {
+                Some(target_layer) => target_layer.len(),
+                None => {
+                    error(None, format!("link target layer {} is missing", target));
+                    return;
+                }
+            }
+        },
+        _ => 0
+    };
+    for (i, value) in data.into_iter().enumerate() {
+        match (data_type, value) {
+            (DataType::Enum(values), TeangaData::String(s)) if !values.contains(&s) =>
+                error(Some(i), format!("{} is not an allowed value", s)),
+            (DataType::Int, TeangaData::String(s)) if s.parse::<i64>().is_err() =>
+                error(Some(i), format!("{} is not an integer", s)),
+            (DataType::Float, TeangaData::String(s)) if s.parse::<f64>().is_err() =>
+                error(Some(i), format!("{} is not a number", s)),
+            (DataType::Link, TeangaData::Link(link)) if link as usize >= target_len =>
+                error(Some(i), format!("link {} is out of range of the target layer of length {}",
+                    link, target_len)),
+            (DataType::Link, TeangaData::TypedLink(link, link_type)) => {
+                if link as usize >= target_len {
+                    error(Some(i), format!("link {} is out of range of the target layer of length {}",
+                        link, target_len));
+                }
+                if let Some(ref link_types) = desc.link_types {
+                    if !link_types.contains(&link_type) {
+                        error(Some(i), format!("{} is not an allowed link type", link_type));
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Corpus, SimpleCorpus};
+
+    #[test]
+    fn test_validate_corpus() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").layer_type(LayerType::span).base("text").add().unwrap();
+        corpus.build_layer("upos").layer_type(LayerType::seq).base("tokens")
+            .data(DataType::Enum(vec!["NOUN".to_string(), "VERB".to_string()])).add().unwrap();
+        corpus.build_layer("dep").layer_type(LayerType::seq).base("tokens")
+            .data(DataType::Link).add().unwrap();
+        let good = corpus.build_doc()
+            .layer("text", "Dogs bark").unwrap()
+            .layer("tokens", vec![(0, 4), (5, 9)]).unwrap()
+            .layer("upos", vec!["NOUN", "VERB"]).unwrap()
+            .layer("dep", vec![1u32, 1]).unwrap()
+            .add().unwrap();
+        let doc = corpus.get_doc_by_id(&good).unwrap();
+        assert!(validate_doc(&good, &doc, corpus.get_meta()).is_empty());
+
+        let mut bad = doc.clone();
+        bad.content.insert("tokens".to_string(), Layer::L2(vec![(5, 9), (0, 12)]));
+        bad.content.insert("upos".to_string(), Layer::LS(vec!["NOUN".to_string(), "ADJ".to_string()]));
+        bad.content.insert("dep".to_string(), Layer::L1(vec![2, 0]));
+        let errors: Vec<(String, Option<usize>)> = validate_doc("bad", &bad, corpus.get_meta())
+            .into_iter().map(|e| (e.layer, e.index)).collect();
+        assert_eq!(errors, vec![
+            ("dep".to_string(), Some(0)),
+            ("tokens".to_string(), Some(1)),
+            ("tokens".to_string(), Some(1)),
+            ("upos".to_string(), Some(1))]);
+
+        bad.content.insert("upos".to_string(), Layer::LS(vec!["NOUN".to_string()]));
+        bad.content.remove("text");
+        let errors: Vec<String> = validate_doc("bad", &bad, corpus.get_meta())
+            .into_iter().map(|e| e.to_string()).collect();
+        assert!(errors.contains(&"bad: tokens: base layer text is missing".to_string()));
+        assert!(errors.contains(&"bad: upos: layer has 1 values but the base layer has 2".to_string()));
+    }
+}